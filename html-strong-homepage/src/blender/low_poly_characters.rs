@@ -1,4 +1,4 @@
-use crate::components::Article;
+use crate::components::{video::VideoClip, Article};
 
 pub fn hello_world() -> Article {
     Article::new()
@@ -142,7 +142,11 @@ pub fn cyborg_follow_up() -> Article {
             "The tutorial does not include anything about animations, so I fumbled my way to do \
              something:",
         )
-        .video("cyborg-lights-animation.webm")
+        .video_clip(
+            VideoClip::new(&["cyborg-lights-animation.webm"])
+                .expect("animation should be a known video format")
+                .seek_to_start(),
+        )
         .p(
             "The rotation messed up badly, but that's just funny. And we get to test video on the \
              blog.",
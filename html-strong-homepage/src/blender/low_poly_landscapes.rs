@@ -1,4 +1,4 @@
-use crate::components::Article;
+use crate::components::{video::VideoClip, Article};
 
 pub fn hello_world() -> Article {
     Article::new()
@@ -55,5 +55,10 @@ pub fn bye_world() -> Article {
         )
         .br()
         .p("Let's take it for a literal spin:")
-        .video("island.mp4")
+        .video_clip(
+            VideoClip::new(&["island.mp4"])
+                .expect("island should be a known video format")
+                .seek_to_start()
+                .clip(),
+        )
 }
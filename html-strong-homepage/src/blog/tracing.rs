@@ -166,9 +166,11 @@ pub fn intro() -> Article {
         .p("But why would we give it the name ")
         .shell("_")
         .p("?")
-        .p("Turns out, rustlang has an ")
-        .url("https://github.com/rust-lang/rfcs/blob/master/text/2166-impl-only-use.md", "RFC for this")
+        .p("Turns out, rustlang has an RFC for this")
+        .footnote_ref("rfc")
         .p(".")
+        .footnote("rfc", Article::new()
+            .url("https://github.com/rust-lang/rfcs/blob/master/text/2166-impl-only-use.md", "https://github.com/rust-lang/rfcs/blob/master/text/2166-impl-only-use.md"))
         .p("The answer is simply that we can bring a trait into scope, and also use the same name as the trait for other things.")
         .p("Which is then probably why we can use ")
         .shell("CALLSITE")
@@ -241,9 +243,10 @@ pub fn intro() -> Article {
             "Each callsite stores the information whether anyone (subscribers) might care (😢) about them (optimization)",
             "Even though we haven't seen it yet, we can only assume subscribers get a look at the event when it occurs"
         ])
-        .p("Let's draw our current assumptions. I use ")
-        .url("https://excalidraw.com/", "Excalidraw")
+        .p("Let's draw our current assumptions. I use Excalidraw")
+        .footnote_ref("excalidraw")
         .p(" by the way.")
+        .footnote("excalidraw", Article::new().url("https://excalidraw.com/", "https://excalidraw.com/"))
         .image("tracing-01.webp")
         .p("So, an event comes from a callsite. The global registry checks if subscribers want to get events from that callsite. If they do, the event is passed on.")
         .p("Note that at this point some details are likely to be a bit off target but things are clearing up so it's all good.")
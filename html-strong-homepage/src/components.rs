@@ -1,10 +1,26 @@
 use std::path::{Path, PathBuf};
 
-use html_strong::{document_tree::Node, science_lab::NodeExt, tags::*};
+use html_strong::{document_tree::Node, science_lab::NodeExt, tags::td::td, tags::th::th, tags::*};
 use tracing::{debug, error};
 
 use crate::listing::Listing;
 
+use self::{
+    footnotes::Footnotes,
+    list::{List, ListKind},
+    markup::Inline,
+    table::{Cell, DataTable},
+    video::VideoClip,
+    youtube::{Timestamp, YoutubeEmbed, YoutubeVideo},
+};
+
+mod footnotes;
+pub mod list;
+pub mod markup;
+pub mod table;
+pub mod video;
+pub mod youtube;
+
 #[derive(Debug)]
 pub struct ShellMultiline(pub String);
 
@@ -32,26 +48,35 @@ impl From<PathBuf> for ShellMultiline {
 #[derive(Debug, Clone)]
 pub enum Tidbit {
     Text(String),
+    Markup(Vec<Inline>),
     Url { url: String, text: String },
     Image(String),
     Code(Listing),
     CodeInline(String),
     Shell(String),
     ShellMultiline(String),
-    Youtube(String),
+    Youtube(YoutubeEmbed),
+    Chapter { title: String, at: Timestamp },
     H2(String),
     H3(String),
-    List(Vec<String>),
+    List(List),
+    Table(DataTable),
     Breather,
-    Video(String),
+    Video(VideoClip),
+    FileLink(String),
     Sidenote(Article),
     Quote(Article),
+    FootnoteRef(String),
+    Footnote { id: String, contents: Article },
 }
 
 #[derive(Debug, Clone)]
 pub struct Article {
     stuff: Vec<Tidbit>,
     pub url_prefix: Option<String>,
+
+    /// Goes into the ids of footnotes, see [`Article::footnote_prefix`].
+    footnote_prefix: Option<String>,
 }
 
 impl Article {
@@ -59,9 +84,31 @@ impl Article {
         Self {
             stuff: vec![],
             url_prefix: None,
+            footnote_prefix: None,
         }
     }
 
+    /// Give the ids of this article's footnotes a prefix.
+    /// Needed when there are several articles with footnotes on the same page.
+    ///
+    /// Articles within this one, e.g. in quotes, get prefixes of their own.
+    pub fn footnote_prefix(mut self, prefix: &str) -> Self {
+        self.footnote_prefix = Some(prefix.into());
+        self
+    }
+
+    /// An article within this one, at the given position,
+    /// with a footnote prefix of its own so their footnotes don't share ids.
+    fn nested(&self, index: usize, article: &Article) -> Article {
+        // Starts with a letter, so it can't be mistaken for a footnote number.
+        let prefix = match &self.footnote_prefix {
+            Some(prefix) => format!("{prefix}-a{index}"),
+            None => format!("a{index}"),
+        };
+
+        article.clone().footnote_prefix(&prefix)
+    }
+
     fn absolute_path(&self, url: &str) -> String {
         if let Some(absolute_prefix) = self.url_prefix.as_ref() {
             format!("{absolute_prefix}/{url}",)
@@ -95,6 +142,33 @@ impl Article {
         self.add_tidbit(Tidbit::Text(html_escape::encode_text(text).to_string()))
     }
 
+    /// Add paragraph text with inline markup.
+    ///
+    /// See [`markup`] for what is supported.
+    /// Text is escaped when rendered, so only the markup itself turns into HTML.
+    pub fn md(self, text: &str) -> Self {
+        self.add_tidbit(Tidbit::Markup(markup::parse(text)))
+    }
+
+    /// Adds a reference to the footnote with the given id.
+    /// Inline- i.e. continues paragraph.
+    ///
+    /// Footnotes are numbered by the order they are first referenced in.
+    /// `[^id]` in [`Article::md`] text does the same thing.
+    pub fn footnote_ref(self, id: &str) -> Self {
+        self.add_tidbit(Tidbit::FootnoteRef(id.into()))
+    }
+
+    /// Define the footnote with the given id.
+    /// It is not shown here, but in a "References" section at the end of the article.
+    /// Contents are arbitrary- therefore takes an article as well.
+    pub fn footnote(self, id: &str, contents: Article) -> Self {
+        self.add_tidbit(Tidbit::Footnote {
+            id: id.into(),
+            contents,
+        })
+    }
+
     /// Add a br.
     pub fn br(self) -> Self {
         self.add_tidbit(Tidbit::Breather)
@@ -120,8 +194,23 @@ impl Article {
 
     /// Adds a video with loop and controls.
     /// Is displayed in its own area.
+    ///
+    /// If the path is not a video format we know how to show,
+    /// an error is logged and a link to the file is added instead.
     pub fn video(self, path: &str) -> Self {
-        self.add_tidbit(Tidbit::Video(path.to_string()))
+        match VideoClip::new(&[path]) {
+            Ok(clip) => self.video_clip(clip),
+            Err(error) => {
+                error!(?path, %error, "Video shown as a link");
+                self.add_tidbit(Tidbit::FileLink(path.into()))
+            }
+        }
+    }
+
+    /// Adds a video which may have several sources, a poster, or play as a clip.
+    /// Is displayed in its own area.
+    pub fn video_clip(self, clip: VideoClip) -> Self {
+        self.add_tidbit(Tidbit::Video(clip))
     }
 
     /// This adds an inline shell-like command.
@@ -157,19 +246,93 @@ impl Article {
     }
 
     /// Adds a new YouTube video under the current section.
-    pub fn youtube(self, _url: &'static str) -> Self {
-        unimplemented!("Youtube embeds bring SO much garbage into the site load so screw that");
-        // self.add_tidbit(Tidbit::Youtube(url.into()))
+    ///
+    /// Youtube embeds bring SO much garbage into the site load,
+    /// so this is a link to the video until the reader clicks it.
+    /// Only then is the actual player loaded.
+    ///
+    /// If the url is not a YouTube video, an error is logged and the video is left out.
+    pub fn youtube(self, url: &str, title: &str) -> Self {
+        self.add_youtube(url, title, None)
+    }
+
+    /// Like [`Article::youtube`], but shows the given (local) image until clicked.
+    pub fn youtube_with_poster(self, url: &str, title: &str, poster: &str) -> Self {
+        self.add_youtube(url, title, Some(poster.into()))
+    }
+
+    fn add_youtube(self, url: &str, title: &str, poster: Option<String>) -> Self {
+        let video = match YoutubeVideo::parse(url) {
+            Ok(video) => video,
+            Err(error) => {
+                error!(?url, %error, "YouTube video left out");
+                return self;
+            }
+        };
+
+        self.add_tidbit(Tidbit::Youtube(YoutubeEmbed {
+            video,
+            title: title.into(),
+            poster,
+        }))
+    }
+
+    /// This adds an inline url to a YouTube video at the given time, e.g. `9:05`.
+    ///
+    /// If the url or timestamp is bad, an error is logged and only the text is added.
+    pub fn youtube_at(self, url: &str, timestamp: &str, text: &str) -> Self {
+        let video = YoutubeVideo::parse(url).and_then(|video| Ok(video.at(timestamp.parse()?)));
+
+        match video {
+            Ok(video) => self.url(&video.watch_url(), text),
+            Err(error) => {
+                error!(?url, ?timestamp, %error, "YouTube link left out");
+                self.p(text)
+            }
+        }
+    }
+
+    /// Add an h2 element for a chapter of the YouTube video most recently added.
+    /// The heading links to the video at the given time, e.g. `9:05`.
+    ///
+    /// If the timestamp is bad, an error is logged and the chapter is a plain h2.
+    pub fn chapter(self, title: &str, timestamp: &str) -> Self {
+        let at = match timestamp.parse() {
+            Ok(at) => at,
+            Err(error) => {
+                error!(?title, ?timestamp, %error, "chapter timestamp left out");
+                return self.h2(title);
+            }
+        };
+
+        self.add_tidbit(Tidbit::Chapter {
+            title: title.into(),
+            at,
+        })
     }
 
     /// Adds a new unordered list of text items.
+    /// Text is escaped, like [`Article::p`], and not parsed as markup.
     pub fn list<S: AsRef<str>>(self, entries: Vec<S>) -> Self {
-        self.add_tidbit(Tidbit::List(
-            entries
-                .into_iter()
-                .map(|s| s.as_ref().to_string())
-                .collect::<Vec<String>>(),
-        ))
+        self.add_tidbit(Tidbit::List(List::plain(ListKind::Unordered, entries)))
+    }
+
+    /// Adds a new numbered list of text items.
+    /// Text is escaped, like [`Article::p`], and not parsed as markup.
+    pub fn ordered_list<S: AsRef<str>>(self, entries: Vec<S>) -> Self {
+        self.add_tidbit(Tidbit::List(List::plain(ListKind::Ordered, entries)))
+    }
+
+    /// Adds a table.
+    /// Is displayed in its own area.
+    pub fn table(self, table: DataTable) -> Self {
+        self.add_tidbit(Tidbit::Table(table))
+    }
+
+    /// Adds a list which may be nested, numbered, a checklist,
+    /// and have inline markup in its items.
+    pub fn rich_list(self, list: List) -> Self {
+        self.add_tidbit(Tidbit::List(list))
     }
 }
 
@@ -235,18 +398,128 @@ impl NodeExt for Article {
             }
         }
 
+        fn inline_content<'a>(part: &'a Inline, footnotes: &mut Footnotes<'a>) -> ParagraphContent {
+            match part {
+                Inline::Text(text) => ParagraphContent::text(html_escape::encode_text(text).into()),
+                Inline::Emphasis(text) => {
+                    ParagraphContent::kid(Em.text(html_escape::encode_text(text)))
+                }
+                Inline::Strong(text) => {
+                    ParagraphContent::kid(Strong.text(html_escape::encode_text(text)))
+                }
+                Inline::Code(code) => ParagraphContent::kid(
+                    Code.class("rust-inline rounded")
+                        .text(html_escape::encode_text(code)),
+                ),
+                Inline::Link { url, text } => ParagraphContent::kid(
+                    A::href(&html_escape::encode_double_quoted_attribute(url))
+                        .text(html_escape::encode_text(text)),
+                ),
+                Inline::FootnoteRef(id) => ParagraphContent::kid(footnotes.reference(id)),
+            }
+        }
+
+        fn list_node<'a>(list: &'a List, footnotes: &mut Footnotes<'a>) -> Node {
+            let mut node = match list.kind {
+                ListKind::Ordered => Ol.into_node(),
+                ListKind::Unordered => Ul.into_node(),
+                ListKind::Checklist => Ul.class("checklist"),
+            };
+
+            for item in &list.items {
+                let mut li = match item.done {
+                    Some(true) => Li.class("task done").kid(Span.class("task-box").text("☑")),
+                    Some(false) => Li.class("task").kid(Span.class("task-box").text("☐")),
+                    None => Li.into_node(),
+                };
+
+                for part in &item.contents {
+                    li = match inline_content(part, footnotes) {
+                        ParagraphContent::Text(t) => li.add_text(&t),
+                        ParagraphContent::Kid(k) => li.kid(k),
+                    };
+                }
+
+                if let Some(sublist) = &item.sublist {
+                    li.push_kid(list_node(sublist, footnotes));
+                }
+
+                node.push_kid(li);
+            }
+
+            node
+        }
+
+        fn table_node(table: &DataTable) -> Node {
+            let aligns = (0..table.header.len())
+                .map(|column| table.column_align(column))
+                .collect::<Vec<_>>();
+
+            let mut header = Tr.into_node();
+            for (title, align) in table.header.iter().zip(&aligns) {
+                header.push_kid(
+                    th().class(align.class())
+                        .text(html_escape::encode_text(title)),
+                );
+            }
+
+            let mut node = Table.class("article-table rounded soft-shadow").kid(header);
+
+            for row in &table.rows {
+                let mut tr = Tr.into_node();
+                for (cell, align) in row.iter().zip(&aligns) {
+                    let td = match cell {
+                        Cell::Number(_) => td().class(&format!("{} number", align.class())),
+                        _ => td().class(align.class()),
+                    };
+                    let text = cell.to_string();
+                    let text = html_escape::encode_text(&text);
+
+                    tr.push_kid(match cell {
+                        Cell::Text(_) | Cell::Number(_) => td.text(text),
+                        Cell::Link { url, .. } => td.kid(
+                            A::href(&html_escape::encode_double_quoted_attribute(url)).text(text),
+                        ),
+                        Cell::Code(_) => td.kid(Code.class("rust-inline rounded").text(text)),
+                    });
+                }
+                node.push_kid(tr);
+            }
+
+            // Wide tables scroll sideways on small screens instead of overflowing.
+            Div.class("table-scroll breather-y").kid(node)
+        }
+
         let mut output = Output::new(Div.class("component-article"));
 
-        for tidbit in &self.stuff {
+        // Chapters link to the most recent video.
+        let mut last_video: Option<&YoutubeVideo> = None;
+
+        let mut footnotes = Footnotes::new(
+            self.footnote_prefix.as_deref(),
+            self.stuff
+                .iter()
+                .enumerate()
+                .filter_map(|(index, tidbit)| match tidbit {
+                    Tidbit::Footnote { id, contents } => {
+                        Some((id.as_str(), self.nested(index, contents)))
+                    }
+                    _ => None,
+                })
+                .collect(),
+        );
+
+        for (index, tidbit) in self.stuff.iter().enumerate() {
             match tidbit {
                 Tidbit::Quote(article) => output.add_standalone(
                     Div.class("quote breather-y rounded")
                         .kid(Div.class("quote-mark").text("“"))
-                        .kid(article.clone()),
+                        .kid(self.nested(index, article)),
+                ),
+                Tidbit::Sidenote(article) => output.add_standalone(
+                    self.nested(index, article)
+                        .class("sidenote breather-y rounded"),
                 ),
-                Tidbit::Sidenote(article) => {
-                    output.add_standalone(article.clone().class("sidenote breather-y rounded"))
-                }
                 Tidbit::Url { url, text } => {
                     output.continue_paragraph(ParagraphContent::kid(A::href(url).text(text)));
                 }
@@ -257,21 +530,16 @@ impl NodeExt for Article {
                             .class("rounded breather-y center width-100"),
                     );
                 }
-                Tidbit::Video(path) => output.add_standalone({
-                    let source = if path.ends_with("webm") {
-                        Source::new_webm(self.absolute_path(path))
-                    } else if path.ends_with("mp4") {
-                        Source::new_mp4(self.absolute_path(path))
-                    } else {
-                        error!(?path, "unhandled video extension");
-                        panic!()
-                    };
-
-                    Video::new()
-                        .controls()
-                        .kid(source)
-                        .class("rounded breather-y width-100")
-                }),
+                Tidbit::Video(clip) => output.add_standalone(Div.text(
+                    clip.markup("rounded breather-y width-100", |path| {
+                        self.absolute_path(path)
+                    }),
+                )),
+                Tidbit::FileLink(path) => {
+                    output.continue_paragraph(ParagraphContent::kid(
+                        A::href(&self.absolute_path(path)).text(path),
+                    ));
+                }
                 Tidbit::CodeInline(code) => output.continue_paragraph(ParagraphContent::Kid(
                     Code.class("rust-inline rounded")
                         .text(html_escape::encode_text(code)),
@@ -299,27 +567,77 @@ impl NodeExt for Article {
                     }
                     output.continue_paragraph(ParagraphContent::text(text.to_string()));
                 }
-                Tidbit::Youtube(url) => {
-                    output.add_standalone(Iframe::new(url));
+                Tidbit::Markup(parts) => {
+                    if output.last_was_p {
+                        output.finish_up();
+                    }
+                    for part in parts {
+                        output.continue_paragraph(inline_content(part, &mut footnotes));
+                    }
+                }
+                Tidbit::Youtube(embed) => {
+                    last_video = Some(&embed.video);
+
+                    let preview = match &embed.poster {
+                        Some(poster) => Img::new(&self.absolute_path(poster))
+                            .class("youtube-embed-poster width-100"),
+                        None => Div.class("youtube-embed-poster youtube-embed-blank"),
+                    };
+
+                    output.add_standalone(
+                        A::href(&html_escape::encode_double_quoted_attribute(
+                            &embed.video.watch_url(),
+                        ))
+                        .class("youtube-embed rounded breather-y soft-shadow")
+                        .kid(preview)
+                        .kid(Div.class("youtube-embed-play").text("▶"))
+                        .kid(
+                            Div.class("youtube-embed-title")
+                                .text(html_escape::encode_text(&embed.title)),
+                        ),
+                    );
+                }
+                Tidbit::Chapter { title, at } => {
+                    let heading = H2.text(html_escape::encode_text(title)).add_text(" ");
+
+                    output.add_standalone(match last_video {
+                        Some(video) => heading.kid(
+                            A::href(&html_escape::encode_double_quoted_attribute(
+                                &video.at(*at).watch_url(),
+                            ))
+                            .class("chapter-link")
+                            .text(format!("({at})")),
+                        ),
+                        None => {
+                            error!(?title, "chapter without a YouTube video before it");
+                            heading.add_text(&format!("({at})"))
+                        }
+                    });
                 }
                 Tidbit::H2(title) => output.add_standalone(H2.text(&title)),
                 Tidbit::H3(title) => output.add_standalone(H3.text(&title)),
-                Tidbit::List(text_list) => {
-                    let mut list = Ul.into_node();
-                    for text in text_list {
-                        list.push_kid(Li.text(text));
-                    }
-                    output.add_standalone(list.class("breather-y"))
+                Tidbit::List(list) => {
+                    output.add_standalone(list_node(list, &mut footnotes).class("breather-y"))
                 }
+                Tidbit::Table(table) => output.add_standalone(table_node(table)),
                 Tidbit::Breather => output.add_standalone(Br),
+                Tidbit::FootnoteRef(id) => {
+                    output.continue_paragraph(ParagraphContent::kid(footnotes.reference(id)))
+                }
+                // Collected into the references at the end.
+                Tidbit::Footnote { .. } => {}
             };
 
             // If we know if the last tidbit was a paragraph/text,
             // we can handle line breaks in a smarter way.
-            output.last_was_p = matches!(tidbit, Tidbit::Text(_));
+            output.last_was_p = matches!(tidbit, Tidbit::Text(_) | Tidbit::Markup(_));
         }
         output.finish_up();
 
+        if let Some(references) = footnotes.into_node() {
+            output.add_standalone(references);
+        }
+
         output.output
     }
 }
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use html_strong::{document_tree::Node, science_lab::NodeExt, tags::*};
+use tracing::{error, warn};
+
+use super::Article;
+
+/// Keeps track of footnotes while an [`Article`] is turned into a node.
+///
+/// Footnotes are numbered in the order they are first referenced.
+/// Each reference links down to its footnote, and each footnote links back up
+/// to where it was first referenced.
+///
+/// Ids are `fn-{number}` and `fnref-{number}`, with the article's
+/// [`Article::footnote_prefix`] in between if it has one, so articles on the same page
+/// don't link to each other's footnotes.
+pub(super) struct Footnotes<'a> {
+    prefix: Option<&'a str>,
+
+    definitions: Vec<(&'a str, Article)>,
+
+    // Footnote ids in the order they were first referenced.
+    order: Vec<&'a str>,
+
+    times_referenced: HashMap<&'a str, usize>,
+}
+
+impl<'a> Footnotes<'a> {
+    pub(super) fn new(prefix: Option<&'a str>, definitions: Vec<(&'a str, Article)>) -> Self {
+        Self {
+            prefix,
+            definitions,
+            order: vec![],
+            times_referenced: HashMap::new(),
+        }
+    }
+
+    fn id(&self, kind: &str, number: usize) -> String {
+        match self.prefix {
+            Some(prefix) => format!("{kind}-{prefix}-{number}"),
+            None => format!("{kind}-{number}"),
+        }
+    }
+
+    fn number(&self, id: &str) -> Option<usize> {
+        self.order
+            .iter()
+            .position(|&seen| seen == id)
+            .map(|i| i + 1)
+    }
+
+    /// A superscript link to the footnote with the given id.
+    pub(super) fn reference(&mut self, id: &'a str) -> Node {
+        if !self.definitions.iter().any(|&(defined, _)| defined == id) {
+            error!(?id, "reference to undefined footnote");
+            return Sup.class("footnote-ref").text("[?]");
+        }
+
+        let number = self.number(id).unwrap_or_else(|| {
+            self.order.push(id);
+            self.order.len()
+        });
+
+        let times = self.times_referenced.entry(id).or_default();
+        *times += 1;
+
+        // Only the first reference is linked back to, but every reference
+        // still needs a unique id.
+        let times = *times;
+        let ref_id = if times == 1 {
+            self.id("fnref", number)
+        } else {
+            format!("{}-{times}", self.id("fnref", number))
+        };
+
+        Sup.class("footnote-ref")
+            .id(&ref_id)
+            .kid(A::href(&format!("#{}", self.id("fn", number))).text(format!("[{number}]")))
+    }
+
+    /// The "References" section, if there are any footnotes.
+    pub(super) fn into_node(mut self) -> Option<Node> {
+        if self.definitions.is_empty() {
+            return None;
+        }
+
+        for &(id, _) in &self.definitions {
+            if !self.order.contains(&id) {
+                warn!(?id, "footnote is never referenced");
+                self.order.push(id);
+            }
+        }
+
+        let mut list = Ol.into_node();
+
+        for (index, id) in self.order.iter().enumerate() {
+            let number = index + 1;
+            let (_, contents) = self
+                .definitions
+                .iter()
+                .find(|&&(defined, _)| defined == *id)
+                .expect("only defined footnotes are numbered");
+
+            let mut footnote = Li.id(&self.id("fn", number)).kid(contents.clone());
+            if self.times_referenced.contains_key(id) {
+                footnote.push_kid(
+                    A::href(&format!("#{}", self.id("fnref", number)))
+                        .class("footnote-back")
+                        .text("↩"),
+                );
+            }
+
+            list.push_kid(footnote);
+        }
+
+        Some(
+            Div.class("footnotes breather-y")
+                .kid(H2.text("References"))
+                .kid(list),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(article: Article) -> String {
+        article
+            .into_node()
+            .render_string()
+            .expect("article should render")
+    }
+
+    #[test]
+    fn test_numbered_by_first_reference() {
+        let html = render(
+            Article::new()
+                .footnote("b", Article::new().p("Defined first, referenced second."))
+                .footnote("a", Article::new().p("Defined second, referenced first."))
+                .md("First[^a], second[^b]."),
+        );
+
+        assert!(
+            html.contains(r##"<sup class="footnote-ref" id="fnref-1"><a href="#fn-1">[1]</a>"##)
+        );
+        assert!(
+            html.contains(r##"<sup class="footnote-ref" id="fnref-2"><a href="#fn-2">[2]</a>"##)
+        );
+
+        let first = html
+            .find(r#"id="fn-1""#)
+            .expect("footnote 1 should be listed");
+        let second = html
+            .find(r#"id="fn-2""#)
+            .expect("footnote 2 should be listed");
+        assert!(first < second);
+        assert!(html[first..second].contains("Defined second, referenced first."));
+        assert!(html[second..].contains("Defined first, referenced second."));
+        assert!(html.contains(r##"<a href="#fnref-1" class="footnote-back">↩</a>"##));
+    }
+
+    #[test]
+    fn test_repeated_references() {
+        let html = render(
+            Article::new()
+                .md("Once[^a], twice[^a]")
+                .footnote_ref("a")
+                .footnote("a", Article::new().p("Referenced three times.")),
+        );
+
+        assert!(html.contains(r#"id="fnref-1""#));
+        assert!(html.contains(r#"id="fnref-1-2""#));
+        assert!(html.contains(r#"id="fnref-1-3""#));
+        assert_eq!(html.matches(r#"id="fn-1""#).count(), 1);
+        assert_eq!(html.matches("[2]").count(), 0);
+    }
+
+    #[test]
+    fn test_undefined_and_unreferenced() {
+        let html = render(
+            Article::new()
+                .md("Nowhere[^missing].")
+                .footnote("unused", Article::new().p("Never referenced.")),
+        );
+
+        assert!(html.contains("[?]"));
+        assert!(html.contains(r#"id="fn-1""#));
+        assert!(html.contains("Never referenced."));
+        assert!(!html.contains("footnote-back"));
+
+        assert!(!render(Article::new().p("No footnotes.")).contains("References"));
+    }
+
+    #[test]
+    fn test_nested_articles_have_their_own_ids() {
+        let nested = || {
+            Article::new()
+                .md("Nested[^a].")
+                .footnote("a", Article::new().p("Nested footnote."))
+        };
+        let html = render(
+            Article::new()
+                .md("Outer[^a].")
+                .sidenote(nested())
+                .quote(nested())
+                .footnote("a", nested()),
+        );
+
+        for id in [
+            "fnref-1",
+            "fn-1",
+            "fnref-a1-1",
+            "fn-a1-1",
+            "fnref-a2-1",
+            "fn-a2-1",
+            "fnref-a3-1",
+            "fn-a3-1",
+        ] {
+            assert_eq!(html.matches(&format!(r#"id="{id}""#)).count(), 1, "{id}");
+        }
+        assert!(html.contains(r##"href="#fn-a1-1""##));
+
+        // Several articles on the same page.
+        let html = render(
+            Article::new()
+                .footnote_prefix("post")
+                .md("Outer[^a].")
+                .footnote("a", nested()),
+        );
+        assert!(html.contains(r#"id="fnref-post-1""#));
+        assert!(html.contains(r#"id="fn-post-a1-1""#));
+    }
+}
@@ -0,0 +1,180 @@
+use super::markup::{self, Inline};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListKind {
+    Unordered,
+    Ordered,
+    Checklist,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub(super) contents: Vec<Inline>,
+
+    /// Only set for checklist items.
+    pub(super) done: Option<bool>,
+
+    pub(super) sublist: Option<List>,
+}
+
+/// A list which can be put in an [`super::Article`].
+///
+/// Item text is parsed as inline markup, see [`markup`].
+///
+/// # Example
+///
+/// ```rust
+/// use html_strong_homepage::components::list::List;
+///
+/// let list = List::unordered()
+///     .item("Then put in cup of water!")
+///     .item_with(
+///         "Rule of thumb:",
+///         List::unordered()
+///             .item("Two nodes under water (no leaves)")
+///             .item("`>=4` leaves on top node"),
+///     );
+/// ```
+#[derive(Debug, Clone)]
+pub struct List {
+    pub(super) kind: ListKind,
+    pub(super) items: Vec<ListItem>,
+}
+
+impl List {
+    fn new(kind: ListKind) -> Self {
+        Self {
+            kind,
+            items: vec![],
+        }
+    }
+
+    /// A bullet point list.
+    pub fn unordered() -> Self {
+        Self::new(ListKind::Unordered)
+    }
+
+    /// A numbered list.
+    pub fn ordered() -> Self {
+        Self::new(ListKind::Ordered)
+    }
+
+    /// A list of tasks which are either done or not.
+    /// Add items via [`List::task`].
+    pub fn checklist() -> Self {
+        Self::new(ListKind::Checklist)
+    }
+
+    /// A list of plain text items, no markup.
+    /// The text is escaped when rendered.
+    pub(super) fn plain<S: AsRef<str>>(kind: ListKind, entries: Vec<S>) -> Self {
+        Self {
+            kind,
+            items: entries
+                .into_iter()
+                .map(|entry| ListItem {
+                    contents: vec![Inline::Text(entry.as_ref().to_string())],
+                    done: None,
+                    sublist: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn add_item(mut self, text: &str, done: Option<bool>, sublist: Option<List>) -> Self {
+        self.items.push(ListItem {
+            contents: markup::parse(text),
+            done,
+            sublist,
+        });
+        self
+    }
+
+    /// Add an item.
+    pub fn item(self, text: &str) -> Self {
+        self.add_item(text, None, None)
+    }
+
+    /// Add an item with a nested list below it.
+    pub fn item_with(self, text: &str, sublist: List) -> Self {
+        self.add_item(text, None, Some(sublist))
+    }
+
+    /// Add a task which is either done or not.
+    /// Meant for [`List::checklist`], but any list may have them.
+    pub fn task(self, text: &str, done: bool) -> Self {
+        self.add_item(text, Some(done), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use html_strong::science_lab::NodeExt;
+
+    use super::*;
+    use crate::components::Article;
+
+    fn render(article: Article) -> String {
+        article
+            .into_node()
+            .render_string()
+            .expect("article should render")
+    }
+
+    #[test]
+    fn test_plain_lists() {
+        let html = render(Article::new().list(vec!["Exercises", "Reps"]));
+        assert!(html.contains("<ul class=\"breather-y\"><li>Exercises</li><li>Reps</li></ul>"));
+
+        let html = render(Article::new().ordered_list(vec![
+            "Feel contraction?".to_string(),
+            "Feel a pump after/during?".to_string(),
+        ]));
+        assert!(html.contains(
+            "<ol class=\"breather-y\"><li>Feel contraction?</li><li>Feel a pump after/during?</li></ol>"
+        ));
+
+        // Escaped, and not markup.
+        let html = render(Article::new().list(vec!["<b>x</b> & `y` _z_"]));
+        assert!(html.contains("<li>&lt;b&gt;x&lt;/b&gt; &amp; `y` _z_</li>"));
+    }
+
+    #[test]
+    fn test_rich_lists() {
+        let html = render(
+            Article::new().rich_list(
+                List::unordered()
+                    .item("Then put in cup of water!")
+                    .item_with(
+                        "Rule of thumb:",
+                        List::ordered()
+                            .item("Two nodes under water")
+                            .item("`>=4` leaves on top node"),
+                    ),
+            ),
+        );
+
+        assert!(html.contains(
+            "<li>Rule of thumb:<ol><li>Two nodes under water</li>\
+             <li><code class=\"rust-inline rounded\">&gt;=4</code> leaves on top node</li></ol></li>"
+        ));
+    }
+
+    #[test]
+    fn test_checklist() {
+        let html = render(
+            Article::new().rich_list(
+                List::checklist()
+                    .task("Plant basil", true)
+                    .task("Eat _basil_", false),
+            ),
+        );
+
+        assert!(html.contains("<ul class=\"checklist\""));
+        assert!(html
+            .contains("<li class=\"task done\"><span class=\"task-box\">☑</span>Plant basil</li>"));
+        assert!(html.contains(
+            "<li class=\"task\"><span class=\"task-box\">☐</span>Eat <em>basil</em></li>"
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+//! A small Markdown-like inline markup for paragraph text.
+//!
+//! Supported:
+//!
+//! - `_emphasis_`
+//! - `**strong**`
+//! - `` `inline code` ``
+//! - `[text](url)`
+//! - `[^footnote]`
+//!
+//! A backslash escapes the next character.
+//! Anything which does not form a complete construct is kept as literal text,
+//! so e.g. `snake_case_names` or a lone `*` are left alone.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inline {
+    Text(String),
+    Emphasis(String),
+    Strong(String),
+    Code(String),
+    Link { url: String, text: String },
+    FootnoteRef(String),
+}
+
+/// Parse text with inline markup into its parts.
+///
+/// Nothing is escaped here. That is up to whoever renders the parts.
+pub fn parse(text: &str) -> Vec<Inline> {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    let mut prev: Option<char> = None;
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let construct = match c {
+            '\\' => {
+                let escaped = rest[1..].chars().next();
+                literal.push(escaped.unwrap_or('\\'));
+                let consumed = 1 + escaped.map(char::len_utf8).unwrap_or(0);
+
+                prev = escaped.or(Some('\\'));
+                rest = &rest[consumed..];
+                continue;
+            }
+            '_' => delimited(rest, "_", prev).map(|(s, n)| (Inline::Emphasis(s), n)),
+            '*' => delimited(rest, "**", prev).map(|(s, n)| (Inline::Strong(s), n)),
+            '`' => code(rest),
+            '[' => footnote_ref(rest).or_else(|| link(rest)),
+            _ => None,
+        };
+
+        match construct {
+            Some((inline, consumed)) => {
+                if !literal.is_empty() {
+                    parts.push(Inline::Text(std::mem::take(&mut literal)));
+                }
+                parts.push(inline);
+
+                prev = rest[..consumed].chars().last();
+                rest = &rest[consumed..];
+            }
+            None => {
+                literal.push(c);
+
+                prev = Some(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    if !literal.is_empty() {
+        parts.push(Inline::Text(literal));
+    }
+
+    parts
+}
+
+// Word boundaries are needed on the outside of a delimiter pair,
+// and non-whitespace on the inside.
+// This is what keeps `images_before_day_of` from turning into emphasis.
+fn delimited(rest: &str, delimiter: &str, prev: Option<char>) -> Option<(String, usize)> {
+    let inner = rest.strip_prefix(delimiter)?;
+
+    if matches!(prev, Some(c) if c.is_alphanumeric()) || inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let (end, _) = inner.match_indices(delimiter).find(|&(at, _)| {
+        let before = inner[..at].chars().last();
+        let after = inner[at + delimiter.len()..].chars().next();
+
+        at > 0
+            && matches!(before, Some(c) if !c.is_whitespace())
+            && !matches!(after, Some(c) if c.is_alphanumeric())
+    })?;
+
+    Some((inner[..end].to_string(), 2 * delimiter.len() + end))
+}
+
+fn code(rest: &str) -> Option<(Inline, usize)> {
+    let inner = rest.strip_prefix('`')?;
+    let end = inner.find('`').filter(|&end| end > 0)?;
+
+    Some((Inline::Code(inner[..end].to_string()), end + 2))
+}
+
+fn footnote_ref(rest: &str) -> Option<(Inline, usize)> {
+    let inner = rest.strip_prefix("[^")?;
+    let end = inner.find(']')?;
+    let id = &inner[..end];
+
+    if id.is_empty() || id.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some((Inline::FootnoteRef(id.to_string()), end + 3))
+}
+
+fn link(rest: &str) -> Option<(Inline, usize)> {
+    let inner = rest.strip_prefix('[')?;
+    let text_end = inner.find("](")?;
+    let text = &inner[..text_end];
+
+    let after_text = &inner[text_end + 2..];
+    let url_end = after_text.find(')')?;
+    let url = &after_text[..url_end];
+
+    if text.is_empty() || !is_safe_url(url) {
+        return None;
+    }
+
+    Some((
+        Inline::Link {
+            url: url.to_string(),
+            text: text.to_string(),
+        },
+        1 + text_end + 2 + url_end + 1,
+    ))
+}
+
+/// Only allow urls which can't run script when clicked.
+pub(super) fn is_safe_url(url: &str) -> bool {
+    if url.is_empty() || url.contains(|c: char| c.is_whitespace() || c == '"' || c == '<') {
+        return false;
+    }
+
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains('/') => {
+            matches!(scheme, "http" | "https" | "mailto")
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Inline {
+        Inline::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_plain_text_is_untouched() {
+        assert_eq!(
+            parse("Volume. Reps x sets."),
+            vec![text("Volume. Reps x sets.")]
+        );
+        assert_eq!(
+            parse("images_before_day_of and a * b * c"),
+            vec![text("images_before_day_of and a * b * c")]
+        );
+        assert_eq!(parse("a _ b _ c"), vec![text("a _ b _ c")]);
+    }
+
+    #[test]
+    fn test_emphasis_and_strong() {
+        assert_eq!(
+            parse("Hypertrophy: Not _intensity_ the main driver!"),
+            vec![
+                text("Hypertrophy: Not "),
+                Inline::Emphasis("intensity".into()),
+                text(" the main driver!"),
+            ]
+        );
+        assert_eq!(
+            parse("**Volume** is king"),
+            vec![Inline::Strong("Volume".into()), text(" is king")]
+        );
+    }
+
+    #[test]
+    fn test_code_links_and_footnotes() {
+        assert_eq!(
+            parse("Use `tracing::info!` from [tracing](https://github.com/tokio-rs/tracing)[^1]."),
+            vec![
+                text("Use "),
+                Inline::Code("tracing::info!".into()),
+                text(" from "),
+                Inline::Link {
+                    url: "https://github.com/tokio-rs/tracing".into(),
+                    text: "tracing".into()
+                },
+                Inline::FootnoteRef("1".into()),
+                text("."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escapes_and_unsafe_links() {
+        assert_eq!(parse(r"\_not emphasis\_"), vec![text("_not emphasis_")]);
+        assert_eq!(
+            parse("[click](javascript:alert(1))"),
+            vec![text("[click](javascript:alert(1))")]
+        );
+    }
+}
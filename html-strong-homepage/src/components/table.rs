@@ -0,0 +1,389 @@
+use std::fmt::Display;
+
+use thiserror::Error;
+use tracing::error;
+
+use super::markup::{self, Inline};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    pub(super) fn class(&self) -> &'static str {
+        match self {
+            Align::Left => "align-left",
+            Align::Center => "align-center",
+            Align::Right => "align-right",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Number(f64),
+    Link { url: String, text: String },
+    Code(String),
+}
+
+impl Cell {
+    /// A link, if the url is one which is safe to click, see [`markup`].
+    /// Otherwise an error is logged and the cell is only the text.
+    pub fn link(url: &str, text: &str) -> Self {
+        if !markup::is_safe_url(url) {
+            error!(?url, "link left out of table");
+            return Self::Text(text.to_string());
+        }
+
+        Self::Link {
+            url: url.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn code(code: &str) -> Self {
+        Self::Code(code.to_string())
+    }
+
+    /// Guess the type of a cell from its text.
+    ///
+    /// Numbers written the plain way, like `-12` or `85.5`, become [`Cell::Number`],
+    /// and a cell which is only a `[text](url)` or only `` `code` `` becomes a link
+    /// or code respectively.
+    /// Anything else is text, including `007` or `1e3`, which would not show as written.
+    pub fn guess(text: &str) -> Self {
+        let text = text.trim();
+
+        if is_plain_number(text) {
+            if let Ok(number) = text.parse::<f64>() {
+                return Self::Number(number);
+            }
+        }
+
+        match markup::parse(text).as_slice() {
+            [Inline::Link { url, text }] => Self::link(url, text),
+            [Inline::Code(code)] => Self::code(code),
+            _ => Self::Text(text.to_string()),
+        }
+    }
+}
+
+// An optional minus, digits without leading zeros, and optional decimals.
+fn is_plain_number(text: &str) -> bool {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let all_digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    let (whole, decimals_ok) = match digits.split_once('.') {
+        Some((whole, decimals)) => (whole, all_digits(decimals)),
+        None => (digits, true),
+    };
+
+    all_digits(whole) && (whole == "0" || !whole.starts_with('0')) && decimals_ok
+}
+
+impl Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cell::Number(number) if number.fract() == 0.0 => write!(f, "{number:.0}"),
+            Cell::Number(number) => write!(f, "{number}"),
+            Cell::Text(text) | Cell::Code(text) | Cell::Link { text, .. } => write!(f, "{text}"),
+        }
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<f64> for Cell {
+    fn from(number: f64) -> Self {
+        Self::Number(number)
+    }
+}
+
+impl From<i32> for Cell {
+    fn from(number: i32) -> Self {
+        Self::Number(number.into())
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TableError {
+    #[error("CSV has no header row")]
+    NoHeader,
+
+    #[error("Row {row} has {found} cells, want {expected}")]
+    RowLength {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("Unterminated quote on line {line}")]
+    UnterminatedQuote { line: usize },
+}
+
+/// A table of data which can be put in an [`super::Article`].
+///
+/// # Example
+///
+/// ```rust
+/// use html_strong_homepage::components::table::{Align, Cell, DataTable};
+///
+/// let table = DataTable::new(&["Day", "Watered (dl)", "Notes"])
+///     .align(1, Align::Right)
+///     .row(vec!["Monday".into(), 2.into(), Cell::code("none")]);
+///
+/// let same = DataTable::from_csv("Day,Watered (dl),Notes\nMonday,2,`none`")
+///     .expect("valid csv")
+///     .align(1, Align::Right);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataTable {
+    pub(super) header: Vec<String>,
+    pub(super) align: Vec<Option<Align>>,
+    pub(super) rows: Vec<Vec<Cell>>,
+}
+
+impl DataTable {
+    pub fn new<S: AsRef<str>>(header: &[S]) -> Self {
+        Self {
+            header: header.iter().map(|s| s.as_ref().to_string()).collect(),
+            align: vec![None; header.len()],
+            rows: vec![],
+        }
+    }
+
+    /// Set the alignment of a column.
+    /// Without this, columns of only numbers are right aligned and everything else left aligned.
+    ///
+    /// If there is no such column, an error is logged and nothing changes.
+    pub fn align(mut self, column: usize, align: Align) -> Self {
+        match self.align.get_mut(column) {
+            Some(column_align) => *column_align = Some(align),
+            None => error!(column, columns = self.header.len(), "no column to align"),
+        }
+        self
+    }
+
+    /// Add a row. It must have as many cells as the header.
+    ///
+    /// If it does not, an error is logged and the row is left out.
+    pub fn row(mut self, cells: Vec<Cell>) -> Self {
+        if cells.len() != self.header.len() {
+            error!(
+                ?cells,
+                columns = self.header.len(),
+                "row left out, should have one cell per column"
+            );
+            return self;
+        }
+
+        self.rows.push(cells);
+        self
+    }
+
+    pub(super) fn column_align(&self, column: usize) -> Align {
+        self.align[column].unwrap_or_else(|| {
+            let numeric = !self.rows.is_empty()
+                && self
+                    .rows
+                    .iter()
+                    .all(|row| matches!(row[column], Cell::Number(_)));
+
+            if numeric {
+                Align::Right
+            } else {
+                Align::Left
+            }
+        })
+    }
+
+    /// Make a table from comma separated values.
+    ///
+    /// The first line is the header.
+    /// Fields may be quoted with `"`, and a quote is escaped by doubling it.
+    /// Every line is a row, so unlike full CSV a quoted field can't span lines.
+    /// Cell types are guessed via [`Cell::guess`].
+    pub fn from_csv(csv: &str) -> Result<Self, TableError> {
+        let mut lines = csv
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        let (line, header) = lines.next().ok_or(TableError::NoHeader)?;
+        let header = csv_fields(header).ok_or(TableError::UnterminatedQuote { line: line + 1 })?;
+
+        let mut table = Self::new(&header);
+
+        for (row, (line, text)) in lines.enumerate() {
+            let fields =
+                csv_fields(text).ok_or(TableError::UnterminatedQuote { line: line + 1 })?;
+
+            if fields.len() != header.len() {
+                return Err(TableError::RowLength {
+                    row: row + 1,
+                    expected: header.len(),
+                    found: fields.len(),
+                });
+            }
+
+            table
+                .rows
+                .push(fields.iter().map(|field| Cell::guess(field)).collect());
+        }
+
+        Ok(table)
+    }
+}
+
+// Split a single CSV line into its fields.
+// Returns `None` if a quote is left open.
+fn csv_fields(line: &str) -> Option<Vec<String>> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+
+    fields.push(field);
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_csv() {
+        let table = DataTable::from_csv(
+            "Set,Intensity (%),Link\n\
+             Warmup,50,[video](https://www.youtube.com/embed/IAnhFUUCq6c)\n\
+             \"Work, heavy\",85.5,`3x5`\n",
+        )
+        .unwrap();
+
+        assert_eq!(table.header, vec!["Set", "Intensity (%)", "Link"]);
+        assert_eq!(
+            table.rows,
+            vec![
+                vec![
+                    Cell::Text("Warmup".into()),
+                    Cell::Number(50.0),
+                    Cell::link("https://www.youtube.com/embed/IAnhFUUCq6c", "video"),
+                ],
+                vec![
+                    Cell::Text("Work, heavy".into()),
+                    Cell::Number(85.5),
+                    Cell::code("3x5"),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_csv_errors() {
+        assert_eq!(DataTable::from_csv(""), Err(TableError::NoHeader));
+        assert_eq!(
+            DataTable::from_csv("a,b\n1,2,3"),
+            Err(TableError::RowLength {
+                row: 1,
+                expected: 2,
+                found: 3
+            })
+        );
+        assert_eq!(
+            DataTable::from_csv("a,b\n\"1,2"),
+            Err(TableError::UnterminatedQuote { line: 2 })
+        );
+        assert_eq!(
+            DataTable::from_csv("a,b\n\"first line\nsecond line\",2"),
+            Err(TableError::UnterminatedQuote { line: 2 })
+        );
+    }
+
+    #[test]
+    fn test_quoted_fields() {
+        let table =
+            DataTable::from_csv("Quote,Empty\n\"He said \"\"3 by 5\"\"\",\"\"\n\"007\",").unwrap();
+
+        assert_eq!(
+            table.rows,
+            vec![
+                vec![
+                    Cell::Text("He said \"3 by 5\"".into()),
+                    Cell::Text("".into())
+                ],
+                vec![Cell::Text("007".into()), Cell::Text("".into())],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_guess() {
+        for (text, number) in [("0", 0.0), ("-12", -12.0), ("85.5", 85.5), (" 0.25 ", 0.25)] {
+            assert_eq!(Cell::guess(text), Cell::Number(number), "{text}");
+        }
+        for text in [
+            "007", "1e3", "+5", ".5", "5.", "-", "inf", "NaN", "1,000", "0x10",
+        ] {
+            assert_eq!(Cell::guess(text), Cell::Text(text.into()), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_unsafe_link() {
+        assert_eq!(
+            Cell::link("javascript:alert(1)", "click"),
+            Cell::Text("click".into())
+        );
+        assert_eq!(
+            Cell::guess("[click](javascript:alert(1))"),
+            Cell::Text("[click](javascript:alert(1))".into())
+        );
+        assert_eq!(
+            Cell::link("https://example.com", "fine"),
+            Cell::Link {
+                url: "https://example.com".into(),
+                text: "fine".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_bad_columns_are_ignored() {
+        let table = DataTable::new(&["a", "b"])
+            .align(2, Align::Right)
+            .row(vec![1.into()])
+            .row(vec![1.into(), 2.into()]);
+
+        assert_eq!(table.align, vec![None, None]);
+        assert_eq!(table.rows, vec![vec![Cell::Number(1.0), Cell::Number(2.0)]]);
+    }
+
+    #[test]
+    fn test_number_display() {
+        assert_eq!(Cell::Number(3.0).to_string(), "3");
+        assert_eq!(Cell::Number(2.5).to_string(), "2.5");
+    }
+}
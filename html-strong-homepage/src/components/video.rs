@@ -0,0 +1,232 @@
+use std::path::Path;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum VideoError {
+    #[error("Unknown video format, want webm or mp4: {0}")]
+    UnknownFormat(String),
+
+    #[error("A video needs at least one source")]
+    NoSources,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    Webm,
+    Mp4,
+}
+
+impl VideoFormat {
+    pub fn from_path(path: &str) -> Result<Self, VideoError> {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match ext.as_deref() {
+            Some("webm") => Ok(Self::Webm),
+            Some("mp4") => Ok(Self::Mp4),
+            _ => Err(VideoError::UnknownFormat(path.to_string())),
+        }
+    }
+
+    fn mime(&self) -> &'static str {
+        match self {
+            Self::Webm => "video/webm",
+            Self::Mp4 => "video/mp4",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playback {
+    /// Controls shown, looping, and the reader starts it.
+    Controls,
+
+    /// Muted, autoplaying, looping and inline on phones.
+    /// For short clips which should feel like a moving image.
+    Clip,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Poster {
+    /// An image shown until the video plays.
+    Image(String),
+
+    /// No image, instead the video is told to start just after zero and its metadata is preloaded.
+    /// Browsers which load that much show the first frame, others show nothing until it plays.
+    SeekToStart,
+}
+
+/// A video which can be put in an [`super::Article`].
+///
+/// # Example
+///
+/// ```rust
+/// use html_strong_homepage::components::video::VideoClip;
+///
+/// // The browser picks the first source it can play.
+/// let clip = VideoClip::new(&["island.webm", "island.mp4"])
+///     .expect("known formats")
+///     .seek_to_start()
+///     .clip();
+///
+/// assert!(VideoClip::new(&["island.gif"]).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct VideoClip {
+    pub(super) sources: Vec<(String, VideoFormat)>,
+    pub(super) poster: Option<Poster>,
+    pub(super) playback: Playback,
+}
+
+impl VideoClip {
+    /// A video with the given sources, in order of preference.
+    pub fn new<S: AsRef<str>>(paths: &[S]) -> Result<Self, VideoError> {
+        if paths.is_empty() {
+            return Err(VideoError::NoSources);
+        }
+
+        let sources = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                VideoFormat::from_path(path).map(|format| (path.to_string(), format))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            sources,
+            poster: None,
+            playback: Playback::Controls,
+        })
+    }
+
+    /// Show this image until the video plays.
+    pub fn poster(mut self, path: &str) -> Self {
+        self.poster = Some(Poster::Image(path.to_string()));
+        self
+    }
+
+    /// Ask the browser to show the start of the video until it plays, see [`Poster::SeekToStart`].
+    pub fn seek_to_start(mut self) -> Self {
+        self.poster = Some(Poster::SeekToStart);
+        self
+    }
+
+    /// Play as a muted, autoplaying, looping clip.
+    pub fn clip(mut self) -> Self {
+        self.playback = Playback::Clip;
+        self
+    }
+
+    /// The `<video>` element, with paths made absolute via `absolute_path`.
+    /// Written as markup since `html_strong` has no way to set most of these attributes.
+    pub(super) fn markup(&self, class: &str, absolute_path: impl Fn(&str) -> String) -> String {
+        let quoted = |value: &str| html_escape::encode_double_quoted_attribute(value).to_string();
+
+        let mut attributes = vec![format!("class=\"{}\"", quoted(class))];
+        let flags: &[&str] = match self.playback {
+            Playback::Controls => &["controls", "loop"],
+            Playback::Clip => &["muted", "autoplay", "loop", "playsinline"],
+        };
+        attributes.extend(flags.iter().map(|flag| flag.to_string()));
+
+        // Browsers which preload the metadata show the frame a video is told to start at,
+        // so starting just after zero may get us the first frame.
+        let fragment = match &self.poster {
+            Some(Poster::Image(poster)) => {
+                attributes.push(format!("poster=\"{}\"", quoted(&absolute_path(poster))));
+                ""
+            }
+            Some(Poster::SeekToStart) => {
+                attributes.push("preload=\"metadata\"".to_string());
+                "#t=0.001"
+            }
+            None => "",
+        };
+
+        let sources = self
+            .sources
+            .iter()
+            .map(|(path, format)| {
+                format!(
+                    "<source src=\"{}\" type=\"{}\">",
+                    quoted(&format!("{}{fragment}", absolute_path(path))),
+                    format.mime()
+                )
+            })
+            .collect::<String>();
+
+        format!("<video {}>{sources}</video>", attributes.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use html_strong::science_lab::NodeExt;
+
+    use super::*;
+    use crate::components::Article;
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(VideoFormat::from_path("a/b.webm"), Ok(VideoFormat::Webm));
+        assert_eq!(VideoFormat::from_path("a/b.MP4"), Ok(VideoFormat::Mp4));
+        assert_eq!(
+            VideoFormat::from_path("a/b.mov"),
+            Err(VideoError::UnknownFormat("a/b.mov".into()))
+        );
+        assert_eq!(
+            VideoFormat::from_path("webm"),
+            Err(VideoError::UnknownFormat("webm".into()))
+        );
+        assert_eq!(
+            VideoClip::new::<&str>(&[]).unwrap_err(),
+            VideoError::NoSources
+        );
+    }
+
+    #[test]
+    fn test_markup() {
+        let absolute_path = |path: &str| format!("/static/blender/{path}");
+
+        let video = VideoClip::new(&["island.webm", "island.mp4"])
+            .unwrap()
+            .poster("island \"poster\".webp");
+        assert_eq!(
+            video.markup("rounded", absolute_path),
+            "<video class=\"rounded\" controls loop poster=\"/static/blender/island &quot;poster&quot;.webp\">\
+             <source src=\"/static/blender/island.webm\" type=\"video/webm\">\
+             <source src=\"/static/blender/island.mp4\" type=\"video/mp4\">\
+             </video>"
+        );
+
+        let clip = VideoClip::new(&["island.mp4"])
+            .unwrap()
+            .seek_to_start()
+            .clip();
+        assert_eq!(
+            clip.markup("rounded", absolute_path),
+            "<video class=\"rounded\" muted autoplay loop playsinline preload=\"metadata\">\
+             <source src=\"/static/blender/island.mp4#t=0.001\" type=\"video/mp4\">\
+             </video>"
+        );
+    }
+
+    #[test]
+    fn test_unknown_format_is_a_link() {
+        let article = Article {
+            url_prefix: Some("/static/blender".to_string()),
+            ..Article::new().video("island.gif")
+        };
+        let html = article
+            .into_node()
+            .render_string()
+            .expect("article should render");
+
+        assert!(html.contains("href=\"/static/blender/island.gif\""));
+        assert!(!html.contains("<video"));
+    }
+}
@@ -0,0 +1,266 @@
+use std::{fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum YoutubeError {
+    #[error("Not a YouTube url: {0}")]
+    NotYoutube(String),
+
+    #[error("No video id in url: {0}")]
+    NoVideoId(String),
+
+    #[error("Bad timestamp: {0}")]
+    BadTimestamp(String),
+}
+
+/// A point in time in a video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    seconds: u32,
+}
+
+impl Timestamp {
+    pub fn seconds(&self) -> u32 {
+        self.seconds
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = YoutubeError;
+
+    /// Parses what people write in chapter notes, `9:05` or `1:02:03`,
+    /// and what YouTube puts in urls, `545`, `545s` or `9m5s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || YoutubeError::BadTimestamp(s.to_string());
+        let s = s.trim();
+
+        let seconds = if s.contains(':') {
+            let parts = s
+                .split(':')
+                .map(|part| part.parse::<u32>().map_err(|_| bad()))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match parts.as_slice() {
+                [m, s] if *s < 60 => in_seconds(0, *m, *s),
+                [h, m, s] if *m < 60 && *s < 60 => in_seconds(*h, *m, *s),
+                _ => None,
+            }
+            .ok_or_else(bad)?
+        } else if s.ends_with(|c: char| c.is_ascii_digit()) {
+            s.parse().map_err(|_| bad())?
+        } else {
+            let mut seconds: u32 = 0;
+            let mut number = String::new();
+
+            for c in s.chars() {
+                match c {
+                    '0'..='9' => number.push(c),
+                    'h' | 'm' | 's' => {
+                        let value: u32 = number.parse().map_err(|_| bad())?;
+                        number.clear();
+
+                        let value = match c {
+                            'h' => in_seconds(value, 0, 0),
+                            'm' => in_seconds(0, value, 0),
+                            _ => Some(value),
+                        };
+                        seconds = value
+                            .and_then(|value| seconds.checked_add(value))
+                            .ok_or_else(bad)?;
+                    }
+                    _ => return Err(bad()),
+                }
+            }
+
+            seconds
+        };
+
+        Ok(Self { seconds })
+    }
+}
+
+// `None` if it doesn't fit.
+fn in_seconds(h: u32, m: u32, s: u32) -> Option<u32> {
+    h.checked_mul(60 * 60)?
+        .checked_add(m.checked_mul(60)?)?
+        .checked_add(s)
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let h = self.seconds / 60 / 60;
+        let m = self.seconds / 60 % 60;
+        let s = self.seconds % 60;
+
+        if h > 0 {
+            write!(f, "{h}:{m:02}:{s:02}")
+        } else {
+            write!(f, "{m}:{s:02}")
+        }
+    }
+}
+
+/// A YouTube video, possibly starting at some point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YoutubeVideo {
+    id: String,
+    start: Option<Timestamp>,
+}
+
+impl YoutubeVideo {
+    /// Parse `youtube.com/watch?v=..`, `youtube.com/embed/..` and `youtu.be/..` urls,
+    /// including a start time given by `t` or `start`.
+    pub fn parse(url: &str) -> Result<Self, YoutubeError> {
+        let not_youtube = || YoutubeError::NotYoutube(url.to_string());
+
+        let without_scheme = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .unwrap_or(url);
+        let (host, path) = without_scheme.split_once('/').ok_or_else(not_youtube)?;
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+
+        let param = |name: &str| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|&(key, _)| key == name)
+                .map(|(_, value)| value)
+        };
+
+        let id = match host.trim_start_matches("www.").trim_start_matches("m.") {
+            "youtube.com" | "youtube-nocookie.com" => match path.split_once('/') {
+                Some(("embed", id)) => Some(id),
+                None if path == "watch" => param("v"),
+                _ => None,
+            },
+            "youtu.be" => Some(path),
+            _ => return Err(not_youtube()),
+        }
+        .filter(|id| {
+            !id.is_empty()
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .ok_or_else(|| YoutubeError::NoVideoId(url.to_string()))?;
+
+        let start = param("t")
+            .or_else(|| param("start"))
+            .map(str::parse)
+            .transpose()?;
+
+        Ok(Self {
+            id: id.to_string(),
+            start,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The same video, starting at the given time instead.
+    pub fn at(&self, start: Timestamp) -> Self {
+        Self {
+            id: self.id.clone(),
+            start: Some(start),
+        }
+    }
+
+    /// The url to watch this video on YouTube itself.
+    pub fn watch_url(&self) -> String {
+        match self.start {
+            Some(start) => format!(
+                "https://www.youtube.com/watch?v={}&t={}s",
+                self.id,
+                start.seconds()
+            ),
+            None => format!("https://www.youtube.com/watch?v={}", self.id),
+        }
+    }
+}
+
+/// A click-to-load YouTube embed.
+///
+/// Until clicked this is a plain link to the video showing a local poster image,
+/// so nothing is loaded from YouTube just by visiting the page.
+/// With JS the click swaps the link for the actual player.
+#[derive(Debug, Clone)]
+pub struct YoutubeEmbed {
+    pub video: YoutubeVideo,
+    pub title: String,
+    pub poster: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(s: &str) -> u32 {
+        s.parse::<Timestamp>().unwrap().seconds()
+    }
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(ts("9:05"), 545);
+        assert_eq!(ts("47:30"), 2850);
+        assert_eq!(ts("1:02:03"), 3723);
+        assert_eq!(ts("545"), 545);
+        assert_eq!(ts("545s"), 545);
+        assert_eq!(ts("9m5s"), 545);
+        assert_eq!(ts("1h2m3s"), 3723);
+
+        assert!("9:65".parse::<Timestamp>().is_err());
+        assert!("soon".parse::<Timestamp>().is_err());
+
+        // Too long to count in seconds.
+        for timestamp in [
+            "71582789:00",
+            "1193047:00:00",
+            "1193047h",
+            "71582789m",
+            "4294967295s1s",
+        ] {
+            assert_eq!(
+                timestamp.parse::<Timestamp>(),
+                Err(YoutubeError::BadTimestamp(timestamp.into()))
+            );
+        }
+        assert_eq!(ts("1193046:00:00"), 1193046 * 60 * 60);
+
+        assert_eq!("545".parse::<Timestamp>().unwrap().to_string(), "9:05");
+        assert_eq!("3723".parse::<Timestamp>().unwrap().to_string(), "1:02:03");
+    }
+
+    #[test]
+    fn test_urls() {
+        for url in [
+            "https://www.youtube.com/embed/IAnhFUUCq6c",
+            "https://www.youtube.com/watch?v=IAnhFUUCq6c",
+            "https://youtu.be/IAnhFUUCq6c",
+        ] {
+            let video = YoutubeVideo::parse(url).unwrap();
+            assert_eq!(video.id(), "IAnhFUUCq6c");
+            assert_eq!(
+                video.watch_url(),
+                "https://www.youtube.com/watch?v=IAnhFUUCq6c"
+            );
+        }
+
+        let video = YoutubeVideo::parse("https://youtu.be/MyKrc-fheBw?t=545").unwrap();
+        assert_eq!(
+            video.watch_url(),
+            "https://www.youtube.com/watch?v=MyKrc-fheBw&t=545s"
+        );
+
+        assert_eq!(
+            YoutubeVideo::parse("https://torste.in/timelapse"),
+            Err(YoutubeError::NotYoutube(
+                "https://torste.in/timelapse".into()
+            ))
+        );
+        assert!(YoutubeVideo::parse("https://www.youtube.com/watch?list=abc").is_err());
+    }
+}
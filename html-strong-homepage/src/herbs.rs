@@ -1,86 +1,912 @@
-use std::{io::Cursor, path::PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use axum::{response::IntoResponse, routing::post, Json, Router};
-use image::GenericImageView;
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Query, RawBody},
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Local, Utc};
 use reqwest::StatusCode;
 use thiserror::Error;
+use tokio::sync::Mutex;
 use tower_http::{auth, limit};
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
-use shared::image::Image;
+use serde::Serialize;
+use shared::{
+    accept::{AcceptRules, Rejection},
+    image::{content_hash, Image, UploadReceipt},
+    signing::SignatureError,
+};
+use timelapsifier::{
+    capture::{self, CaptureMeta, CaptureQuery},
+    schedule::Trigger,
+    StateVideos, TimelapserOptions,
+};
 
 pub mod basil;
+pub mod timelapse;
+pub mod tokens;
+pub mod uploads;
+
+use tokens::{Credentials, RequireBearerToken, RequireUploadToken, UploadTokens, Uploader};
 
 #[derive(Debug, Error)]
 pub enum ImageError {
     #[error("Io issue")]
     Io(#[from] std::io::Error),
 
-    #[error("Image decode issue")]
-    Image(#[from] image::ImageError),
+    #[error("Image not accepted")]
+    Rejected(#[from] Rejection),
+
+    #[error("No camera called {0}")]
+    UnknownCamera(String),
+
+    #[error("Not allowed to upload images of {0}")]
+    WrongCamera(String),
+
+    #[error("Upload not signed with a known token")]
+    Signature(#[from] SignatureError),
+
+    #[error("Invalid header {0}")]
+    BadHeader(String),
+
+    #[error("Upload could not be received")]
+    Body(String),
 
-    #[error("Image wrong size {width}, {height}, want 1280x720")]
-    BadSize { width: u32, height: u32 },
+    #[error("Image larger than {max} bytes")]
+    TooLarge { max: u64 },
+
+    #[error("Image has SHA-256 {actual}, not {claimed}")]
+    HashMismatch { claimed: String, actual: String },
+
+    #[error("Idempotency key already used for another image, stored as {stored_as}")]
+    KeyReused { stored_as: String },
+
+    #[error("Another image is already stored as {stored_as}")]
+    Exists { stored_as: String },
+
+    #[error("Image could not be checked")]
+    Join(#[from] tokio::task::JoinError),
+
+    #[error("Could not store capture metadata")]
+    Metadata(#[from] timelapsifier::TimelapsifyError),
 }
 
 impl IntoResponse for ImageError {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::BAD_REQUEST, format!("{self:?}")).into_response()
+        let status = match self {
+            ImageError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ImageError::WrongCamera(_) => StatusCode::FORBIDDEN,
+            ImageError::Signature(_) => StatusCode::UNAUTHORIZED,
+            ImageError::Join(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ImageError::KeyReused { .. } | ImageError::Exists { .. } => StatusCode::CONFLICT,
+            _ => StatusCode::BAD_REQUEST,
+        };
+
+        (status, format!("{self:?}")).into_response()
     }
 }
 
-async fn handle_new_image(Json(image): Json<Image>) -> Result<(), ImageError> {
-    debug!(?image, "New image");
-    let file_name = shared::image::human_time(&image.timestamp);
+/// A camera, where its images go and the timelapses made from them.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub id: String,
+    pub upload_folder: PathBuf,
 
-    let image = image::io::Reader::new(Cursor::new(image.buffer))
-        .with_guessed_format()?
-        .decode()?;
+    /// Records of the images uploaded, see [`uploads`].
+    pub upload_records: PathBuf,
 
-    let (width, height) = image.dimensions();
-    if width != 1280 || height != 720 {
-        return Err(ImageError::BadSize { width, height });
+    pub capture_folders: CaptureFolders,
+    pub videos: StateVideos,
+    pub trigger: Trigger,
+}
+
+impl Camera {
+    /// The camera a timelapsifier worker makes timelapses for.
+    pub fn new(id: &str, options: &TimelapserOptions, upload_records: PathBuf) -> Self {
+        Self {
+            id: id.to_string(),
+            upload_folder: options.unprocessed_images_folder.clone(),
+            upload_records,
+            capture_folders: CaptureFolders(vec![
+                options.processed_images_folder.clone(),
+                options.unprocessed_images_folder.clone(),
+            ]),
+            videos: options.timelapse_videos.clone(),
+            trigger: options.trigger.clone(),
+        }
     }
+}
 
-    let output_path = PathBuf::from(format!(
-        "{}/{}/{file_name}.jpg",
-        std::env!("CARGO_MANIFEST_DIR"),
-        shared::herbs::new_image_output_relative_folder()
-    ));
-    debug!("Storing image: `{output_path:?}`");
+/// All the cameras, the first one being the default.
+#[derive(Debug, Clone)]
+pub struct Cameras(Arc<Vec<Camera>>);
 
-    if let Some(parent) = output_path.parent() {
-        if !parent.exists() {
-            info!("Creating folder: {parent:?}");
+impl Cameras {
+    /// Panics if there are no cameras, or if an id is the name of a timelapse page.
+    pub fn new(cameras: Vec<Camera>) -> Self {
+        assert!(!cameras.is_empty(), "there should be a camera");
+        for camera in &cameras {
+            assert!(
+                !timelapse::is_page_name(&camera.id),
+                "camera {} would hide the timelapse page with that name",
+                camera.id
+            );
+        }
 
-            std::fs::create_dir_all(parent)?;
+        Self(Arc::new(cameras))
+    }
+
+    pub fn default_camera(&self) -> &Camera {
+        &self.0[0]
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Camera> {
+        self.0.iter().find(|camera| camera.id == id)
+    }
+
+    /// The camera an image is uploaded from.
+    /// Images which don't say where they're from are from the default camera.
+    pub fn uploaded_by(&self, device: Option<&str>) -> Result<&Camera, ImageError> {
+        match device {
+            Some(device) => self
+                .get(device)
+                .ok_or_else(|| ImageError::UnknownCamera(device.to_string())),
+            None => Ok(self.default_camera()),
         }
     }
 
-    image.save(output_path)?;
+    pub fn iter(&self) -> impl Iterator<Item = &Camera> {
+        self.0.iter()
+    }
+
+    /// The folders with captured images of all cameras.
+    pub fn capture_folders(&self) -> CaptureFolders {
+        CaptureFolders(
+            self.iter()
+                .flat_map(|camera| camera.capture_folders.0.clone())
+                .collect(),
+        )
+    }
+}
+
+/// Where a camera keeps something, such as [`shared::herbs::new_image_output_relative_folder`].
+type CameraFolder = fn(&str) -> String;
+
+/// Where images and job records were kept when there was a single camera,
+/// and where each camera keeps them now.
+/// Timelapses and notes were already kept where the `basil` camera keeps them.
+const SINGLE_CAMERA_FOLDERS: [(&str, CameraFolder); 3] = [
+    (
+        "upload/herbs/webcam",
+        shared::herbs::new_image_output_relative_folder,
+    ),
+    (
+        "processed/herbs/webcam",
+        shared::herbs::processed_image_output_relative_folder,
+    ),
+    (
+        "jobs/herbs/timelapse",
+        shared::herbs::timelapse_jobs_relative_folder,
+    ),
+];
+
+/// Move the folders from when there was a single camera to where the given camera keeps them,
+/// unless it already has some.
+pub fn move_single_camera_folders(root: &Path, camera: &str) -> std::io::Result<()> {
+    for (before, now) in SINGLE_CAMERA_FOLDERS {
+        let (before, now) = (root.join(before), root.join(now(camera)));
+        if !before.exists() || now.exists() {
+            continue;
+        }
 
-    debug!("Image saved!");
+        info!(?before, ?now, "Moving single camera folder");
+        if let Some(parent) = now.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(before, now)?;
+    }
 
     Ok(())
 }
 
-/// Get POST url and router for uploading new images from
-/// Raspberry Pi.
-///
-/// Spawns worker which processes images into new timelapse MP4s.
-pub fn timelapsify_init(options: timelapsifier::TimelapserOptions) -> (&'static str, Router) {
-    timelapsifier::spawn_worker(options);
+/// How uploaded images are handled.
+#[derive(Debug)]
+struct Uploads {
+    rules: AcceptRules,
+
+    /// Who signed an upload, see [`Credentials::uploader`].
+    tokens: Arc<UploadTokens>,
+
+    /// The largest image which can be uploaded, see [`shared::herbs::upload_max_bytes`].
+    max_bytes: u64,
+
+    /// Held from looking for an earlier upload until the image is recorded,
+    /// so an image sent twice at once is stored once.
+    /// Images are accepted before it is taken, so uploads don't wait on each other's decoding.
+    lock: Mutex<()>,
+}
+
+/// What an upload says about itself, besides the image.
+#[derive(Debug, Default)]
+struct UploadClaims {
+    /// See [`shared::herbs::CONTENT_SHA256_HEADER`].
+    sha256: Option<String>,
+
+    /// See [`shared::herbs::IDEMPOTENCY_KEY_HEADER`].
+    idempotency_key: Option<String>,
+}
+
+impl UploadClaims {
+    fn from_headers(headers: &HeaderMap) -> Result<Self, ImageError> {
+        Ok(Self {
+            sha256: header_value(headers, shared::herbs::CONTENT_SHA256_HEADER)?
+                .map(str::to_lowercase),
+            idempotency_key: header_value(headers, shared::herbs::IDEMPOTENCY_KEY_HEADER)?
+                .map(String::from),
+        })
+    }
+}
+
+/// The value of a header, if it is there.
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Result<Option<&'a str>, ImageError> {
+    headers
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_| ImageError::BadHeader(name.to_string()))
+        })
+        .transpose()
+}
+
+/// The camera an image is uploaded from, see [`Cameras::uploaded_by`].
+/// Devices with their own token upload images of their own camera, so they don't need to say which.
+fn camera_of<'a>(
+    cameras: &'a Cameras,
+    uploader: &Uploader,
+    device: Option<&str>,
+) -> Result<&'a Camera, ImageError> {
+    let camera = cameras.uploaded_by(device.or_else(|| uploader.device()))?;
+    if !uploader.may_upload(&camera.id) {
+        return Err(ImageError::WrongCamera(camera.id.clone()));
+    }
+
+    Ok(camera)
+}
+
+/// Tell the uploader an image is already stored, so it can stop sending it.
+fn already_stored(stored_as: String) -> (StatusCode, Json<UploadReceipt>) {
+    debug!(%stored_as, "Image already stored");
 
     (
-        shared::herbs::IMAGE_POST_ENDPOINT,
-        Router::new()
-            .route("/", post(handle_new_image))
-            .layer(auth::RequireAuthorizationLayer::bearer(
-                &shared::herbs::new_image_auth(),
-            ))
-            .layer(
-                // Max 500 KB images
-                limit::RequestBodyLimitLayer::new(1024 * 500),
-            ),
+        StatusCode::OK,
+        Json(UploadReceipt {
+            stored_as,
+            duplicate: true,
+        }),
     )
 }
+
+/// Store an uploaded image under its camera, along with its metadata.
+///
+/// An image which was uploaded before, by content or by idempotency key, is not stored again.
+/// Responds with `201 Created` for a new image, and `200 OK` for one already stored.
+async fn store_image(
+    cameras: &Cameras,
+    uploads: &Uploads,
+    uploader: &Uploader,
+    upload: Image,
+    claims: UploadClaims,
+) -> Result<(StatusCode, Json<UploadReceipt>), ImageError> {
+    debug!(?upload, ?uploader, "New image");
+
+    let camera = camera_of(cameras, uploader, upload.device.as_deref())?;
+
+    let received = Utc::now();
+    let file_name = shared::image::file_name_time(&upload.timestamp);
+
+    let sha256 = content_hash(&upload.buffer);
+    if let Some(claimed) = claims.sha256 {
+        if claimed != sha256 {
+            return Err(ImageError::HashMismatch {
+                claimed,
+                actual: sha256,
+            });
+        }
+    }
+
+    // Decoding, resizing and encoding takes a while, so it is done off the runtime,
+    // and before waiting for other uploads.
+    let bytes = upload.buffer.len() as u64;
+    let rules = uploads.rules.clone();
+    let buffer = upload.buffer;
+    let accepted = tokio::task::spawn_blocking(move || rules.accept(&buffer)).await??;
+
+    let stored_as = format!("{file_name}.{}", accepted.format.extension());
+    let output_path = camera.upload_folder.join(&stored_as);
+
+    let _lock = uploads.lock.lock().await;
+
+    let records = &camera.upload_records;
+    if let Some(key) = claims.idempotency_key.as_deref() {
+        if let Some(record) = uploads::find(records, uploads::Key::Idempotency(key)).await? {
+            if record.sha256 != sha256 {
+                return Err(ImageError::KeyReused {
+                    stored_as: record.stored_as,
+                });
+            }
+
+            return Ok(already_stored(record.stored_as));
+        }
+    }
+    if let Some(record) = uploads::find(records, uploads::Key::Content(&sha256)).await? {
+        return Ok(already_stored(record.stored_as));
+    }
+
+    debug!("Storing image: `{output_path:?}`");
+
+    // Either stored before there were records, or another image taken at the same time.
+    let duplicate = match tokio::fs::read(&output_path).await {
+        Ok(existing) if existing == accepted.bytes => true,
+        Ok(_) => return Err(ImageError::Exists { stored_as }),
+        Err(_) => false,
+    };
+
+    if !duplicate {
+        if let Some(parent) = output_path.parent() {
+            if !parent.exists() {
+                info!("Creating folder: {parent:?}");
+
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
+
+        tokio::fs::write(&output_path, &accepted.bytes).await?;
+
+        let meta = CaptureMeta {
+            device: Some(camera.id.clone()),
+            captured: upload.timestamp,
+            received,
+            format: accepted.original_format.to_string(),
+            width: accepted.width,
+            height: accepted.height,
+            bytes,
+            readings: upload.readings,
+        };
+        capture::write(&output_path, &meta).await?;
+
+        debug!("Image saved!");
+    }
+
+    let record = uploads::UploadRecord {
+        stored_as: stored_as.clone(),
+        sha256,
+        received,
+    };
+    uploads::record(records, uploads::Key::Content(&record.sha256), &record).await?;
+    if let Some(key) = claims.idempotency_key.as_deref() {
+        uploads::record(records, uploads::Key::Idempotency(key), &record).await?;
+    }
+
+    if duplicate {
+        return Ok(already_stored(stored_as));
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(UploadReceipt {
+            stored_as,
+            duplicate: false,
+        }),
+    ))
+}
+
+async fn handle_new_image(
+    Extension(cameras): Extension<Cameras>,
+    Extension(uploads): Extension<Arc<Uploads>>,
+    Extension(credentials): Extension<Credentials>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<UploadReceipt>), ImageError> {
+    let uploader = credentials.uploader(&uploads.tokens, &headers, &body)?;
+    let upload: Image =
+        serde_json::from_slice(&body).map_err(|error| ImageError::Body(error.to_string()))?;
+    let claims = UploadClaims::from_headers(&headers)?;
+    store_image(&cameras, &uploads, &uploader, upload, claims).await
+}
+
+/// The body as it arrives, stopping as soon as it is larger than `max` bytes.
+///
+/// Kept in memory, nothing is written until the image is accepted,
+/// so uploads arriving at the same time can't get in each other's way.
+async fn receive(mut body: Body, max: u64) -> Result<Vec<u8>, ImageError> {
+    let mut buffer = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|error| ImageError::Body(error.to_string()))?;
+
+        if (buffer.len() + chunk.len()) as u64 > max {
+            return Err(ImageError::TooLarge { max });
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+
+    Ok(buffer)
+}
+
+/// An image POSTed as it is, with the rest in headers.
+async fn handle_upload_image(
+    Extension(cameras): Extension<Cameras>,
+    Extension(uploads): Extension<Arc<Uploads>>,
+    Extension(credentials): Extension<Credentials>,
+    headers: HeaderMap,
+    RawBody(body): RawBody,
+) -> Result<(StatusCode, Json<UploadReceipt>), ImageError> {
+    let value_of = |name: &str| header_value(&headers, name);
+    let max = uploads.max_bytes;
+
+    let timestamp = match value_of(shared::herbs::CAPTURE_TIMESTAMP_HEADER)? {
+        Some(timestamp) => DateTime::parse_from_rfc3339(timestamp)
+            .map_err(|error| ImageError::BadHeader(format!("{timestamp}: {error}")))?
+            .with_timezone(&Utc),
+        None => Utc::now(),
+    };
+    let device = value_of(shared::herbs::CAMERA_HEADER)?.map(String::from);
+    let readings = value_of(shared::herbs::READINGS_HEADER)?
+        .map(shared::image::parse_readings)
+        .transpose()
+        .map_err(ImageError::BadHeader)?
+        .unwrap_or_default();
+
+    let claims = UploadClaims::from_headers(&headers)?;
+    // Who signed an upload is only known once it has arrived,
+    // so until then nothing is said about its camera.
+    if let Credentials::Bearer(uploader) = &credentials {
+        camera_of(&cameras, uploader, device.as_deref())?;
+    }
+
+    // No need to wait for the body of an image which says it is too large.
+    let length =
+        value_of(header::CONTENT_LENGTH.as_str())?.and_then(|length| length.parse::<u64>().ok());
+    if matches!(length, Some(length) if length > max) {
+        return Err(ImageError::TooLarge { max });
+    }
+
+    let buffer = receive(body, max).await?;
+    let uploader = credentials.uploader(&uploads.tokens, &headers, &buffer)?;
+
+    let upload = Image {
+        buffer,
+        timestamp,
+        device,
+        readings,
+    };
+    store_image(&cameras, &uploads, &uploader, upload, claims).await
+}
+
+/// Router for uploading new images from the cameras, either as they are or as JSON.
+fn upload_router(
+    cameras: Cameras,
+    tokens: UploadTokens,
+    rules: AcceptRules,
+    max_bytes: u64,
+) -> Router {
+    let tokens = Arc::new(tokens);
+
+    Router::new()
+        .route(
+            shared::herbs::IMAGE_POST_ENDPOINT,
+            post(handle_new_image).layer(limit::RequestBodyLimitLayer::new(
+                (max_bytes * 4).try_into().unwrap_or(usize::MAX),
+            )),
+        )
+        .route(
+            shared::herbs::IMAGE_UPLOAD_ENDPOINT,
+            post(handle_upload_image),
+        )
+        .layer(Extension(cameras))
+        .layer(Extension(Arc::new(Uploads {
+            rules,
+            tokens: tokens.clone(),
+            max_bytes,
+            lock: Mutex::new(()),
+        })))
+        .layer(auth::RequireAuthorizationLayer::custom(RequireUploadToken(
+            tokens,
+        )))
+}
+
+/// Router for uploading new images from Raspberry Pi.
+///
+/// Images are accepted by the rules from [`AcceptRules::from_env`], the same ones the uploader uses,
+/// from devices with one of the `tokens`.
+///
+/// Spawns a worker per camera which processes its images into new timelapse MP4s.
+pub fn timelapsify_init(
+    workers: Vec<TimelapserOptions>,
+    cameras: Cameras,
+    tokens: UploadTokens,
+) -> Router {
+    for options in workers {
+        timelapsifier::spawn_worker(options);
+    }
+
+    upload_router(
+        cameras,
+        tokens,
+        AcceptRules::from_env(),
+        shared::herbs::upload_max_bytes(),
+    )
+}
+
+async fn handle_timelapse_run(Extension(cameras): Extension<Cameras>) -> StatusCode {
+    info!("Timelapses triggered");
+    for camera in cameras.iter() {
+        camera.trigger.run_now();
+    }
+
+    StatusCode::ACCEPTED
+}
+
+/// Router for making timelapses of all cameras right away, instead of waiting for the schedule.
+///
+/// Has no routes if no bearer token is configured.
+pub fn timelapse_run_router(cameras: Cameras) -> Router {
+    let token = match shared::herbs::timelapse_run_auth() {
+        Some(token) => token,
+        None => return Router::new(),
+    };
+
+    Router::new().route(
+        shared::herbs::TIMELAPSE_RUN_ENDPOINT,
+        post(handle_timelapse_run).layer(Extension(cameras)).layer(
+            auth::RequireAuthorizationLayer::custom(RequireBearerToken(token)),
+        ),
+    )
+}
+
+/// Folders with captured images, newest last.
+#[derive(Debug, Clone)]
+pub struct CaptureFolders(pub Vec<PathBuf>);
+
+#[derive(Debug, Serialize)]
+struct CaptureResponse {
+    file_name: String,
+    timestamp: DateTime<Local>,
+    meta: Option<CaptureMeta>,
+}
+
+async fn handle_captures(
+    Query(query): Query<CaptureQuery>,
+    Extension(folders): Extension<CaptureFolders>,
+) -> Result<Json<Vec<CaptureResponse>>, StatusCode> {
+    let captures = capture::query(&folders.0, &query).await.map_err(|error| {
+        error!(?error, "Could not query captures");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(
+        captures
+            .into_iter()
+            .map(|capture| CaptureResponse {
+                file_name: capture
+                    .file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                timestamp: capture.timestamp,
+                meta: capture.meta,
+            })
+            .collect(),
+    ))
+}
+
+/// Router for querying the metadata of captured images, such as
+/// `?device=basil&from=2022-07-12T00:00:00Z&to=2022-07-13T00:00:00Z`.
+pub fn captures_router(folders: CaptureFolders) -> Router {
+    Router::new().route(
+        shared::herbs::CAPTURES_ENDPOINT,
+        get(handle_captures).layer(Extension(folders)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use axum::http::Request;
+    use chrono::TimeZone;
+    use image::{DynamicImage, ImageOutputFormat, RgbImage};
+    use shared::signing::Signature;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn camera(root: &Path, id: &str) -> Camera {
+        Camera {
+            id: id.to_string(),
+            upload_folder: root.join(id),
+            upload_records: root.join("records").join(id),
+            capture_folders: CaptureFolders(vec![root.join(id)]),
+            videos: Default::default(),
+            trigger: Default::default(),
+        }
+    }
+
+    fn jpeg() -> Vec<u8> {
+        jpeg_of(RgbImage::new(1280, 720))
+    }
+
+    fn jpeg_of(image: RgbImage) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut bytes, ImageOutputFormat::Jpeg(80))
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    /// An upload from the `basil` camera.
+    fn post(uri: &str) -> axum::http::request::Builder {
+        post_as(uri, "b4s1l")
+    }
+
+    fn post_as(uri: &str, token: &str) -> axum::http::request::Builder {
+        Request::post(uri).header(header::AUTHORIZATION, format!("Bearer {token}"))
+    }
+
+    #[tokio::test]
+    async fn test_upload() {
+        let root = std::env::temp_dir().join(format!("homepage-upload-{}", std::process::id()));
+        let cameras = Cameras::new(vec![camera(&root, "basil"), camera(&root, "chili")]);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("tokens"), "basil b4s1l\nchili ch1l1\n").unwrap();
+        let tokens = UploadTokens::new(Some(root.join("tokens")), None, false).unwrap();
+        let router = upload_router(cameras, tokens, AcceptRules::default(), 100_000);
+        let status = |request: Request<Body>| {
+            let router = router.clone();
+            async move { router.oneshot(request).await.unwrap().status() }
+        };
+        let receipt = |request: Request<Body>| {
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let status = response.status();
+                let mut body = vec![];
+                let mut response = response.into_body();
+                while let Some(chunk) = response.data().await {
+                    body.extend_from_slice(&chunk.unwrap());
+                }
+                let receipt: UploadReceipt = serde_json::from_slice(&body)
+                    .unwrap_or_else(|_| panic!("{status}: {}", String::from_utf8_lossy(&body)));
+                (status, receipt)
+            }
+        };
+
+        let timestamp = Utc.ymd(2022, 7, 12).and_hms(10, 0, 0);
+        let stored = |camera: &str| {
+            root.join(camera)
+                .join(format!("{}.jpg", shared::image::file_name_time(&timestamp)))
+        };
+
+        // As JSON, from the default camera.
+        let upload = serde_json::to_vec(&Image::new_with_timestamp(&jpeg(), timestamp)).unwrap();
+        let json = || {
+            post(shared::herbs::IMAGE_POST_ENDPOINT)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(upload.clone()))
+                .unwrap()
+        };
+        let (code, stored_as) = receipt(json()).await;
+        assert_eq!(code, StatusCode::CREATED);
+        assert!(!stored_as.duplicate);
+        assert!(stored("basil").ends_with(&stored_as.stored_as));
+        assert!(stored("basil").exists());
+
+        // Sent again, since the uploader never heard back.
+        assert_eq!(
+            receipt(json()).await,
+            (
+                StatusCode::OK,
+                UploadReceipt {
+                    duplicate: true,
+                    ..stored_as.clone()
+                }
+            )
+        );
+
+        // As it is, with the rest in headers.
+        let request = post_as(shared::herbs::IMAGE_UPLOAD_ENDPOINT, "ch1l1")
+            .header(header::CONTENT_TYPE, "image/jpeg")
+            .header(
+                shared::herbs::CAPTURE_TIMESTAMP_HEADER,
+                timestamp.to_rfc3339(),
+            )
+            .header(shared::herbs::CAMERA_HEADER, "chili")
+            .header(shared::herbs::READINGS_HEADER, "temperature=21.5")
+            .header(
+                shared::herbs::CONTENT_SHA256_HEADER,
+                shared::image::content_hash(&jpeg()),
+            )
+            .header(shared::herbs::IDEMPOTENCY_KEY_HEADER, "chili-1")
+            .body(Body::from(jpeg()))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::CREATED);
+
+        let meta = capture::read(&stored("chili")).await.unwrap().unwrap();
+        assert_eq!(meta.device.as_deref(), Some("chili"));
+        assert_eq!(meta.captured, timestamp);
+        assert_eq!(meta.readings["temperature"], 21.5);
+
+        // The same idempotency key for another image, from the camera of the token.
+        let green = jpeg_of(RgbImage::from_pixel(1280, 720, image::Rgb([0, 255, 0])));
+        let request = post_as(shared::herbs::IMAGE_UPLOAD_ENDPOINT, "ch1l1")
+            .header(shared::herbs::IDEMPOTENCY_KEY_HEADER, "chili-1")
+            .body(Body::from(green.clone()))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::CONFLICT);
+
+        // Another image taken at the same time.
+        let request = post(shared::herbs::IMAGE_UPLOAD_ENDPOINT)
+            .header(
+                shared::herbs::CAPTURE_TIMESTAMP_HEADER,
+                timestamp.to_rfc3339(),
+            )
+            .body(Body::from(green.clone()))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::CONFLICT);
+
+        // Not what it says it is.
+        let request = post(shared::herbs::IMAGE_UPLOAD_ENDPOINT)
+            .header(
+                shared::herbs::CONTENT_SHA256_HEADER,
+                shared::image::content_hash(&jpeg()),
+            )
+            .body(Body::from(green))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::BAD_REQUEST);
+
+        // Signed with the token instead of sending it, from the camera of the token.
+        let red = jpeg_of(RgbImage::from_pixel(1280, 720, image::Rgb([255, 0, 0])));
+        let readings = |value: &'static str| [(shared::herbs::READINGS_HEADER, value)];
+        let signed = |image: &[u8], readings: &[(&str, &str)], signature: &Signature| {
+            let mut request = Request::post(shared::herbs::IMAGE_UPLOAD_ENDPOINT);
+            for (name, value) in readings {
+                request = request.header(*name, *value);
+            }
+            for (name, value) in signature.headers() {
+                request = request.header(name, value);
+            }
+            request.body(Body::from(image.to_vec())).unwrap()
+        };
+
+        let signature = Signature::sign("ch1l1", &readings("humidity=40"), &red);
+        let request = signed(&red, &readings("humidity=40"), &signature);
+        assert_eq!(status(request).await, StatusCode::CREATED);
+
+        // Sent again by someone who saw it on its way.
+        let request = signed(&red, &readings("humidity=40"), &signature);
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+
+        // Changed on its way.
+        let signature = Signature::sign("ch1l1", &readings("humidity=40"), &red);
+        let request = signed(&red, &readings("humidity=90"), &signature);
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+
+        // Signed too long ago.
+        let signature = Signature::sign_at(
+            "ch1l1",
+            Utc::now().timestamp() - 3600,
+            "an-hour-ago".to_string(),
+            &readings("humidity=40"),
+            &red,
+        );
+        let request = signed(&red, &readings("humidity=40"), &signature);
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+
+        // Not signed with a known token, which says nothing about the cameras there are.
+        let camera = [(shared::herbs::CAMERA_HEADER, "oregano")];
+        let signature = Signature::sign("0r3g4n0", &camera, &red);
+        let request = signed(&red, &camera, &signature);
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+
+        // Without the bearer token.
+        let request = Request::post(shared::herbs::IMAGE_UPLOAD_ENDPOINT)
+            .body(Body::from(jpeg()))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+
+        let request = post_as(shared::herbs::IMAGE_UPLOAD_ENDPOINT, "basilisk")
+            .body(Body::from(jpeg()))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+
+        // For another camera than the token is for.
+        let request = post(shared::herbs::IMAGE_UPLOAD_ENDPOINT)
+            .header(shared::herbs::CAMERA_HEADER, "chili")
+            .body(Body::from(jpeg()))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+
+        // Bad headers.
+        for (name, value) in [
+            (shared::herbs::CAMERA_HEADER, "mint"),
+            (shared::herbs::CAPTURE_TIMESTAMP_HEADER, "yesterday"),
+            (shared::herbs::READINGS_HEADER, "temperature=warm"),
+        ] {
+            let request = post(shared::herbs::IMAGE_UPLOAD_ENDPOINT)
+                .header(name, value)
+                .body(Body::from(jpeg()))
+                .unwrap();
+            assert_eq!(status(request).await, StatusCode::BAD_REQUEST, "{name}");
+        }
+
+        // Too large, whether it says so or not.
+        let request = post(shared::herbs::IMAGE_UPLOAD_ENDPOINT)
+            .body(Body::from(vec![0; 100_001]))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..11 {
+                if sender.send_data(vec![0; 10_000].into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let request = post(shared::herbs::IMAGE_UPLOAD_ENDPOINT)
+            .body(body)
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let request = post(shared::herbs::IMAGE_POST_ENDPOINT)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(vec![b' '; 400_001]))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Two images taken at the same time, arriving together: one is stored whole.
+        let later = (timestamp + chrono::Duration::minutes(1)).to_rfc3339();
+        let upload = |image: Vec<u8>| {
+            post(shared::herbs::IMAGE_UPLOAD_ENDPOINT)
+                .header(shared::herbs::CAPTURE_TIMESTAMP_HEADER, &later)
+                .body(Body::from(image))
+                .unwrap()
+        };
+        let blue = jpeg_of(RgbImage::from_pixel(1280, 720, image::Rgb([0, 0, 255])));
+        let yellow = jpeg_of(RgbImage::from_pixel(1280, 720, image::Rgb([255, 255, 0])));
+        let (first, second) =
+            tokio::join!(status(upload(blue.clone())), status(upload(yellow.clone())));
+        let mut statuses = [first, second];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::CREATED, StatusCode::CONFLICT]);
+
+        let rules = AcceptRules::default();
+        let stored_later = root.join("basil").join(format!(
+            "{}.jpg",
+            shared::image::file_name_time(&(timestamp + chrono::Duration::minutes(1)))
+        ));
+        let stored_bytes = std::fs::read(stored_later).unwrap();
+        assert!(
+            stored_bytes == rules.accept(&blue).unwrap().bytes
+                || stored_bytes == rules.accept(&yellow).unwrap().bytes
+        );
+
+        // Nothing left behind from the uploads which did not make it.
+        let mut files = std::fs::read_dir(root.join("basil"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files.len(), 4, "{files:?}");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
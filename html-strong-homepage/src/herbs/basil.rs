@@ -1,44 +1,4 @@
-use axum::{response::Html, Extension};
-use html_strong::science_lab::NodeExt;
-use pathdiff::diff_paths;
-use reqwest::StatusCode;
-use timelapsifier::sort_files_by_timestamp;
-
-use crate::{base::html_doc, common::render, components::Article};
-
-pub async fn timelapse(
-    Extension(videos): Extension<timelapsifier::StateVideos>,
-) -> Result<Html<String>, (StatusCode, String)> {
-    let mut article = Article::new()
-        .h2("Timelapse")
-        .p("I have set up a time lapse for the herb growing.")
-        .p("This page should auto-update every night.")
-        .br()
-        .p(
-            "Currently, one timelapse is one full day. Eventually I will merge day-timelapses \
-             into longer periods.",
-        );
-
-    let mut videos = videos.read().await.clone();
-    sort_files_by_timestamp(&mut videos);
-
-    for video in videos.iter().rev() {
-        let rel = diff_paths(video, env!("CARGO_MANIFEST_DIR")).expect("a relative path");
-        let rel_str = rel.to_str().expect("relative path ok");
-
-        let video_name = rel.file_stem().expect("file name ok");
-        article = article.h3(video_name.to_str().expect("file name ok"));
-        article = article.video(&format!("/{rel_str}"));
-    }
-
-    render(html_doc::<&'static str>(
-        "Timelapse",
-        None,
-        None,
-        None,
-        article.class("post").into_node(),
-    ))
-}
+use crate::components::{list::List, Article};
 
 pub fn hello_world() -> Article {
     Article::new()
@@ -48,26 +8,49 @@ pub fn hello_world() -> Article {
         .url("https://www.youtube.com/embed/byoEBdVoVpM", "this")
         .p(" video.")
         .p("Notes:")
-        .list(vec![
-            "Cut just below a 'node', ie where smaller branches.. branch out",
-            "Then snip off lower leaves from cuttings so they don't get soaked in water (and die)",
-            "Then put in cup of water!",
-            "Rule of thumb: Two nodes under water (no leaves), >=4 leaves on top node",
-            "~18 days, he used grow light and heatmat. Looking not that much bigger but nice deep \
-             green",
-            "Then into small pots with compost",
-            "One pot for one cutting, even though the cups shared cuttings",
-            "Then ~22 days or so with grow lamp in compost (pete/peet? based, it holds water well)",
-            "Last 3 days liquid fertilizer, nitrogen something?",
-            "So ish 40 days.",
-            "Results were mixed, all got to be big plants",
-            "Some less deep green",
-            "Not as good soil",
-            "Too big plant for pot",
-            "Now we prune tops to encourage width instead of height",
-            "We cut right above a nice split, to encourage more of that.",
-            "So the top will be a type of 'knot'",
-        ])
+        .rich_list(
+            List::unordered()
+                .item("Cut just below a 'node', ie where smaller branches.. branch out")
+                .item(
+                    "Then snip off lower leaves from cuttings so they don't get soaked in water \
+                     (and die)",
+                )
+                .item("Then put in cup of water!")
+                .item_with(
+                    "Rule of thumb:",
+                    List::unordered()
+                        .item("Two nodes under water (no leaves)")
+                        .item(">=4 leaves on top node"),
+                )
+                .item(
+                    "~18 days, he used grow light and heatmat. Looking not that much bigger but \
+                     nice deep green",
+                )
+                .item_with(
+                    "Then into small pots with compost",
+                    List::unordered()
+                        .item("One pot for one cutting, even though the cups shared cuttings"),
+                )
+                .item(
+                    "Then ~22 days or so with grow lamp in compost (pete/peet? based, it holds \
+                     water well)",
+                )
+                .item("Last 3 days liquid fertilizer, nitrogen something?")
+                .item("So ish 40 days.")
+                .item_with(
+                    "Results were mixed, all got to be big plants",
+                    List::unordered()
+                        .item("Some less deep green")
+                        .item("Not as good soil")
+                        .item("Too big plant for pot"),
+                )
+                .item_with(
+                    "Now we prune tops to encourage width instead of height",
+                    List::unordered()
+                        .item("We cut right above a nice split, to encourage more of that.")
+                        .item("So the top will be a type of 'knot'"),
+                ),
+        )
         .h2("Starting setup")
         .p("We took four wine glasses with water and put some cuttings into them.")
         .p("The mother plant is then left like so:")
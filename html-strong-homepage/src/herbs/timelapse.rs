@@ -0,0 +1,774 @@
+//! Pages showing the timelapse videos of the herbs.
+//!
+//! The newest month is shown on `/timelapse`, older months on `/timelapse/2022/07`.
+//! Single videos are on `/timelapse/2022-07-12` for a day,
+//! `/timelapse/week-2022-07-11` for the week starting that monday,
+//! and `/timelapse/month-2022-07-01` for a whole month.
+//!
+//! Those are the pages of the default camera. Other cameras have the same pages below their id,
+//! such as `/timelapse/chili` and `/timelapse/chili/2022-07-12`.
+//! The videos of all cameras are shown next to each other on `/timelapse/side-by-side/2022-07-12`,
+//! or the newest day on `/timelapse/side-by-side`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path as FsPath,
+    time::Duration,
+};
+
+use axum::{extract::Path, response::Html, routing::get, Extension, Router};
+use chrono::{Datelike, NaiveDate, Utc, Weekday};
+use html_strong::{document_tree::Node, science_lab::NodeExt, tags::td::td, tags::th::th, tags::*};
+use pathdiff::diff_paths;
+use reqwest::StatusCode;
+use timelapsifier::{
+    capture::{self, Capture, CaptureQuery},
+    meta::TimelapseVideo,
+    Month, Timelapses,
+};
+
+use super::{Camera, Cameras};
+use crate::{
+    base::html_doc,
+    common::{no_such_page, render},
+    components::{
+        video::{VideoClip, VideoFormat},
+        Article,
+    },
+};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+const SIDE_BY_SIDE: &str = "side-by-side";
+
+/// Whether a camera with this id would be mistaken for one of the pages.
+pub(crate) fn is_page_name(name: &str) -> bool {
+    name == SIDE_BY_SIDE || Period::parse(name).is_some()
+}
+
+/// Where the pages of a camera are.
+/// The default camera keeps the ones from before there could be more than one.
+fn camera_url(cameras: &Cameras, camera: &Camera) -> String {
+    if camera.id == cameras.default_camera().id {
+        "/timelapse".to_string()
+    } else {
+        format!("/timelapse/{}", camera.id)
+    }
+}
+
+// The url a file in the static folder is served at.
+fn static_url(file: &FsPath) -> String {
+    let rel = diff_paths(file, env!("CARGO_MANIFEST_DIR")).expect("a relative path");
+    format!("/{}", rel.to_str().expect("relative path ok"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Period::Day => "",
+            Period::Week => "week-",
+            Period::Month => "month-",
+        }
+    }
+
+    /// The period and its first day from a name such as `week-2022-07-11`.
+    fn parse(name: &str) -> Option<(Self, NaiveDate)> {
+        let (period, date) = if let Some(date) = name.strip_prefix(Period::Week.prefix()) {
+            (Period::Week, date)
+        } else if let Some(date) = name.strip_prefix(Period::Month.prefix()) {
+            (Period::Month, date)
+        } else {
+            (Period::Day, name)
+        };
+
+        Some((period, NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?))
+    }
+
+    fn videos<'a>(&self, timelapses: &'a Timelapses) -> &'a [TimelapseVideo] {
+        match self {
+            Period::Day => &timelapses.days,
+            Period::Week => &timelapses.weeks,
+            Period::Month => &timelapses.months,
+        }
+    }
+
+    fn title(&self, first_day: NaiveDate) -> String {
+        match self {
+            Period::Day => first_day.format("%Y-%m-%d").to_string(),
+            Period::Week => format!("Week of {}", first_day.format("%Y-%m-%d")),
+            Period::Month => format!("All of {}", first_day.format("%B %Y")),
+        }
+    }
+
+    fn url(&self, base: &str, first_day: NaiveDate) -> String {
+        format!("{base}/{}{}", self.prefix(), first_day.format("%Y-%m-%d"))
+    }
+}
+
+fn month_url(base: &str, month: Month) -> String {
+    format!("{base}/{:04}/{:02}", month.year, month.month)
+}
+
+fn month_name(month: Month) -> String {
+    month.first_day().format("%B %Y").to_string()
+}
+
+/// The day a video is of.
+///
+/// Its timestamp is the start of the day in its name, in the server's time zone.
+/// That name is already the day in the time zone days start in,
+/// so the timestamp is read in the server's time zone, and not moved to that one again.
+fn day_of(video: &TimelapseVideo) -> NaiveDate {
+    timelapsifier::day_of(&video.video.timestamp, None)
+}
+
+/// The month a video is of, see [`day_of`].
+fn month_of(video: &TimelapseVideo) -> Month {
+    let day = day_of(video);
+    Month::new(day.year(), day.month()).expect("a day should be in a month")
+}
+
+/// Videos grouped by their month, in the order they are given within a month.
+fn by_month(videos: &[TimelapseVideo]) -> BTreeMap<Month, Vec<&TimelapseVideo>> {
+    let mut months = BTreeMap::<Month, Vec<&TimelapseVideo>>::new();
+    for video in videos {
+        months.entry(month_of(video)).or_default().push(video);
+    }
+    months
+}
+
+fn minutes_seconds(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64().round() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Which cameras took the captures, and the range of each sensor reading.
+fn capture_summary(captures: &[Capture]) -> Vec<String> {
+    let mut devices = BTreeSet::new();
+    let mut readings = BTreeMap::<&str, (f64, f64)>::new();
+
+    for meta in captures.iter().filter_map(|capture| capture.meta.as_ref()) {
+        if let Some(device) = &meta.device {
+            devices.insert(device.as_str());
+        }
+        for (name, value) in &meta.readings {
+            let (min, max) = readings.entry(name).or_insert((*value, *value));
+            *min = min.min(*value);
+            *max = max.max(*value);
+        }
+    }
+
+    let mut lines = vec![];
+    if !devices.is_empty() {
+        let devices = devices.into_iter().collect::<Vec<_>>();
+        lines.push(format!("Captured by {}.", devices.join(", ")));
+    }
+    for (name, (min, max)) in readings {
+        lines.push(format!("{name}: {min:.1} to {max:.1}."));
+    }
+
+    lines
+}
+
+fn timelapse_card(base: &str, period: Period, video: &TimelapseVideo) -> Node {
+    let day = day_of(video);
+
+    let poster = match &video.poster {
+        Some(poster) => Img::new(&static_url(poster)).class("timelapse-poster width-100"),
+        None => Div.class("timelapse-poster timelapse-poster-blank"),
+    };
+
+    let mut caption = Div
+        .class("timelapse-caption")
+        .kid(Strong.text(period.title(day)));
+    if let Some(meta) = &video.meta {
+        caption.push_kid(Span.text(format!(
+            "{} · {} frames",
+            minutes_seconds(meta.duration()),
+            meta.frame_count
+        )));
+    }
+
+    A::href(&period.url(base, day))
+        .class("timelapse-card rounded soft-shadow link-reset")
+        .kid(poster)
+        .kid(caption)
+}
+
+/// A calendar of the month, one row per week.
+/// Days with a timelapse link to it.
+fn calendar(base: &str, month: Month, videos: &[&TimelapseVideo]) -> Node {
+    let mut header = Tr.into_node();
+    for weekday in WEEKDAYS {
+        header.push_kid(th().text(weekday));
+    }
+
+    let mut table = Table
+        .class("timelapse-calendar rounded soft-shadow")
+        .kid(header);
+
+    // Pad the first week so days end up below their weekday.
+    let padding = month.first_day().weekday().num_days_from_monday() as usize;
+    let mut week = Tr.into_node();
+    for _ in 0..padding {
+        week.push_kid(td());
+    }
+
+    for day in month.days() {
+        let number = day.day().to_string();
+
+        week.push_kid(if videos.iter().any(|video| day_of(video) == day) {
+            td().class("has-video")
+                .kid(A::href(&Period::Day.url(base, day)).text(number))
+        } else {
+            td().class("no-video").text(number)
+        });
+
+        if day.weekday() == Weekday::Sun {
+            table.push_kid(std::mem::replace(&mut week, Tr.into_node()));
+        }
+    }
+
+    // The last week is only unfinished if the month ends before a sunday.
+    if month.days().last().map(|day| day.weekday()) != Some(Weekday::Sun) {
+        table.push_kid(week);
+    }
+
+    Div.class("table-scroll breather-y").kid(table)
+}
+
+fn card_grid<'a>(
+    base: &str,
+    period: Period,
+    videos: impl Iterator<Item = &'a TimelapseVideo>,
+) -> Node {
+    let mut grid = Div.class("timelapse-grid breather-y");
+    for video in videos {
+        grid.push_kid(timelapse_card(base, period, video));
+    }
+    grid
+}
+
+/// A month of timelapses: links to the neighbouring months, a calendar and the videos.
+fn month_section(base: &str, month: Month, timelapses: &Timelapses) -> Node {
+    let months = by_month(&timelapses.days);
+    let days = months.get(&month).map(Vec::as_slice).unwrap_or_default();
+
+    // Skip over months without any timelapses.
+    let previous = months.range(..month).next_back().map(|(month, _)| *month);
+    let next = months.range(month.next()..).next().map(|(month, _)| *month);
+
+    let neighbour = |month: Option<Month>, text: &str| match month {
+        Some(month) => {
+            A::href(&month_url(base, month)).text(format!("{text} {}", month_name(month)))
+        }
+        None => Span.into_node(),
+    };
+
+    let nav = Div
+        .class("timelapse-months")
+        .kid(neighbour(previous, "←"))
+        .kid(neighbour(next, "→"));
+
+    let mut section = Div
+        .class("timelapse-month")
+        .kid(H2.text(month_name(month)))
+        .kid(nav);
+
+    // Weeks belong to the month their monday is in.
+    let in_month = |video: &&TimelapseVideo| month_of(video) == month;
+    let compilations = Period::Month
+        .videos(timelapses)
+        .iter()
+        .filter(in_month)
+        .map(|video| (Period::Month, video))
+        .chain(
+            Period::Week
+                .videos(timelapses)
+                .iter()
+                .filter(in_month)
+                .map(|video| (Period::Week, video)),
+        )
+        .collect::<Vec<_>>();
+
+    if !compilations.is_empty() {
+        let mut grid = Div.class("timelapse-grid breather-y");
+        for (period, video) in compilations {
+            grid.push_kid(timelapse_card(base, period, video));
+        }
+        section.push_kid(grid);
+    }
+
+    section.push_kid(calendar(base, month, days));
+
+    if days.is_empty() {
+        section.push_kid(P.text("No timelapses this month."));
+    } else {
+        section.push_kid(card_grid(base, Period::Day, days.iter().rev().copied()));
+    }
+
+    section
+}
+
+fn render_month(
+    base: &str,
+    month: Month,
+    timelapses: &Timelapses,
+    intro: Option<Article>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let mut content = Div.class("post");
+    if let Some(intro) = intro {
+        content.push_kid(intro.into_node());
+    }
+    content.push_kid(month_section(base, month, timelapses));
+
+    render(html_doc::<&'static str>(
+        &month_name(month),
+        None,
+        None,
+        None,
+        content,
+    ))
+}
+
+/// The newest month of timelapses of a camera.
+async fn newest_month(
+    cameras: &Cameras,
+    camera: &Camera,
+    intro: Article,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let videos = camera.videos.read().await;
+
+    // Videos are sorted, so the last one is the newest.
+    let month = videos
+        .days
+        .last()
+        .map(month_of)
+        .unwrap_or_else(|| Month::of(&chrono::Local::now()));
+
+    render_month(&camera_url(cameras, camera), month, &videos, Some(intro))
+}
+
+/// The newest month of timelapses of the default camera.
+pub async fn timelapse(
+    Extension(cameras): Extension<Cameras>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let mut intro = Article::new()
+        .h2("Timelapse")
+        .p("I have set up a time lapse for the herb growing.")
+        .p("This page should auto-update every night.")
+        .br()
+        .p(
+            "Every night the day before becomes a timelapse. Once a week or a month is over, it \
+             gets a timelapse of its own too.",
+        );
+
+    let others = cameras.iter().skip(1).collect::<Vec<_>>();
+    if !others.is_empty() {
+        intro = intro.br();
+        for camera in others {
+            intro = intro.url(
+                &camera_url(&cameras, camera),
+                &format!("Timelapses from the {} camera", camera.id),
+            );
+        }
+        intro = intro.url(
+            &format!("/timelapse/{SIDE_BY_SIDE}"),
+            "All cameras side by side",
+        );
+    }
+
+    newest_month(&cameras, cameras.default_camera(), intro).await
+}
+
+async fn month_page(
+    cameras: &Cameras,
+    camera: &Camera,
+    year: &str,
+    month: &str,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let base = camera_url(cameras, camera);
+    let found = match (year.parse(), month.parse()) {
+        (Ok(year), Ok(month)) => Month::new(year, month),
+        _ => None,
+    };
+    let month = match found {
+        Some(month) => month,
+        None => return Err(no_such_page(format!("{base}/{year}/{month}")).await),
+    };
+
+    let timelapses = camera.videos.read().await;
+    render_month(&base, month, &timelapses, None)
+}
+
+/// Adds the video, or an image for gifs.
+fn with_video(article: Article, video: &TimelapseVideo) -> Article {
+    // The browser plays the first source it can, and webm is usually the smaller one.
+    let url = static_url(&video.video.file);
+    let mut sources = std::iter::once(&video.video.file)
+        .chain(&video.alternatives)
+        .map(|file| static_url(file))
+        .filter(|url| VideoFormat::from_path(url).is_ok())
+        .collect::<Vec<_>>();
+    sources.sort_by_key(|url| VideoFormat::from_path(url) != Ok(VideoFormat::Webm));
+
+    match VideoClip::new(&sources) {
+        Ok(mut clip) => {
+            if let Some(poster) = &video.poster {
+                clip = clip.poster(&static_url(poster));
+            }
+            article.video_clip(clip)
+        }
+        // Gifs are shown as images.
+        Err(_) => article.image(&url),
+    }
+}
+
+/// A single day, week or month of a camera.
+async fn video_page(
+    cameras: &Cameras,
+    camera: &Camera,
+    name: &str,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let base = camera_url(cameras, camera);
+
+    let timelapses = camera.videos.read().await;
+    let found = Period::parse(name).and_then(|(period, first_day)| {
+        let video = period
+            .videos(&timelapses)
+            .iter()
+            .find(|video| day_of(video) == first_day)?;
+        Some((period, video.clone()))
+    });
+    drop(timelapses);
+
+    let (period, video) = match found {
+        Some(found) => found,
+        None => return Err(no_such_page(format!("{base}/{name}")).await),
+    };
+
+    let title = period.title(day_of(&video));
+    let mut article = Article::new().h2(&title);
+
+    if let Some(meta) = &video.meta {
+        let format = match period {
+            Period::Day => "%H:%M",
+            Period::Week | Period::Month => "%Y-%m-%d %H:%M",
+        };
+
+        article = article.p(&format!(
+            "{} frames captured between {} and {}, played back at {} fps.",
+            meta.frame_count,
+            meta.first_capture.format(format),
+            meta.last_capture.format(format),
+            meta.fps,
+        ));
+
+        // Weeks and months would read the metadata of thousands of images.
+        if period == Period::Day {
+            let query = CaptureQuery {
+                from: Some(meta.first_capture.with_timezone(&Utc)),
+                to: Some(meta.last_capture.with_timezone(&Utc) + chrono::Duration::seconds(1)),
+                ..Default::default()
+            };
+            if let Ok(captures) = capture::query(&camera.capture_folders.0, &query).await {
+                for line in capture_summary(&captures) {
+                    article = article.p(&line);
+                }
+            }
+        }
+    }
+
+    article = with_video(article, &video);
+
+    if cameras.iter().count() > 1 {
+        article = article.url(
+            &format!("/timelapse/{SIDE_BY_SIDE}/{name}"),
+            "See all cameras side by side",
+        );
+    }
+
+    let month = month_of(&video);
+    let article = article.url(
+        &month_url(&base, month),
+        &format!("Back to {}", month_name(month)),
+    );
+
+    render(html_doc::<&'static str>(
+        &title,
+        None,
+        None,
+        None,
+        article.class("post").into_node(),
+    ))
+}
+
+/// Timelapses are routed by hand, since the pages of a camera are below its id and
+/// parameters in the same place of a route need the same name.
+pub fn router(cameras: Cameras) -> Router {
+    Router::new()
+        .route("/", get(timelapse))
+        .route("/:name", get(timelapse_name))
+        .route("/:name/:page", get(timelapse_name_page))
+        .route("/:name/:page/:month", get(timelapse_camera_month))
+        .layer(Extension(cameras))
+}
+
+/// The newest timelapses side by side, a camera's newest month, or a video of the default camera.
+async fn timelapse_name(
+    Path(name): Path<String>,
+    Extension(cameras): Extension<Cameras>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    if name == SIDE_BY_SIDE {
+        return side_by_side(&cameras, None).await;
+    }
+
+    match cameras.get(&name) {
+        Some(camera) => {
+            let intro = Article::new()
+                .h2(&format!("Timelapse from the {} camera", camera.id))
+                .url("/timelapse", "Back to the main timelapse");
+            newest_month(&cameras, camera, intro).await
+        }
+        None => video_page(&cameras, cameras.default_camera(), &name).await,
+    }
+}
+
+/// Timelapses side by side, a camera's video, or a month of the default camera.
+async fn timelapse_name_page(
+    Path((name, page)): Path<(String, String)>,
+    Extension(cameras): Extension<Cameras>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    if name == SIDE_BY_SIDE {
+        return side_by_side(&cameras, Some(&page)).await;
+    }
+
+    match cameras.get(&name) {
+        Some(camera) => video_page(&cameras, camera, &page).await,
+        None => month_page(&cameras, cameras.default_camera(), &name, &page).await,
+    }
+}
+
+/// A month of a camera.
+async fn timelapse_camera_month(
+    Path((name, year, month)): Path<(String, String, String)>,
+    Extension(cameras): Extension<Cameras>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    match cameras.get(&name) {
+        Some(camera) => month_page(&cameras, camera, &year, &month).await,
+        None => Err(no_such_page(format!("timelapse/{name}/{year}/{month}")).await),
+    }
+}
+
+/// The same day, week or month from all cameras, next to each other.
+/// The newest day any camera has if none is given.
+async fn side_by_side(
+    cameras: &Cameras,
+    name: Option<&str>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let mut newest = None;
+    for camera in cameras.iter() {
+        let day = camera.videos.read().await.days.last().map(day_of);
+        newest = newest.max(day);
+    }
+
+    let found = match name {
+        Some(name) => Period::parse(name),
+        None => newest.map(|day| (Period::Day, day)),
+    };
+    let (period, first_day) = match found {
+        Some(found) => found,
+        None => {
+            return Err(no_such_page(format!(
+                "timelapse/{SIDE_BY_SIDE}/{}",
+                name.unwrap_or_default()
+            ))
+            .await)
+        }
+    };
+
+    let title = format!("{}, side by side", period.title(first_day));
+    let mut grid = Div.class("timelapse-side-by-side breather-y");
+    for camera in cameras.iter() {
+        let timelapses = camera.videos.read().await;
+        let video = period
+            .videos(&timelapses)
+            .iter()
+            .find(|video| day_of(video) == first_day)
+            .cloned();
+        drop(timelapses);
+
+        let base = camera_url(cameras, camera);
+        let article = Article::new().h3(&camera.id);
+        let article = match video {
+            Some(video) => with_video(article, &video)
+                .url(&period.url(&base, first_day), "More about this timelapse"),
+            None => article.p("No timelapse from this camera."),
+        };
+        grid.push_kid(article.into_node());
+    }
+
+    let content = Div
+        .class("post")
+        .kid(Article::new().h2(&title).into_node())
+        .kid(grid);
+
+    render(html_doc::<&'static str>(&title, None, None, None, content))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use chrono::{Local, TimeZone};
+    use timelapsifier::capture::CaptureMeta;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::herbs::CaptureFolders;
+
+    fn camera(id: &str) -> Camera {
+        Camera {
+            id: id.to_string(),
+            upload_folder: format!("upload/{id}").into(),
+            upload_records: format!("records/{id}").into(),
+            capture_folders: CaptureFolders(vec![]),
+            videos: Default::default(),
+            trigger: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let cameras = Cameras::new(vec![camera("basil"), camera("chili")]);
+        let status = |uri: &str| {
+            let router = router(cameras.clone());
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            async move { router.oneshot(request).await.unwrap().status() }
+        };
+
+        // The default camera, then another one.
+        assert_eq!(status("/").await, StatusCode::OK);
+        assert_eq!(status("/2022/07").await, StatusCode::OK);
+        assert_eq!(status("/chili").await, StatusCode::OK);
+        assert_eq!(status("/chili/2022/07").await, StatusCode::OK);
+
+        // No such videos, months or cameras.
+        assert_eq!(status("/2022-07-12").await, StatusCode::NOT_FOUND);
+        assert_eq!(status("/chili/2022-07-12").await, StatusCode::NOT_FOUND);
+        assert_eq!(status("/2022/13").await, StatusCode::NOT_FOUND);
+        assert_eq!(status("/mint/2022/07").await, StatusCode::NOT_FOUND);
+        assert_eq!(status("/side-by-side").await, StatusCode::NOT_FOUND);
+
+        assert_eq!(status("/side-by-side/2022-07-12").await, StatusCode::OK);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_camera_hiding_page() {
+        Cameras::new(vec![camera("basil"), camera("side-by-side")]);
+    }
+
+    fn capture(device: Option<&str>, readings: &[(&str, f64)]) -> Capture {
+        let timestamp = Local.ymd(2022, 7, 12).and_hms(12, 0, 0);
+
+        Capture {
+            file: "2022-07-12_12-00-00+0200.jpg".into(),
+            timestamp,
+            meta: Some(CaptureMeta {
+                device: device.map(String::from),
+                captured: timestamp.with_timezone(&Utc),
+                received: timestamp.with_timezone(&Utc),
+                format: "jpeg".to_string(),
+                width: 1280,
+                height: 720,
+                bytes: 1000,
+                readings: readings
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), *value))
+                    .collect(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_capture_summary() {
+        assert!(capture_summary(&[]).is_empty());
+
+        let captures = [
+            capture(Some("kitchen"), &[("temperature", 21.0)]),
+            capture(None, &[]),
+            capture(
+                Some("balcony"),
+                &[("temperature", 18.25), ("humidity", 40.0)],
+            ),
+            capture(Some("kitchen"), &[("temperature", 23.5)]),
+            Capture {
+                meta: None,
+                ..capture(None, &[])
+            },
+        ];
+
+        assert_eq!(
+            capture_summary(&captures),
+            vec![
+                "Captured by balcony, kitchen.".to_string(),
+                "humidity: 40.0 to 40.0.".to_string(),
+                "temperature: 18.2 to 23.5.".to_string(),
+            ]
+        );
+    }
+
+    fn video(name: &str) -> TimelapseVideo {
+        TimelapseVideo {
+            video: timelapsifier::TimestampedFile::new_ymd(name.into()).unwrap(),
+            alternatives: vec![],
+            poster: None,
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn test_days_and_months() {
+        // The days the clocks change in Europe, and the last and first days of months and years.
+        let names = [
+            "2022-03-27",
+            "2022-07-31",
+            "2022-08-01",
+            "2022-10-30",
+            "2022-12-31",
+            "2023-01-01",
+        ];
+        let videos = names.map(|name| video(&format!("{name}.mp4")));
+
+        for (name, video) in names.iter().zip(&videos) {
+            assert_eq!(day_of(video).format("%Y-%m-%d").to_string(), *name);
+        }
+
+        let months = by_month(&videos);
+        assert_eq!(
+            months
+                .iter()
+                .map(|(month, videos)| (month_name(*month), videos.len()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("March 2022".to_string(), 1),
+                ("July 2022".to_string(), 1),
+                ("August 2022".to_string(), 1),
+                ("October 2022".to_string(), 1),
+                ("December 2022".to_string(), 1),
+                ("January 2023".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            months[&Month::new(2022, 8).unwrap()][0].video.file,
+            FsPath::new("2022-08-01.mp4")
+        );
+    }
+}
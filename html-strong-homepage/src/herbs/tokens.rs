@@ -0,0 +1,502 @@
+//! Who may upload images: each device has its own bearer token, read from a secrets file,
+//! see [`shared::herbs::upload_tokens_path`].
+//!
+//! Each line of the file is a device and its token, like `basil 5d1c…`.
+//! Lines starting with `#` are comments, and a device may have several lines while its token is changed.
+//! The file is read again whenever it changes, so removing a line revokes the token right away.
+//!
+//! Instead of sending its token, a device may sign its uploads with it, see [`shared::signing`].
+
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use axum::{
+    body::BoxBody,
+    http::{header, HeaderMap, Request, Response},
+    response::IntoResponse,
+};
+use chrono::Utc;
+use reqwest::StatusCode;
+use shared::signing::{self, NonceCache, Signature, SignatureError};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use tower_http::auth::AuthorizeRequest;
+use tracing::{error, info};
+
+#[derive(Debug, Error)]
+pub enum TokensError {
+    #[error("Could not read upload tokens")]
+    Io(#[from] std::io::Error),
+
+    #[error("Line {line} of the upload tokens is not like `basil <token>`")]
+    Line { line: usize },
+
+    #[error("Not a camera id on line {line} of the upload tokens: {device}")]
+    Device { line: usize, device: String },
+
+    #[error("The default token is only allowed in dev mode, set HERBS_UPLOAD_TOKENS or HERBS_NEW_IMAGE_PW")]
+    DefaultToken,
+}
+
+/// A token, and the camera whose images it may upload.
+#[derive(Clone, PartialEq, Eq)]
+struct DeviceToken {
+    /// `None` for the token from `HERBS_NEW_IMAGE_PW`, which may upload images of any camera.
+    device: Option<String>,
+    token: String,
+}
+
+impl Debug for DeviceToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceToken")
+            .field("device", &self.device)
+            .field("token", &"***")
+            .finish()
+    }
+}
+
+/// Tokens from a secrets file, see the [module docs](self).
+fn parse(text: &str, dev: bool) -> Result<Vec<DeviceToken>, TokensError> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, text)| {
+            let (device, token) = match text.split_whitespace().collect::<Vec<_>>()[..] {
+                [device, token] => (device, token),
+                _ => return Err(TokensError::Line { line }),
+            };
+            if !shared::herbs::is_valid_camera(device) {
+                return Err(TokensError::Device {
+                    line,
+                    device: device.to_string(),
+                });
+            }
+
+            device_token(Some(device.to_string()), token.to_string(), dev)
+        })
+        .collect()
+}
+
+fn device_token(
+    device: Option<String>,
+    token: String,
+    dev: bool,
+) -> Result<DeviceToken, TokensError> {
+    if token == shared::herbs::DEFAULT_NEW_IMAGE_TOKEN && !dev {
+        return Err(TokensError::DefaultToken);
+    }
+
+    Ok(DeviceToken { device, token })
+}
+
+/// When the secrets file was last changed, as far as can be told.
+fn version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[derive(Debug, Default)]
+struct Loaded {
+    version: Option<(SystemTime, u64)>,
+    tokens: Vec<DeviceToken>,
+}
+
+/// The tokens images may be uploaded with.
+#[derive(Debug)]
+pub struct UploadTokens {
+    /// The secrets file, `None` if there is a single token for all cameras.
+    path: Option<PathBuf>,
+    dev: bool,
+    loaded: RwLock<Loaded>,
+
+    /// Whether bearer tokens are refused, so only signed uploads are accepted.
+    signed_only: bool,
+
+    nonces: NonceCache,
+}
+
+impl UploadTokens {
+    /// Tokens from the secrets file if there is one, otherwise the `shared` token for all cameras.
+    /// Fails if the default token is used outside of dev mode, so the server does not start.
+    pub fn new(
+        path: Option<PathBuf>,
+        shared: Option<String>,
+        dev: bool,
+    ) -> Result<Self, TokensError> {
+        let loaded = match &path {
+            Some(path) => Loaded {
+                version: version(path),
+                tokens: parse(&std::fs::read_to_string(path)?, dev)?,
+            },
+            None => Loaded {
+                version: None,
+                tokens: vec![device_token(
+                    None,
+                    shared.unwrap_or_else(|| shared::herbs::DEFAULT_NEW_IMAGE_TOKEN.to_string()),
+                    dev,
+                )?],
+            },
+        };
+        info!(path = ?path, tokens = ?loaded.tokens, "Upload tokens loaded");
+
+        Ok(Self {
+            path,
+            dev,
+            loaded: RwLock::new(loaded),
+            signed_only: false,
+            nonces: NonceCache::new(signing::DEFAULT_MAX_SKEW),
+        })
+    }
+
+    /// Only accept signed uploads if `required`, signed at most `max_skew` seconds from now.
+    pub fn with_signatures(self, required: bool, max_skew: i64) -> Self {
+        Self {
+            signed_only: required,
+            nonces: NonceCache::new(max_skew),
+            ..self
+        }
+    }
+
+    /// Tokens from [`shared::herbs::upload_tokens_path`], or `HERBS_NEW_IMAGE_PW` if that is not set.
+    /// Signed uploads are configured by [`shared::herbs::require_signed_uploads`]
+    /// and [`shared::herbs::signature_max_skew`].
+    pub fn from_env() -> Result<Self, TokensError> {
+        Ok(Self::new(
+            shared::herbs::upload_tokens_path().map(PathBuf::from),
+            std::env::var("HERBS_NEW_IMAGE_PW").ok(),
+            shared::herbs::dev_mode(),
+        )?
+        .with_signatures(
+            shared::herbs::require_signed_uploads(),
+            shared::herbs::signature_max_skew(),
+        ))
+    }
+
+    /// Read the secrets file again if it has changed.
+    /// If it can't be read, no tokens are accepted until it is fixed.
+    fn reload(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let version = version(path);
+        if self
+            .loaded
+            .read()
+            .expect("tokens should not be poisoned")
+            .version
+            == version
+        {
+            return;
+        }
+
+        let tokens = match std::fs::read_to_string(path)
+            .map_err(TokensError::from)
+            .and_then(|text| parse(&text, self.dev))
+        {
+            Ok(tokens) => {
+                info!(?path, ?tokens, "Upload tokens reloaded");
+                tokens
+            }
+            Err(error) => {
+                error!(?error, ?path, "Upload tokens unusable, no uploads accepted");
+                vec![]
+            }
+        };
+
+        *self.loaded.write().expect("tokens should not be poisoned") = Loaded { version, tokens };
+    }
+
+    /// Who has the token which `matches`, `None` if nobody.
+    fn find(&self, matches: impl Fn(&str) -> bool) -> Option<Uploader> {
+        self.reload();
+
+        // Every token is compared, so the time taken doesn't tell which one was close.
+        let mut uploader = None;
+        for known in &self
+            .loaded
+            .read()
+            .expect("tokens should not be poisoned")
+            .tokens
+        {
+            if matches(&known.token) {
+                uploader = Some(Uploader(known.device.clone()));
+            }
+        }
+
+        uploader
+    }
+
+    /// Who is uploading with the given token, `None` if nobody may.
+    pub fn uploader(&self, token: &str) -> Option<Uploader> {
+        self.find(|known| bool::from(known.as_bytes().ct_eq(token.as_bytes())))
+    }
+
+    /// Who signed an upload with the given headers and body,
+    /// failing if nobody did or if the signature was used before.
+    pub fn signer(
+        &self,
+        signature: &Signature,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Uploader, SignatureError> {
+        let signed = signing::signed_headers(|name| headers.get(name)?.to_str().ok());
+        let uploader = self
+            .find(|known| signature.verify(known, &signed, body))
+            .ok_or(SignatureError::Mismatch)?;
+        self.nonces.insert(signature, Utc::now().timestamp())?;
+
+        Ok(uploader)
+    }
+}
+
+/// What a request was let through with, see [`RequireUploadToken`].
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    /// A bearer token, already checked.
+    Bearer(Uploader),
+
+    /// A signature, which can only be checked once the body has arrived.
+    Signed(Signature),
+}
+
+impl Credentials {
+    /// Who is uploading the body.
+    pub fn uploader(
+        &self,
+        tokens: &UploadTokens,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Uploader, SignatureError> {
+        match self {
+            Credentials::Bearer(uploader) => Ok(uploader.clone()),
+            Credentials::Signed(signature) => tokens.signer(signature, headers, body),
+        }
+    }
+}
+
+/// Who uploads an image, `None` for the shared token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uploader(pub Option<String>);
+
+impl Uploader {
+    /// The camera the uploader has a token for, if it is for a single one.
+    pub fn device(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// Whether images of the given camera may be uploaded.
+    pub fn may_upload(&self, camera: &str) -> bool {
+        match self.device() {
+            Some(device) => device == camera,
+            None => true,
+        }
+    }
+}
+
+/// Lets requests with an [`UploadTokens`] bearer token or a recent signature through,
+/// along with their [`Credentials`].
+#[derive(Debug, Clone)]
+pub struct RequireUploadToken(pub Arc<UploadTokens>);
+
+impl RequireUploadToken {
+    fn credentials(&self, headers: &HeaderMap) -> Option<Credentials> {
+        let value_of = |name: &str| headers.get(name)?.to_str().ok();
+
+        if let Some(signature) = Signature::from_headers(value_of).ok()? {
+            self.0
+                .nonces
+                .check_skew(&signature, Utc::now().timestamp())
+                .ok()?;
+            return Some(Credentials::Signed(signature));
+        }
+        if self.0.signed_only {
+            return None;
+        }
+
+        let token = value_of(header::AUTHORIZATION.as_str())?.strip_prefix("Bearer ")?;
+        self.0.uploader(token).map(Credentials::Bearer)
+    }
+}
+
+impl<B> AuthorizeRequest<B> for RequireUploadToken {
+    type ResponseBody = BoxBody;
+
+    fn authorize(&mut self, request: &mut Request<B>) -> Result<(), Response<Self::ResponseBody>> {
+        match self.credentials(request.headers()) {
+            Some(credentials) => {
+                request.extensions_mut().insert(credentials);
+                Ok(())
+            }
+            None => Err(StatusCode::UNAUTHORIZED.into_response()),
+        }
+    }
+}
+
+/// Lets requests with a single bearer token through, compared in constant time like upload tokens.
+#[derive(Clone)]
+pub struct RequireBearerToken(pub String);
+
+impl Debug for RequireBearerToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("RequireBearerToken").field(&"***").finish()
+    }
+}
+
+impl RequireBearerToken {
+    fn allows(&self, headers: &HeaderMap) -> bool {
+        match headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            Some(token) => bool::from(self.0.as_bytes().ct_eq(token.as_bytes())),
+            None => false,
+        }
+    }
+}
+
+impl<B> AuthorizeRequest<B> for RequireBearerToken {
+    type ResponseBody = BoxBody;
+
+    fn authorize(&mut self, request: &mut Request<B>) -> Result<(), Response<Self::ResponseBody>> {
+        if self.allows(request.headers()) {
+            Ok(())
+        } else {
+            Err(StatusCode::UNAUTHORIZED.into_response())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let tokens = parse(
+            "# Cameras\nbasil b4s1l\n\n  chili   ch1l1  \nchili new\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| (token.device.as_deref().unwrap(), token.token.as_str()))
+                .collect::<Vec<_>>(),
+            [("basil", "b4s1l"), ("chili", "ch1l1"), ("chili", "new")]
+        );
+
+        assert!(matches!(
+            parse("basil\n", false),
+            Err(TokensError::Line { line: 1 })
+        ));
+        assert!(matches!(
+            parse("basil b4s1l\nChili ch1l1\n", false),
+            Err(TokensError::Device { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse("basil basilisk\n", false),
+            Err(TokensError::DefaultToken)
+        ));
+        assert!(parse("basil basilisk\n", true).is_ok());
+    }
+
+    #[test]
+    fn test_default_token() {
+        assert!(matches!(
+            UploadTokens::new(None, None, false),
+            Err(TokensError::DefaultToken)
+        ));
+
+        let tokens = UploadTokens::new(None, None, true).unwrap();
+        assert_eq!(tokens.uploader("basilisk"), Some(Uploader(None)));
+
+        let tokens = UploadTokens::new(None, Some("s3cr3t".to_string()), false).unwrap();
+        assert_eq!(tokens.uploader("s3cr3t"), Some(Uploader(None)));
+        assert_eq!(tokens.uploader("basilisk"), None);
+    }
+
+    #[test]
+    fn test_signed_only() {
+        let tokens = UploadTokens::new(None, Some("s3cr3t".to_string()), false)
+            .unwrap()
+            .with_signatures(true, 300);
+        let require = RequireUploadToken(Arc::new(tokens));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer s3cr3t".parse().unwrap());
+        assert!(require.credentials(&headers).is_none());
+
+        let signature = Signature::sign("s3cr3t", &[], b"image");
+        for (name, value) in signature.headers() {
+            headers.insert(name, value.parse().unwrap());
+        }
+        assert!(matches!(
+            require.credentials(&headers),
+            Some(Credentials::Signed(_))
+        ));
+        assert_eq!(
+            require.0.signer(&signature, &headers, b"image"),
+            Ok(Uploader(None))
+        );
+        assert_eq!(
+            require.0.signer(&signature, &headers, b"image"),
+            Err(SignatureError::Replayed)
+        );
+    }
+
+    #[test]
+    fn test_revoke() {
+        let path =
+            std::env::temp_dir().join(format!("homepage-upload-tokens-{}", std::process::id()));
+        std::fs::write(&path, "basil b4s1l\nchili ch1l1\n").unwrap();
+
+        let tokens = UploadTokens::new(Some(path.clone()), None, false).unwrap();
+        assert_eq!(
+            tokens.uploader("b4s1l"),
+            Some(Uploader(Some("basil".to_string())))
+        );
+        assert_eq!(
+            tokens.uploader("ch1l1"),
+            Some(Uploader(Some("chili".to_string())))
+        );
+        assert_eq!(tokens.uploader("ch1l"), None);
+        assert_eq!(tokens.uploader(""), None);
+
+        std::fs::write(&path, "basil b4s1l\n").unwrap();
+        assert_eq!(tokens.uploader("ch1l1"), None);
+
+        // Unreadable tokens let nobody in.
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tokens.uploader("b4s1l"), None);
+
+        let chili = Uploader(Some("chili".to_string()));
+        assert!(chili.may_upload("chili"));
+        assert!(!chili.may_upload("basil"));
+        assert!(Uploader(None).may_upload("basil"));
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let require = RequireBearerToken("r3m4k3".to_string());
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+
+        assert!(require.allows(&headers("Bearer r3m4k3")));
+        assert!(!require.allows(&headers("Bearer r3m4k")));
+        assert!(!require.allows(&headers("Bearer r3m4k3 ")));
+        assert!(!require.allows(&headers("Basic r3m4k3")));
+        assert!(!require.allows(&HeaderMap::new()));
+        assert!(!format!("{require:?}").contains("r3m4k3"));
+    }
+}
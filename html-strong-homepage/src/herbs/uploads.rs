@@ -0,0 +1,59 @@
+//! Which images have been uploaded, so an image sent again is not stored twice.
+//!
+//! Each stored image leaves a record named after the SHA-256 of its content,
+//! and one named after the SHA-256 of its idempotency key if it was sent with one.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared::image::content_hash;
+use tokio::fs;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadRecord {
+    /// The file name the image is stored under.
+    pub stored_as: String,
+
+    /// The SHA-256 of the image as it was uploaded.
+    pub sha256: String,
+
+    pub received: DateTime<Utc>,
+}
+
+/// What an upload is recorded under.
+#[derive(Debug, Clone, Copy)]
+pub enum Key<'a> {
+    /// The SHA-256 of the content.
+    Content(&'a str),
+
+    /// An idempotency key, which may be any string.
+    Idempotency(&'a str),
+}
+
+fn path(folder: &Path, key: Key) -> PathBuf {
+    match key {
+        Key::Content(hash) => folder.join(format!("{hash}.json")),
+        Key::Idempotency(key) => folder.join(format!("key-{}.json", content_hash(key.as_bytes()))),
+    }
+}
+
+/// The record of an upload, `None` if there is none.
+pub async fn find(folder: &Path, key: Key<'_>) -> std::io::Result<Option<UploadRecord>> {
+    let path = path(folder, key);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_slice(&fs::read(path).await?)?))
+}
+
+/// Record an upload, written to a temporary file first so a record is never half written.
+pub async fn record(folder: &Path, key: Key<'_>, record: &UploadRecord) -> std::io::Result<()> {
+    fs::create_dir_all(folder).await?;
+
+    let path = path(folder, key);
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, serde_json::to_vec_pretty(record)?).await?;
+    fs::rename(&temporary, &path).await
+}
@@ -4,8 +4,17 @@ use axum::{
 };
 use chrono::{Local, TimeZone};
 use std::{net::SocketAddr, path::PathBuf, sync::Arc};
-use timelapsifier::TimestampedFile;
-use tokio::sync::RwLock;
+use timelapsifier::{
+    deflicker::DeflickerOptions,
+    encoder::FfmpegEncoder,
+    filter::FrameFilter,
+    jobs::RetryPolicy,
+    overlay::OverlayOptions,
+    profile::Profiles,
+    retention::{RetentionOptions, RetentionPolicy},
+    schedule::SystemClock,
+    CompilationOptions, Timelapses,
+};
 use tower::{limit::ConcurrencyLimitLayer, ServiceBuilder};
 use tower_http::{
     compression::CompressionLayer,
@@ -124,7 +133,7 @@ pub async fn main() {
         "Misc",
         "Small things here and there",
         "Props, testing stuff, small chill projects.",
-        Rhs::Nothing
+        Rhs::Nothing,
     )
     .post(
         "mushrooms",
@@ -320,36 +329,129 @@ pub async fn main() {
         )
         .build();
 
-    let timelapse_output_folder = PathBuf::from(format!(
-        "{}/{}",
-        env!("CARGO_MANIFEST_DIR"),
-        shared::herbs::timelapse_output_relative_folder()
-    ));
-    let timelapse_videos = timelapsifier::files_of_ext_in(&timelapse_output_folder, &["mp4"])
-        .await
-        .into_iter()
-        .filter_map(|video| TimestampedFile::new_ymd(video).ok())
-        .collect();
-
-    let timelapse_options = timelapsifier::TimelapserOptions {
-        unprocessed_images_folder: PathBuf::from(format!(
-            "{}/{}",
-            env!("CARGO_MANIFEST_DIR"),
-            shared::herbs::new_image_output_relative_folder()
-        )),
-        processed_images_folder: PathBuf::from(format!(
-            "{}/{}",
-            env!("CARGO_MANIFEST_DIR"),
-            shared::herbs::processed_image_output_relative_folder()
-        )),
-        timelapse_output_folder,
-        timelapse_videos: Arc::new(RwLock::new(timelapse_videos)),
+    let manifest_folder =
+        |relative: &str| PathBuf::from(format!("{}/{relative}", env!("CARGO_MANIFEST_DIR")));
+
+    // Encoding profiles are read from a JSON file if given, see `timelapsifier::profile`.
+    let profiles = match std::env::var("TIMELAPSE_PROFILES") {
+        Ok(path) => Profiles::load(&path)
+            .await
+            .expect("timelapse profiles should be valid"),
+        Err(_) => Profiles::default(),
     };
-    // Whenever a new video is created, its path is updated in this state.
-    // Share this state with the herbs post which displays these.
-    let videos = timelapse_options.timelapse_videos.clone();
 
-    let (herbs_new_image, herbs_new_image_router) = herbs::timelapsify_init(timelapse_options);
+    // Refuse to start with tokens anyone could know.
+    let upload_tokens = herbs::tokens::UploadTokens::from_env()
+        .expect("upload tokens should be usable, see `shared::herbs::upload_tokens_path`");
+
+    // Images from before there could be more than one camera belong to the default one.
+    herbs::move_single_camera_folders(&manifest_folder(""), shared::herbs::DEFAULT_CAMERA)
+        .expect("single camera folders should be movable");
+
+    // Each camera gets its own folders and its own timelapsifier worker.
+    let mut cameras = vec![];
+    let mut workers = vec![];
+    for camera in shared::herbs::cameras() {
+        // The camera burns in the time, so the overlay counts days and adds captions.
+        let notes = manifest_folder(&shared::herbs::timelapse_notes_relative_path(&camera));
+        let overlay = OverlayOptions {
+            planted: std::env::var("HERBS_PLANTED").ok().map(|date| {
+                date.parse()
+                    .expect("HERBS_PLANTED should be a date like 2022-07-01")
+            }),
+            watermark: std::env::var("TIMELAPSE_WATERMARK").ok(),
+            notes: notes.exists().then_some(notes),
+            ..OverlayOptions::new(manifest_folder("static/fonts/ComicMono.ttf"))
+        };
+
+        let timelapse_options = timelapsifier::TimelapserOptions {
+            unprocessed_images_folder: manifest_folder(
+                &shared::herbs::new_image_output_relative_folder(&camera),
+            ),
+            processed_images_folder: manifest_folder(
+                &shared::herbs::processed_image_output_relative_folder(&camera),
+            ),
+            timelapse_output_folder: manifest_folder(
+                &shared::herbs::timelapse_output_relative_folder(&camera),
+            ),
+            profile: profiles.day.clone(),
+            jobs_folder: manifest_folder(&shared::herbs::timelapse_jobs_relative_folder(&camera)),
+            retry: RetryPolicy::default(),
+            // Images arrive every 5 minutes, so a week of every other image is about half a minute.
+            weekly: CompilationOptions {
+                output_folder: manifest_folder(
+                    &shared::herbs::timelapse_weekly_output_relative_folder(&camera),
+                ),
+                profile: profiles.week.clone(),
+                every_nth_image: 2,
+            },
+            // Every half hour, so a month is under a minute.
+            monthly: CompilationOptions {
+                output_folder: manifest_folder(
+                    &shared::herbs::timelapse_monthly_output_relative_folder(&camera),
+                ),
+                profile: profiles.month.clone(),
+                every_nth_image: 6,
+            },
+            encoder: Arc::new(FfmpegEncoder),
+            // Night time captures have a mean brightness of about 70,
+            // and a frozen camera sends the same image over and over.
+            filter: Some(FrameFilter {
+                min_brightness: Some(80.0),
+                min_difference: Some(0.5),
+                ..Default::default()
+            }),
+            deflicker: Some(DeflickerOptions::default()),
+            // Nothing to draw unless a planting date, watermark or notes are set.
+            overlay: overlay.draws_anything().then_some(overlay),
+            // A time zone like Europe/Oslo, when the server's is not the one days should start in.
+            day_boundary: std::env::var("TIMELAPSE_TIME_ZONE").ok().map(|time_zone| {
+                time_zone
+                    .parse()
+                    .expect("TIMELAPSE_TIME_ZONE should be a time zone like Europe/Oslo")
+            }),
+            // Monthly compilations are made from the images of the month before, so they are kept
+            // for a bit longer than that. After that one an hour is enough for looking back,
+            // and compilations made already are not made again from the thinned images.
+            // Set TIMELAPSE_RETENTION_DRY_RUN to see what would be removed without removing it.
+            retention: Some(RetentionOptions {
+                policies: vec![RetentionPolicy {
+                    keep_days: Some(35),
+                    thin_to_hourly: true,
+                    max_bytes: Some(20 * 1024 * 1024 * 1024),
+                    ..RetentionPolicy::new(manifest_folder(
+                        &shared::herbs::processed_image_output_relative_folder(&camera),
+                    ))
+                }],
+                dry_run: std::env::var("TIMELAPSE_RETENTION_DRY_RUN").is_ok(),
+            }),
+            // One in the night by default, see `timelapsifier::schedule`.
+            schedule: std::env::var("TIMELAPSE_SCHEDULE")
+                .map(|schedule| {
+                    schedule.parse().expect(
+                        "TIMELAPSE_SCHEDULE should be a time like 01:00 or a cron expression",
+                    )
+                })
+                .unwrap_or_default(),
+            clock: Arc::new(SystemClock),
+            trigger: Default::default(),
+            timelapse_videos: Default::default(),
+        };
+        *timelapse_options.timelapse_videos.write().await =
+            Timelapses::load(&timelapse_options).await;
+
+        // Whenever a new video is created, its path is updated in the camera's state,
+        // which the timelapse pages display.
+        cameras.push(herbs::Camera::new(
+            &camera,
+            &timelapse_options,
+            manifest_folder(&shared::herbs::upload_records_relative_folder(&camera)),
+        ));
+        workers.push(timelapse_options);
+    }
+    let cameras = herbs::Cameras::new(cameras);
+
+    let herbs_new_image_router = herbs::timelapsify_init(workers, cameras.clone(), upload_tokens);
 
     let mut all_posts = vec![&blog, &blender, &training, &herbs]
         .iter()
@@ -374,13 +476,10 @@ pub async fn main() {
         .nest(training.url(), training.router())
         .nest(herbs.url(), herbs.router())
         // TODO: Merge these into one thing
-        .nest(herbs_new_image, herbs_new_image_router)
-        .nest(
-            "/timelapse",
-            Router::new()
-                .route("/", get(herbs::basil::timelapse))
-                .layer(Extension(videos)),
-        )
+        .merge(herbs_new_image_router)
+        .merge(herbs::timelapse_run_router(cameras.clone()))
+        .merge(herbs::captures_router(cameras.capture_folders()))
+        .nest("/timelapse", herbs::timelapse::router(cameras))
         .route(
             "/favicon.ico",
             get_service(ServeFile::new("static/favicon.ico")).handle_error(internal_server_error),
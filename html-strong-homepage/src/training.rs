@@ -1,13 +1,17 @@
-use crate::components::Article;
+use crate::components::{
+    table::{Align, DataTable},
+    Article,
+};
 
 pub fn huberman_podcast_with_andy_galpin() -> Article {
     Article::new()
-        .h2("Adaptations of Exercise, Progressive Overload")
-        .url(
+        .youtube(
             "https://www.youtube.com/embed/IAnhFUUCq6c",
-            " YouTube link.",
+            "Dr. Andy Galpin: How to Build Strength, Muscle Size & Endurance | Huberman Lab \
+             Podcast #65",
         )
-        .p(" Needs progressive overload, stress. More weight, more reps, more often etc.")
+        .h2("Adaptations of Exercise, Progressive Overload")
+        .p("Needs progressive overload, stress. More weight, more reps, more often etc.")
         .h2("Modifiable Variables, One-Rep Max, Muscle Soreness")
         .p("Exercise choice != adaptation. Sets, reps, rest time etc. affects adaptation.")
         .p(
@@ -25,7 +29,7 @@ pub fn huberman_podcast_with_andy_galpin() -> Article {
             "Larger range of motion is in general linked to more strength, hypertrophy. But of \
              course caveats, technique and safety is important.",
         )
-        .p(
+        .md(
             "SAID: Specificity adaptation imposed demands. Or something like that. Some principle \
              about needing to go for _strength_ as an imposed demand to actually gain strength. \
              So more weight instead of more reps! That means high load.",
@@ -34,6 +38,15 @@ pub fn huberman_podcast_with_andy_galpin() -> Article {
             "Warmup: 50% intensity high rep, work towards 75% intensity lower reps. Then go on to \
              e.g. 85% for work sets.",
         )
+        .table(
+            DataTable::from_csv(
+                "Set,Intensity (% of 1RM),Reps\n\
+                 Warmup start,50,High\n\
+                 Warmup end,75,Lower\n\
+                 Work sets,85,",
+            )
+            .expect("table should be valid csv"),
+        )
         .p(
             "Rest: 2-4 min for a set of 85% intensity. Can super set other exercises in the mean \
              time.",
@@ -43,7 +56,7 @@ pub fn huberman_podcast_with_andy_galpin() -> Article {
             "Hypertrophy: 48-72 hours of new protein synthesis -> recovery time. Hypertrophy \
              needs 72 hours e.g. Monday -> Thursday same muscle to get optimal rest.",
         )
-        .p("Hypertrophy: Not _intensity_ the main driver!")
+        .md("Hypertrophy: Not _intensity_ the main driver!")
         .p(
             "Hypertrophy: Total volume. 10 working sets per muscle group per week. Minimum \
              threshold. (He typically uses 10 reps in his examples here). 15, 20, 25 (trained \
@@ -51,17 +64,27 @@ pub fn huberman_podcast_with_andy_galpin() -> Article {
         )
         .h2("Hypertrophy Training, Repetition Ranges, Blood Flow Restriction")
         .p("8-30 reps per set! Literature shows. So have some fun by varying range for yourself.")
-        .p("Muscular failure! You need it. But not _extreme_ failure.")
+        .md("Muscular failure! You need it. But not _extreme_ failure.")
+        .ordered_list(vec![
+            "Feel contraction?",
+            "Feel a pump after/during?",
+            "No soreness day after?",
+        ])
         .p(
-            "1. Feel contraction? 2. Feel a pump after/during? 3. No soreness day after? If none \
-             of these, probably little muscle growth. So we want to hit all of these/at least \
-             some of these for each muscle group.",
+            "If none of these, probably little muscle growth. So we want to hit all of these/at \
+             least some of these for each muscle group.",
         )
         .h2("Tools: Protocols for Strength Training, the 3 by 5 Concept")
-        .p(
-            "3 to 5 exercises, 3 to 5 reps, 3 to 5 sets, 3 to 5 minutes rest, 3 to 5 times per \
-             week. Modify the number by how you are feeling.",
-        )
+        .table(
+            DataTable::new(&["Variable", "Amount"])
+                .align(1, Align::Center)
+                .row(vec!["Exercises".into(), "3 to 5".into()])
+                .row(vec!["Reps".into(), "3 to 5".into()])
+                .row(vec!["Sets".into(), "3 to 5".into()])
+                .row(vec!["Rest".into(), "3 to 5 minutes".into()])
+                .row(vec!["Frequency".into(), "3 to 5 times per week".into()]),
+        )
+        .p("Modify the number by how you are feeling.")
         .h2("Mind-Muscle Connection")
         .p(
             "Intention to do things is important, e.g. for strength folks it can build more \
@@ -96,23 +119,29 @@ pub fn huberman_podcast_with_andy_galpin() -> Article {
 
 pub fn eating_for_hypertrophy() -> Article {
     Article::new()
+        .youtube(
+            "https://www.youtube.com/embed/0fCtyTChU_U",
+            "Eating for Hypertrophy",
+        )
         .h2("Hypertrophy eating")
-        .url("https://www.youtube.com/embed/0fCtyTChU_U", "YouTube link.")
-        .p(" Need caloric surplus. 10-15% caloric surplus is the research.")
+        .p("Need caloric surplus. 10-15% caloric surplus is the research.")
         .p("2-4g/kg proteins. 2g considered low end for hypertrophy.")
         .p("Need high quality proteins. 700-3000mg leucine? Total proteins?")
         .p("Number of meals? Who knows. >=3 min is probably ok.")
-        .p("Probably doesn't matter that meals are _directly_ after a workout")
+        .md("Probably doesn't matter that meals are _directly_ after a workout")
 }
 
 pub fn new_science_of_muscle_hypertrophy_1() -> Article {
     Article::new()
-        .h2("How much muscle grows (9:05)")
-        .url("https://www.youtube.com/embed/MyKrc-fheBw", "YouTube link.")
-        .p(" 5-20% muscle volume mass in first 8-16 weeks")
-        .h2("Is the growth uniform across the muscle? (9:50)")
+        .youtube(
+            "https://www.youtube.com/embed/MyKrc-fheBw",
+            "New Science of Muscle Hypertrophy: Physiology",
+        )
+        .chapter("How much muscle grows", "9:05")
+        .p("5-20% muscle volume mass in first 8-16 weeks")
+        .chapter("Is the growth uniform across the muscle?", "9:50")
         .p("Probably not")
-        .h2("Show you ACTUAL elite athlete muscle fibers (15:00)")
+        .chapter("Show you ACTUAL elite athlete muscle fibers", "15:00")
         .p("Cool, actual fibers like small threads out of a red chunk of muscle/myo fiber/cell")
         .p("So fibers are made of myofibril, which again contains actin, myosin")
         .p(
@@ -132,7 +161,7 @@ pub fn new_science_of_muscle_hypertrophy_1() -> Article {
             "Maybe more realistic that we split fibers and they grow independent. Likely possible \
              but very uncommon (unless steroids)",
         )
-        .h2("Myofiber growth (47:30)")
+        .chapter("Myofiber growth", "47:30")
         .p(
             "Resistance exercise is very well documented to increase muscle cell cross sectional \
              area",
@@ -147,10 +176,13 @@ pub fn new_science_of_muscle_hypertrophy_1() -> Article {
 
 pub fn new_science_of_muscle_hypertrophy_2() -> Article {
     Article::new()
+        .youtube(
+            "https://www.youtube.com/embed/-FR5CQhsDg4",
+            "New Science of Muscle Hypertrophy: Stimuli",
+        )
         .h2("5 Steps To Activating Muscle Growth")
-        .url("https://www.youtube.com/embed/-FR5CQhsDg4", "YouTube link.")
         .p(
-            " 1: Stimulus. Of the muscle cell membrane. Mechanical tension, by lifting 'heavy' \
+            "1: Stimulus. Of the muscle cell membrane. Mechanical tension, by lifting 'heavy' \
              (30-60%+ of 1RM",
         )
         .p(
@@ -188,11 +220,11 @@ pub fn new_science_of_muscle_hypertrophy_2() -> Article {
         )
         .p("The above holds given essential amino acids. Needs the leucine threshold.")
         .h2("If You Want To Add Muscle, You Have To..")
-        .p("* Train. Stress, tension, damage. Need to signal the cells.")
-        .p(
-            "* Eat. Fuel the training (carbs) and build back up (proteins). Protein synthesis is \
+        .list(vec![
+            "Train. Stress, tension, damage. Need to signal the cells.",
+            "Eat. Fuel the training (carbs) and build back up (proteins). Protein synthesis is \
              faster with carbs (woah keto spooky).",
-        )
+        ])
         .h2("Short summary")
         .p(
             "Hit tension/stress/damage 1-3x per week per muscle group, eat >=10% Kcal surplus and \
@@ -202,9 +234,12 @@ pub fn new_science_of_muscle_hypertrophy_2() -> Article {
 
 pub fn new_science_of_muscle_hypertrophy_3() -> Article {
     Article::new()
+        .youtube(
+            "https://www.youtube.com/embed/cw6XPWaEK20",
+            "New Science of Muscle Hypertrophy: Eating and Training",
+        )
         .h2("How many calories for muscle growth")
-        .url("https://www.youtube.com/embed/cw6XPWaEK20", "YouTube link.")
-        .p(" 10-15% surplus at least")
+        .p("10-15% surplus at least")
         .p("Carbohydrates: 4-7g/kg")
         .p("Fat: 1g/kg")
         .p("Protein: 2-4g/kg. Most important. Move fat/carbs around with proteins pinned.")
@@ -219,7 +254,7 @@ pub fn new_science_of_muscle_hypertrophy_3() -> Article {
         )
         .p("5 sets a week enough for maintenance.")
         .p("Have to balance volume against fatigue.")
-        .h2("Intensity (31:30)")
+        .chapter("Intensity", "31:30")
         .p(
             "~30-85% 1RM. Kinda idiot proof as long as you get the volume. Just gotta hit \
              tension/stress/damage.",
@@ -229,7 +264,7 @@ pub fn new_science_of_muscle_hypertrophy_3() -> Article {
              at higher %s",
         )
         .p("Implicit: Gotta do to failure or close (especially at lower %s)")
-        .h2("Training to failure (35:30)")
+        .chapter("Training to failure", "35:30")
         .p("Stopping 1-3 reps short of failure seems to be OK, when volume is equated.")
         .p("Stopping short a few can be an advantage for recovery.")
         .h2("Rest intervals")
@@ -3,4 +3,29 @@ document.addEventListener("DOMContentLoaded", function() {
     document.querySelectorAll('code.rust-inline').forEach(el => {
         hljs.highlightElement(el);
     });
+
+    // YouTube embeds are plain links until clicked.
+    // Only then do we load the player (from the no-cookie domain).
+    document.querySelectorAll('a.youtube-embed').forEach(el => {
+        el.addEventListener('click', event => {
+            const url = new URL(el.href);
+            const id = url.searchParams.get('v');
+            if (!id) {
+                return;
+            }
+            event.preventDefault();
+
+            const start = parseInt(url.searchParams.get('t') || '0', 10);
+
+            const iframe = document.createElement('iframe');
+            iframe.src = `https://www.youtube-nocookie.com/embed/${id}?autoplay=1&start=${start}`;
+            const title = el.querySelector('.youtube-embed-title');
+            iframe.title = title ? title.textContent : 'YouTube video';
+            iframe.allow = 'autoplay; encrypted-media; picture-in-picture';
+            iframe.allowFullscreen = true;
+            iframe.className = 'youtube-embed-player rounded breather-y';
+
+            el.replaceWith(iframe);
+        });
+    });
 });
@@ -1,7 +1,11 @@
 use std::{collections::VecDeque, time::Duration};
 
 use reqwest::StatusCode;
-use shared::image::Image;
+use shared::{
+    accept::{AcceptRules, Format},
+    image::{Image, UploadReceipt},
+    signing::{self, Signature},
+};
 use tracing::{info, warn};
 
 const SECONDS_INTERVAL: u64 = 60 * 5;
@@ -13,25 +17,28 @@ fn impl_produce_image() -> Image {
     use chrono::Utc;
 
     let now = Utc::now();
-    let human_time = shared::image::human_time(&now);
 
-    let drawtext = format!("drawtext=text='{human_time}':fontcolor=white:fontsize=24:box=1:boxcolor=black:boxborderw=5:x=0:y=5");
+    let mut args = vec![
+        "-f".to_string(),
+        "v4l2".to_string(),
+        "-i".to_string(),
+        "/dev/video0".to_string(),
+        "-video_size".to_string(),
+        "1280x720".to_string(),
+    ];
+
+    // Without this the timelapsifier can draw the time itself, in any style.
+    #[cfg(feature = "burn-in-time")]
+    {
+        let human_time = shared::image::human_time(&now);
+        let drawtext = format!("drawtext=text='{human_time}':fontcolor=white:fontsize=24:box=1:boxcolor=black:boxborderw=5:x=0:y=5");
+        args.extend(["-vf".to_string(), drawtext]);
+    }
+
+    args.extend(["-frames", "1", "-y", "img.jpg"].map(String::from));
 
     let output = Command::new("ffmpeg")
-        .args([
-            "-f",
-            "v4l2",
-            "-i",
-            "/dev/video0",
-            "-video_size",
-            "1280x720",
-            "-vf",
-            &drawtext,
-            "-frames",
-            "1",
-            "-y",
-            "img.jpg",
-        ])
+        .args(&args)
         .output()
         .expect("Couldn't run ffmpeg properly");
 
@@ -57,6 +64,12 @@ struct Postman {
     backlog: VecDeque<Image>,
 
     client: reqwest::blocking::Client,
+
+    /// See [`shared::herbs::new_image_auth`].
+    token: String,
+
+    /// Sign uploads with the token instead of sending it, see [`shared::herbs::sign_uploads`].
+    sign: bool,
 }
 
 impl Postman {
@@ -64,18 +77,64 @@ impl Postman {
         Self {
             backlog: VecDeque::new(),
             client: reqwest::blocking::Client::new(),
+            token: shared::herbs::new_image_auth(),
+            sign: shared::herbs::sign_uploads(),
         }
     }
 
     fn send(&self, image: &Image) -> reqwest::Result<reqwest::blocking::Response> {
-        let endpoint = shared::herbs::IMAGE_POST_ENDPOINT;
+        let endpoint = shared::herbs::IMAGE_UPLOAD_ENDPOINT;
         let endpoint = format!("http://localhost:8000{endpoint}");
 
-        self.client
+        // The server finds the format from the bytes, the content type is for anyone watching.
+        let content_type = Format::guess(&image.buffer)
+            .map(|format| format.mime_type())
+            .unwrap_or("application/octet-stream");
+
+        let mut request = self
+            .client
             .post(endpoint)
-            .bearer_auth(shared::herbs::new_image_auth())
-            .json(image)
-            .send()
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .header(
+                shared::herbs::CAPTURE_TIMESTAMP_HEADER,
+                image.timestamp.to_rfc3339(),
+            )
+            // So an image sent again after a lost reply is not stored twice.
+            .header(
+                shared::herbs::CONTENT_SHA256_HEADER,
+                shared::image::content_hash(&image.buffer),
+            );
+        if let Some(device) = &image.device {
+            request = request.header(shared::herbs::CAMERA_HEADER, device);
+        }
+        if !image.readings.is_empty() {
+            request = request.header(
+                shared::herbs::READINGS_HEADER,
+                shared::image::format_readings(&image.readings),
+            );
+        }
+
+        let mut request = request.body(image.buffer.clone()).build()?;
+        if self.sign {
+            // Signed again on every try, since the server only accepts each signature once.
+            let signed = signing::signed_headers(|name| request.headers().get(name)?.to_str().ok());
+            let signature = Signature::sign(&self.token, &signed, &image.buffer);
+            for (name, value) in signature.headers() {
+                let value = value
+                    .parse()
+                    .expect("signature headers should be valid header values");
+                request.headers_mut().insert(name, value);
+            }
+        } else {
+            let value = format!("Bearer {}", self.token)
+                .parse()
+                .expect("the token should be a valid header value");
+            request
+                .headers_mut()
+                .insert(reqwest::header::AUTHORIZATION, value);
+        }
+
+        self.client.execute(request)
     }
 
     fn bad_reply(&mut self, image: Image) {
@@ -91,8 +150,25 @@ impl Postman {
         while let Some(backlog_image) = self.backlog.pop_front() {
             info!("Sending {backlog_image:?}");
             match self.send(&backlog_image) {
-                Ok(response) if response.status() == StatusCode::OK => {
-                    info!("Post image successful response: {response:?}")
+                Ok(response)
+                    if matches!(response.status(), StatusCode::OK | StatusCode::CREATED) =>
+                {
+                    // Once the server has confirmed where it's stored, the image can be dropped.
+                    match response.json::<UploadReceipt>() {
+                        Ok(UploadReceipt {
+                            stored_as,
+                            duplicate: true,
+                        }) => info!(%stored_as, "Image was already stored"),
+                        Ok(UploadReceipt { stored_as, .. }) => info!(%stored_as, "Image stored"),
+                        Err(error) => warn!(?error, "Image stored, but the receipt is unreadable"),
+                    }
+                }
+                Ok(response) if response.status() == StatusCode::CONFLICT => {
+                    // Sending it again won't help, it would keep the rest of the backlog waiting.
+                    warn!("Another image is stored in its place, dropping it: {response:?}");
+
+                    let text = response.text();
+                    info!("Text: {text:?}");
                 }
                 Ok(response) => {
                     warn!("Post image bad response: {response:?}");
@@ -125,9 +201,19 @@ fn main() {
 
     let mut postman = Postman::new();
 
+    // The same rules as the server, so images are made to fit before sending,
+    // and images the server would reject are not sent at all.
+    let rules = AcceptRules::from_env();
+
     loop {
-        let image = produce_image();
-        postman.post_image(image);
+        let image = produce_image().with_device(shared::herbs::device_id());
+        match rules.accept(&image.buffer) {
+            Ok(accepted) => postman.post_image(Image {
+                buffer: accepted.bytes,
+                ..image
+            }),
+            Err(error) => warn!(%error, "Image not accepted, not sending it"),
+        }
 
         std::thread::sleep(Duration::from_secs(SECONDS_INTERVAL));
     }
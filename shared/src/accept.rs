@@ -0,0 +1,517 @@
+//! Which uploaded images are accepted, and how they are made to fit the timelapse.
+//!
+//! The uploader checks images against the same rules before sending them,
+//! so images which would be rejected are not sent, and resizing happens before the upload.
+//! Both read the rules from the same env vars, see [`AcceptRules::from_env`].
+
+use std::{env, fmt::Display, io::Cursor, str::FromStr};
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+use thiserror::Error;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Jpeg => "jpg",
+            Format::Png => "png",
+            Format::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Format::Jpeg => "image/jpeg",
+            Format::Png => "image/png",
+            Format::Webp => "image/webp",
+        }
+    }
+
+    /// The format of the image in the bytes, if it is one of these.
+    pub fn guess(bytes: &[u8]) -> Option<Self> {
+        image::guess_format(bytes).ok().and_then(Self::of)
+    }
+
+    fn of(format: ImageFormat) -> Option<Self> {
+        match format {
+            ImageFormat::Jpeg => Some(Format::Jpeg),
+            ImageFormat::Png => Some(Format::Png),
+            ImageFormat::WebP => Some(Format::Webp),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Format::Jpeg => "jpeg",
+            Format::Png => "png",
+            Format::Webp => "webp",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jpeg" | "jpg" => Ok(Format::Jpeg),
+            "png" => Ok(Format::Png),
+            "webp" => Ok(Format::Webp),
+            _ => Err(format!("Unknown image format: {s}")),
+        }
+    }
+}
+
+/// A size images may have, either exactly like `1280x720` or any with an aspect ratio like `16:9`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    Exact { width: u32, height: u32 },
+    AspectRatio { width: u32, height: u32 },
+}
+
+impl Size {
+    /// Aspect ratios match within a percent, since sizes are rounded to whole pixels.
+    fn matches(&self, width: u32, height: u32) -> bool {
+        match *self {
+            Size::Exact {
+                width: w,
+                height: h,
+            } => (width, height) == (w, h),
+            Size::AspectRatio {
+                width: w,
+                height: h,
+            } => {
+                let (wide, high) = (width as u64 * h as u64, height as u64 * w as u64);
+                wide.abs_diff(high) * 100 <= high
+            }
+        }
+    }
+}
+
+impl Display for Size {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Size::Exact { width, height } => write!(f, "{width}x{height}"),
+            Size::AspectRatio { width, height } => write!(f, "{width}:{height}"),
+        }
+    }
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |separator| {
+            let (width, height) = s.split_once(separator)?;
+            match (width.trim().parse(), height.trim().parse()) {
+                (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width, height)),
+                _ => None,
+            }
+        };
+
+        if let Some((width, height)) = parse('x') {
+            Ok(Size::Exact { width, height })
+        } else if let Some((width, height)) = parse(':') {
+            Ok(Size::AspectRatio { width, height })
+        } else {
+            Err(format!("Not a size like 1280x720 or 16:9: {s}"))
+        }
+    }
+}
+
+/// What happens to accepted images which are not the size of the timelapse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// They are rejected after all.
+    Reject,
+
+    /// They are scaled to the size, stretched if the aspect ratio differs.
+    Resize,
+
+    /// They are scaled to cover the size, and what sticks out is cut off evenly.
+    Crop,
+}
+
+impl FromStr for Fit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Fit::Reject),
+            "resize" => Ok(Fit::Resize),
+            "crop" => Ok(Fit::Crop),
+            _ => Err(format!("Not reject, resize or crop: {s}")),
+        }
+    }
+}
+
+/// How accepted images are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    /// The bytes as they were uploaded, unless the image had to be made to fit.
+    Original,
+
+    /// Always re-encoded as a JPEG.
+    Reencode,
+}
+
+impl FromStr for Storage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "original" => Ok(Storage::Original),
+            "reencode" => Ok(Storage::Reencode),
+            _ => Err(format!("Not original or reencode: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcceptRules {
+    pub formats: Vec<Format>,
+
+    /// The sizes images may have. Any size if empty.
+    pub sizes: Vec<Size>,
+
+    /// The size of the timelapse.
+    pub width: u32,
+    pub height: u32,
+
+    pub fit: Fit,
+    pub storage: Storage,
+}
+
+impl Default for AcceptRules {
+    /// Any 16:9 image, cropped to 1280x720 and stored as a JPEG.
+    fn default() -> Self {
+        Self {
+            formats: vec![Format::Jpeg, Format::Png, Format::Webp],
+            sizes: vec![Size::AspectRatio {
+                width: 16,
+                height: 9,
+            }],
+            width: 1280,
+            height: 720,
+            fit: Fit::Crop,
+            storage: Storage::Reencode,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Rejection {
+    #[error("Unknown image format")]
+    UnknownFormat,
+
+    #[error("Image format {0} not accepted")]
+    Format(Format),
+
+    #[error("Image could not be read: {0}")]
+    Decode(String),
+
+    #[error("Image size {width}x{height} not accepted, want {accepted}")]
+    Size {
+        width: u32,
+        height: u32,
+        accepted: String,
+    },
+
+    #[error("Image could not be re-encoded: {0}")]
+    Encode(String),
+}
+
+/// An image which follows the rules, ready to be stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accepted {
+    pub bytes: Vec<u8>,
+
+    /// The format of the bytes.
+    pub format: Format,
+
+    /// The format it was uploaded in.
+    pub original_format: Format,
+
+    /// The size of the stored image.
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The env var, parsed as a comma separated list.
+/// If not set or not valid, `None` and a warning.
+fn env_list<T: FromStr<Err = String>>(var: &str) -> Option<Vec<T>> {
+    let value = env::var(var).ok()?;
+
+    match value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(items) => Some(items),
+        Err(error) => {
+            warn!(var, %error, "Ignoring invalid image accept rule");
+            None
+        }
+    }
+}
+
+/// The env var, parsed. If not set or not valid, `None` and a warning.
+fn env_one<T: FromStr<Err = String>>(var: &str) -> Option<T> {
+    let mut items = env_list(var)?;
+    if items.len() != 1 {
+        warn!(
+            var,
+            "Ignoring image accept rule, it should be a single value"
+        );
+        return None;
+    }
+
+    items.pop()
+}
+
+impl AcceptRules {
+    /// The default rules, changed by the env vars which are set:
+    ///
+    /// - `HERBS_ACCEPT_FORMATS` such as `jpeg,png,webp`
+    /// - `HERBS_ACCEPT_SIZES` such as `1280x720,1920x1080,16:9`, or empty for any size
+    /// - `HERBS_TIMELAPSE_SIZE` such as `1280x720`
+    /// - `HERBS_ACCEPT_FIT` being `reject`, `resize` or `crop`
+    /// - `HERBS_ACCEPT_STORAGE` being `original` or `reencode`
+    ///
+    /// Invalid values are warned about and ignored.
+    pub fn from_env() -> Self {
+        let mut rules = Self::default();
+
+        if let Some(formats) = env_list("HERBS_ACCEPT_FORMATS") {
+            rules.formats = formats;
+        }
+        if let Some(sizes) = env_list("HERBS_ACCEPT_SIZES") {
+            rules.sizes = sizes;
+        }
+        match env_one("HERBS_TIMELAPSE_SIZE") {
+            Some(Size::Exact { width, height }) => {
+                rules.width = width;
+                rules.height = height;
+            }
+            Some(Size::AspectRatio { .. }) => {
+                warn!("Ignoring HERBS_TIMELAPSE_SIZE, it should be a size like 1280x720")
+            }
+            None => {}
+        }
+        if let Some(fit) = env_one("HERBS_ACCEPT_FIT") {
+            rules.fit = fit;
+        }
+        if let Some(storage) = env_one("HERBS_ACCEPT_STORAGE") {
+            rules.storage = storage;
+        }
+
+        rules
+    }
+
+    fn size_rejection(&self, width: u32, height: u32) -> Rejection {
+        let accepted = match self.fit {
+            Fit::Reject => format!("{}x{}", self.width, self.height),
+            Fit::Resize | Fit::Crop => self
+                .sizes
+                .iter()
+                .map(Size::to_string)
+                .collect::<Vec<_>>()
+                .join(" or "),
+        };
+
+        Rejection::Size {
+            width,
+            height,
+            accepted,
+        }
+    }
+
+    /// Check the image against the rules, and make it fit the timelapse.
+    pub fn accept(&self, bytes: &[u8]) -> Result<Accepted, Rejection> {
+        let original_format = Format::guess(bytes).ok_or(Rejection::UnknownFormat)?;
+        if !self.formats.contains(&original_format) {
+            return Err(Rejection::Format(original_format));
+        }
+
+        let image =
+            image::load_from_memory(bytes).map_err(|error| Rejection::Decode(error.to_string()))?;
+
+        let (width, height) = image.dimensions();
+        if !self.sizes.is_empty() && !self.sizes.iter().any(|size| size.matches(width, height)) {
+            return Err(self.size_rejection(width, height));
+        }
+
+        let fits = (width, height) == (self.width, self.height);
+        let image = match self.fit {
+            _ if fits => image,
+            Fit::Reject => return Err(self.size_rejection(width, height)),
+            Fit::Resize => image.resize_exact(self.width, self.height, FilterType::Triangle),
+            Fit::Crop => image.resize_to_fill(self.width, self.height, FilterType::Triangle),
+        };
+
+        if fits && self.storage == Storage::Original {
+            return Ok(Accepted {
+                bytes: bytes.to_vec(),
+                format: original_format,
+                original_format,
+                width,
+                height,
+            });
+        }
+
+        Ok(Accepted {
+            bytes: encode_jpeg(&image)?,
+            format: Format::Jpeg,
+            original_format,
+            width: self.width,
+            height: self.height,
+        })
+    }
+}
+
+fn encode_jpeg(image: &DynamicImage) -> Result<Vec<u8>, Rejection> {
+    let mut bytes = Cursor::new(vec![]);
+    // JPEGs have no alpha channel.
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_to(&mut bytes, ImageOutputFormat::Jpeg(90))
+        .map_err(|error| Rejection::Encode(error.to_string()))?;
+
+    Ok(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use super::*;
+
+    fn encoded(width: u32, height: u32, format: ImageOutputFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "1280x720".parse(),
+            Ok(Size::Exact {
+                width: 1280,
+                height: 720
+            })
+        );
+        assert_eq!(
+            "16:9".parse(),
+            Ok(Size::AspectRatio {
+                width: 16,
+                height: 9
+            })
+        );
+        assert!("16".parse::<Size>().is_err());
+        assert!("0x720".parse::<Size>().is_err());
+
+        assert_eq!("jpg".parse(), Ok(Format::Jpeg));
+        assert!("bmp".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn test_accept() {
+        let rules = AcceptRules::default();
+
+        // Already fits, re-encoded.
+        let accepted = rules
+            .accept(&encoded(1280, 720, ImageOutputFormat::Png))
+            .unwrap();
+        assert_eq!(
+            (accepted.format, accepted.original_format),
+            (Format::Jpeg, Format::Png)
+        );
+
+        // Made to fit.
+        let accepted = rules
+            .accept(&encoded(1920, 1080, ImageOutputFormat::Jpeg(80)))
+            .unwrap();
+        assert_eq!((accepted.width, accepted.height), (1280, 720));
+        let stored = image::load_from_memory(&accepted.bytes).unwrap();
+        assert_eq!(stored.dimensions(), (1280, 720));
+
+        // Close enough to 16:9.
+        assert!(rules
+            .accept(&encoded(854, 480, ImageOutputFormat::Jpeg(80)))
+            .is_ok());
+
+        assert_eq!(
+            rules.accept(&encoded(640, 480, ImageOutputFormat::Jpeg(80))),
+            Err(Rejection::Size {
+                width: 640,
+                height: 480,
+                accepted: "16:9".to_string()
+            })
+        );
+        assert_eq!(
+            rules.accept(&encoded(1280, 720, ImageOutputFormat::Bmp)),
+            Err(Rejection::UnknownFormat)
+        );
+        assert!(matches!(
+            rules.accept(&[0xff, 0xd8, 0xff, 0x00]),
+            Err(Rejection::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn test_accept_strict() {
+        let rules = AcceptRules {
+            formats: vec![Format::Jpeg],
+            sizes: vec![],
+            fit: Fit::Reject,
+            storage: Storage::Original,
+            ..Default::default()
+        };
+
+        // Kept as is.
+        let jpeg = encoded(1280, 720, ImageOutputFormat::Jpeg(80));
+        assert_eq!(rules.accept(&jpeg).unwrap().bytes, jpeg);
+
+        assert_eq!(
+            rules.accept(&encoded(1280, 720, ImageOutputFormat::Png)),
+            Err(Rejection::Format(Format::Png))
+        );
+        assert_eq!(
+            rules.accept(&encoded(1920, 1080, ImageOutputFormat::Jpeg(80))),
+            Err(Rejection::Size {
+                width: 1920,
+                height: 1080,
+                accepted: "1280x720".to_string()
+            })
+        );
+
+        // Cropped, so stored as a JPEG after all.
+        let rules = AcceptRules {
+            fit: Fit::Crop,
+            formats: vec![Format::Png],
+            ..rules
+        };
+        let accepted = rules
+            .accept(&encoded(1000, 1000, ImageOutputFormat::Png))
+            .unwrap();
+        assert_eq!(
+            (accepted.format, accepted.width, accepted.height),
+            (Format::Jpeg, 1280, 720)
+        );
+    }
+}
@@ -3,26 +3,195 @@ use std::env;
 use tracing::warn;
 
 // TODO: Would be nice to create this from parts.
-/// Endpoint where [`Image`]s can be POSTed.
+/// Endpoint where [`Image`](crate::image::Image)s can be POSTed as JSON.
+/// Each byte of the image becomes a number, so [`IMAGE_UPLOAD_ENDPOINT`] is the better choice.
 pub const IMAGE_POST_ENDPOINT: &'static str = "/herbs-new-image";
 
-/// The bearer token needed to post an image.
-/// Stored in the env var `HERBS_NEW_IMAGE_PW`, or a fallback default if that is not set.
+/// Endpoint where images can be POSTed as they are, with the image as the body.
+/// Its format is found from the bytes, and the rest is sent in headers:
+/// [`CAPTURE_TIMESTAMP_HEADER`], [`CAMERA_HEADER`] and [`READINGS_HEADER`].
+pub const IMAGE_UPLOAD_ENDPOINT: &str = "/herbs-upload-image";
+
+/// When the image was taken, in RFC 3339. The time it arrives if not sent.
+pub const CAPTURE_TIMESTAMP_HEADER: &str = "x-capture-timestamp";
+
+/// Which camera took the image, see [`device_id`].
+pub const CAMERA_HEADER: &str = "x-camera";
+
+/// Sensor readings taken along with the image, see [`crate::image::format_readings`].
+pub const READINGS_HEADER: &str = "x-readings";
+
+/// The SHA-256 of the image as lowercase hex, see [`crate::image::content_hash`].
+/// Checked against the image if sent, and images with the same hash are only stored once.
+pub const CONTENT_SHA256_HEADER: &str = "x-content-sha256";
+
+/// Any string the uploader picks for an image, so the image is only stored once
+/// however many times it is sent. Images without one are told apart by their content.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// The largest image which can be uploaded, in bytes.
+/// Stored in the env var `HERBS_UPLOAD_MAX_BYTES`, 10 MB if not set.
+///
+/// Images POSTed as JSON may be four times as large, since each byte is written as a number.
+pub fn upload_max_bytes() -> u64 {
+    match env::var("HERBS_UPLOAD_MAX_BYTES").map(|max| max.parse()) {
+        Ok(Ok(max)) => max,
+        Ok(Err(error)) => {
+            warn!(%error, "Invalid HERBS_UPLOAD_MAX_BYTES, using the default");
+            10 * 1024 * 1024
+        }
+        Err(_) => 10 * 1024 * 1024,
+    }
+}
+
+/// The token used when none is configured. Known to everyone, so the server only accepts it in [`dev_mode`].
+pub const DEFAULT_NEW_IMAGE_TOKEN: &str = "basilisk";
+
+/// The bearer token this device posts images with.
+/// Stored in the env var `HERBS_NEW_IMAGE_PW`, or [`DEFAULT_NEW_IMAGE_TOKEN`] if that is not set.
+///
+/// A server without [`upload_tokens_path`] accepts this token from any camera.
 pub fn new_image_auth() -> String {
     env::var("HERBS_NEW_IMAGE_PW").unwrap_or_else(|_| {
         warn!("Default herbs new image bearer token used!");
-        "basilisk".to_string()
+        DEFAULT_NEW_IMAGE_TOKEN.to_string()
     })
 }
 
-pub fn new_image_output_relative_folder() -> &'static str {
-    "upload/herbs/webcam"
+/// The secrets file with the token of each device allowed to post images, like
+/// `basil <token>` on each line. A device is revoked by removing its line.
+/// Stored in the env var `HERBS_UPLOAD_TOKENS`.
+pub fn upload_tokens_path() -> Option<String> {
+    env::var("HERBS_UPLOAD_TOKENS").ok()
+}
+
+/// Whether this device signs its uploads instead of sending its token, see [`crate::signing`].
+/// Enabled by setting the env var `HERBS_SIGN_UPLOADS`.
+pub fn sign_uploads() -> bool {
+    env::var("HERBS_SIGN_UPLOADS").is_ok()
 }
 
-pub fn processed_image_output_relative_folder() -> &'static str {
-    "processed/herbs/webcam"
+/// Whether the server only accepts signed uploads, see [`crate::signing`].
+/// Enabled by setting the env var `HERBS_REQUIRE_SIGNED_UPLOADS`, otherwise bearer tokens are accepted too.
+pub fn require_signed_uploads() -> bool {
+    env::var("HERBS_REQUIRE_SIGNED_UPLOADS").is_ok()
+}
+
+/// How far from the server's clock an upload may have been signed, in seconds.
+/// Stored in the env var `HERBS_SIGNATURE_MAX_SKEW`, [`crate::signing::DEFAULT_MAX_SKEW`] if not set.
+pub fn signature_max_skew() -> i64 {
+    match env::var("HERBS_SIGNATURE_MAX_SKEW").map(|max| max.parse()) {
+        Ok(Ok(max)) => max,
+        Ok(Err(error)) => {
+            warn!(%error, "Invalid HERBS_SIGNATURE_MAX_SKEW, using the default");
+            crate::signing::DEFAULT_MAX_SKEW
+        }
+        Err(_) => crate::signing::DEFAULT_MAX_SKEW,
+    }
+}
+
+/// Whether the server runs for development, where it may use [`DEFAULT_NEW_IMAGE_TOKEN`].
+/// Enabled by setting the env var `HERBS_DEV`.
+pub fn dev_mode() -> bool {
+    env::var("HERBS_DEV").is_ok()
+}
+
+/// Which camera this is, sent along with its images so they are filed under it.
+/// Stored in the env var `HERBS_DEVICE_ID`, not sent if that is not set,
+/// in which case the images are filed under [`DEFAULT_CAMERA`].
+pub fn device_id() -> Option<String> {
+    env::var("HERBS_DEVICE_ID").ok()
+}
+
+/// Endpoint where the metadata of captured images can be queried, see `timelapsifier::capture`.
+pub const CAPTURES_ENDPOINT: &str = "/herbs-captures";
+
+/// Endpoint where a POST makes timelapses right away, instead of waiting for the schedule.
+pub const TIMELAPSE_RUN_ENDPOINT: &str = "/herbs-timelapse-run";
+
+/// The bearer token needed to make timelapses right away.
+/// Stored in the env var `HERBS_TIMELAPSE_RUN_PW`. There is no default, if not set the endpoint is not served.
+pub fn timelapse_run_auth() -> Option<String> {
+    let token = env::var("HERBS_TIMELAPSE_RUN_PW").ok();
+    if token.is_none() {
+        warn!("No timelapse run bearer token, so timelapses can't be triggered");
+    }
+
+    token
 }
 
-pub fn timelapse_output_relative_folder() -> &'static str {
-    "static/herbs/basil/timelapse/days"
-}
\ No newline at end of file
+/// The camera images are filed under when an upload does not say which camera it is from,
+/// and the only one if `HERBS_CAMERAS` is not set.
+pub const DEFAULT_CAMERA: &str = "basil";
+
+/// Whether this can be used as a camera id.
+/// Ids end up in folder names and urls, so they are lowercase letters, digits and dashes,
+/// starting with a letter.
+pub fn is_valid_camera(id: &str) -> bool {
+    id.len() <= 32
+        && id.starts_with(|c: char| c.is_ascii_lowercase())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// The cameras images are accepted from, the first one being the default.
+/// Stored comma separated in the env var `HERBS_CAMERAS`, such as `basil,chili`.
+/// Invalid ids are skipped, and if none are left only [`DEFAULT_CAMERA`] is used.
+pub fn cameras() -> Vec<String> {
+    let cameras = env::var("HERBS_CAMERAS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .filter(|id| {
+            let valid = is_valid_camera(id);
+            if !valid {
+                warn!(?id, "Skipping invalid camera id");
+            }
+            valid
+        })
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    if cameras.is_empty() {
+        vec![DEFAULT_CAMERA.to_string()]
+    } else {
+        cameras
+    }
+}
+
+pub fn new_image_output_relative_folder(camera: &str) -> String {
+    format!("upload/herbs/{camera}")
+}
+
+pub fn processed_image_output_relative_folder(camera: &str) -> String {
+    format!("processed/herbs/{camera}")
+}
+
+pub fn timelapse_output_relative_folder(camera: &str) -> String {
+    format!("static/herbs/{camera}/timelapse/days")
+}
+
+pub fn timelapse_weekly_output_relative_folder(camera: &str) -> String {
+    format!("static/herbs/{camera}/timelapse/weeks")
+}
+
+pub fn timelapse_monthly_output_relative_folder(camera: &str) -> String {
+    format!("static/herbs/{camera}/timelapse/months")
+}
+
+/// Records of how making each daily timelapse went, see `timelapsifier::jobs`.
+pub fn timelapse_jobs_relative_folder(camera: &str) -> String {
+    format!("jobs/herbs/{camera}/timelapse")
+}
+
+/// Which images have been uploaded, so they are only stored once.
+pub fn upload_records_relative_folder(camera: &str) -> String {
+    format!("jobs/herbs/{camera}/uploads")
+}
+
+/// Captions for single days of the timelapse, see `timelapsifier::overlay`.
+pub fn timelapse_notes_relative_path(camera: &str) -> String {
+    format!("notes/herbs/{camera}.txt")
+}
@@ -1,41 +1,148 @@
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug};
 
-use chrono::{DateTime, Utc, Local};
+use chrono::{DateTime, Local, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Image {
     pub buffer: Vec<u8>,
     pub timestamp: DateTime<Utc>,
-}
 
+    /// Which camera took the image. Older uploaders don't say.
+    #[serde(default)]
+    pub device: Option<String>,
+
+    /// Sensor readings taken along with the image, such as `temperature` or `humidity`.
+    #[serde(default)]
+    pub readings: BTreeMap<String, f64>,
+}
 
+/// The local time, like `2022-07-12_22-44-11`. For reading, not for parsing back,
+/// since it depends on the time zone and is ambiguous when the clocks go back.
 pub fn human_time(timestamp: &DateTime<Utc>) -> String {
     let local: DateTime<Local> = DateTime::from(*timestamp);
     local.format("%F_%H-%M-%S").to_string()
 }
 
+/// The name to store an image taken at the given time under, like `2022-07-12_22-44-11+0200`.
+///
+/// The local time along with its offset from UTC, so names are readable,
+/// and can be parsed back without knowing the time zone they were made in.
+pub fn file_name_time(timestamp: &DateTime<Utc>) -> String {
+    let local: DateTime<Local> = DateTime::from(*timestamp);
+    local.format("%F_%H-%M-%S%z").to_string()
+}
+
+/// The SHA-256 of the bytes as lowercase hex, which uploads are told apart by.
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// What the server says about an uploaded image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadReceipt {
+    /// The file name the image is stored under.
+    pub stored_as: String,
+
+    /// Whether the image had been uploaded before, in which case it was not stored again.
+    pub duplicate: bool,
+}
+
+/// Readings as a header value, like `humidity=40,temperature=21.5`.
+pub fn format_readings(readings: &BTreeMap<String, f64>) -> String {
+    readings
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Readings from a header value, see [`format_readings`].
+/// Values have to be finite, since JSON has no way to store the others.
+pub fn parse_readings(header: &str) -> Result<BTreeMap<String, f64>, String> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|reading| !reading.is_empty())
+        .map(|reading| {
+            let (name, value) = reading
+                .split_once('=')
+                .ok_or_else(|| format!("Not a reading like temperature=21.5: {reading}"))?;
+            let value: f64 = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("Not a number: {reading}"))?;
+            if !value.is_finite() {
+                return Err(format!("Not a finite number: {reading}"));
+            }
+
+            Ok((name.trim().to_string(), value))
+        })
+        .collect()
+}
+
 impl Debug for Image {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Image")
             .field("buffer", &format!("{} bytes", self.buffer.len()))
             .field("timestamp", &self.timestamp)
+            .field("device", &self.device)
+            .field("readings", &self.readings)
             .finish()
     }
 }
 
 impl Image {
     pub fn new(buffer: &[u8]) -> Self {
-        Self {
-            buffer: buffer.to_owned(),
-            timestamp: Utc::now(),
-        }
+        Self::new_with_timestamp(buffer, Utc::now())
     }
 
     pub fn new_with_timestamp(buffer: &[u8], timestamp: DateTime<Utc>) -> Self {
         Self {
             buffer: buffer.to_owned(),
             timestamp,
+            device: None,
+            readings: BTreeMap::new(),
         }
     }
+
+    pub fn with_device(self, device: Option<String>) -> Self {
+        Self { device, ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(
+            content_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_readings() {
+        let readings = [
+            ("humidity".to_string(), 40.0),
+            ("temperature".to_string(), 21.5),
+        ]
+        .into_iter()
+        .collect();
+
+        let header = format_readings(&readings);
+        assert_eq!(header, "humidity=40,temperature=21.5");
+        assert_eq!(parse_readings(&header), Ok(readings));
+        assert_eq!(parse_readings(""), Ok(BTreeMap::new()));
+        assert!(parse_readings("humidity").is_err());
+        assert!(parse_readings("temperature=nan").is_err());
+        assert!(parse_readings("humidity=inf").is_err());
+        assert!(parse_readings("humidity=40,temperature=-infinity").is_err());
+    }
 }
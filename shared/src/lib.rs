@@ -1,2 +1,4 @@
+pub mod accept;
 pub mod image;
 pub mod herbs;
+pub mod signing;
@@ -0,0 +1,304 @@
+//! Signed uploads, so an upload seen on its way can't be changed or sent again.
+//!
+//! The uploader signs with its token instead of sending it: an HMAC-SHA256 over when it signed,
+//! a random nonce, the [`SIGNED_HEADERS`] it sends and the SHA-256 of the body.
+//! The server accepts a signature made with any of its tokens if it was made recently,
+//! and remembers the nonces of those so each can only be used once, see [`NonceCache`].
+
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+
+use crate::{herbs, image::content_hash};
+
+/// The HMAC of a signed upload as lowercase hex.
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+/// When the upload was signed, in seconds since the Unix epoch.
+pub const SIGNATURE_TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+
+/// A random string, different for every upload.
+pub const SIGNATURE_NONCE_HEADER: &str = "x-signature-nonce";
+
+/// Headers which are signed along with the body, when they are sent.
+pub const SIGNED_HEADERS: [&str; 5] = [
+    herbs::CAMERA_HEADER,
+    herbs::CAPTURE_TIMESTAMP_HEADER,
+    herbs::READINGS_HEADER,
+    herbs::CONTENT_SHA256_HEADER,
+    herbs::IDEMPOTENCY_KEY_HEADER,
+];
+
+/// How far from the server's clock a signature may have been made, in seconds,
+/// if `HERBS_SIGNATURE_MAX_SKEW` is not set.
+pub const DEFAULT_MAX_SKEW: i64 = 5 * 60;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SignatureError {
+    #[error("Missing or malformed signature header {0}")]
+    Header(&'static str),
+
+    #[error("Signed {skew} seconds from now, at most {max} are allowed")]
+    Skew { skew: i64, max: i64 },
+
+    #[error("Signature not made with a known token")]
+    Mismatch,
+
+    #[error("Nonce already used")]
+    Replayed,
+}
+
+/// The values of the [`SIGNED_HEADERS`] which are sent, found with `value_of`.
+pub fn signed_headers<'a>(
+    value_of: impl Fn(&str) -> Option<&'a str>,
+) -> Vec<(&'static str, &'a str)> {
+    SIGNED_HEADERS
+        .iter()
+        .filter_map(|name| Some((*name, value_of(name)?)))
+        .collect()
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(
+    key: &str,
+    timestamp: i64,
+    nonce: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC should take keys of any length");
+    mac.update(format!("{timestamp}\n{nonce}\n").as_bytes());
+    for (name, value) in headers {
+        mac.update(format!("{name}:{value}\n").as_bytes());
+    }
+    mac.update(content_hash(body).as_bytes());
+
+    mac
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// What an upload is signed with, sent in the signature headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// See [`SIGNATURE_TIMESTAMP_HEADER`].
+    pub timestamp: i64,
+
+    /// See [`SIGNATURE_NONCE_HEADER`].
+    pub nonce: String,
+
+    /// See [`SIGNATURE_HEADER`].
+    pub mac: String,
+}
+
+impl Signature {
+    /// Sign an upload right now, with a new nonce.
+    pub fn sign(key: &str, headers: &[(&str, &str)], body: &[u8]) -> Self {
+        Self::sign_at(
+            key,
+            Utc::now().timestamp(),
+            hex(&rand::random::<[u8; 16]>()),
+            headers,
+            body,
+        )
+    }
+
+    pub fn sign_at(
+        key: &str,
+        timestamp: i64,
+        nonce: String,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Self {
+        let mac = hex(&mac(key, timestamp, &nonce, headers, body)
+            .finalize()
+            .into_bytes());
+
+        Self {
+            timestamp,
+            nonce,
+            mac,
+        }
+    }
+
+    /// Whether the upload was signed with the given key, compared in constant time.
+    pub fn verify(&self, key: &str, headers: &[(&str, &str)], body: &[u8]) -> bool {
+        match from_hex(&self.mac) {
+            Some(expected) => mac(key, self.timestamp, &self.nonce, headers, body)
+                .verify_slice(&expected)
+                .is_ok(),
+            None => false,
+        }
+    }
+
+    /// The headers to send the signature in.
+    pub fn headers(&self) -> [(&'static str, String); 3] {
+        [
+            (SIGNATURE_HEADER, self.mac.clone()),
+            (SIGNATURE_TIMESTAMP_HEADER, self.timestamp.to_string()),
+            (SIGNATURE_NONCE_HEADER, self.nonce.clone()),
+        ]
+    }
+
+    /// The signature sent in headers found with `value_of`, `None` if the upload is not signed.
+    pub fn from_headers<'a>(
+        value_of: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<Option<Self>, SignatureError> {
+        let mac = match value_of(SIGNATURE_HEADER) {
+            Some(mac) => mac.to_string(),
+            None => return Ok(None),
+        };
+        let timestamp = value_of(SIGNATURE_TIMESTAMP_HEADER)
+            .and_then(|timestamp| timestamp.parse().ok())
+            .ok_or(SignatureError::Header(SIGNATURE_TIMESTAMP_HEADER))?;
+        let nonce = value_of(SIGNATURE_NONCE_HEADER)
+            .filter(|nonce| !nonce.is_empty() && nonce.len() <= 64)
+            .ok_or(SignatureError::Header(SIGNATURE_NONCE_HEADER))?
+            .to_string();
+
+        Ok(Some(Self {
+            timestamp,
+            nonce,
+            mac,
+        }))
+    }
+}
+
+/// The nonces of recent signatures, so none can be used twice.
+///
+/// Nonces are forgotten once their signatures are too old to be accepted anyway,
+/// so the cache only holds as many as there are uploads within the clock skew window.
+#[derive(Debug)]
+pub struct NonceCache {
+    /// See [`herbs::signature_max_skew`].
+    max_skew: i64,
+
+    /// Nonces, along with when they were signed.
+    seen: Mutex<HashMap<String, i64>>,
+}
+
+impl NonceCache {
+    pub fn new(max_skew: i64) -> Self {
+        Self {
+            max_skew,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the signature was made close enough to `now`, in seconds since the Unix epoch.
+    /// Worth checking before the upload arrives.
+    pub fn check_skew(&self, signature: &Signature, now: i64) -> Result<(), SignatureError> {
+        let skew = (now - signature.timestamp).abs();
+        if skew > self.max_skew {
+            return Err(SignatureError::Skew {
+                skew,
+                max: self.max_skew,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Remember the nonce of a verified signature, failing if it was used before.
+    pub fn insert(&self, signature: &Signature, now: i64) -> Result<(), SignatureError> {
+        self.check_skew(signature, now)?;
+
+        let mut seen = self.seen.lock().expect("nonces should not be poisoned");
+        seen.retain(|_, timestamp| now - *timestamp <= self.max_skew);
+        if seen
+            .insert(signature.nonce.clone(), signature.timestamp)
+            .is_some()
+        {
+            return Err(SignatureError::Replayed);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let headers = [(herbs::CAMERA_HEADER, "basil")];
+        let signature = Signature::sign_at(
+            "b4s1l",
+            1_657_620_000,
+            "n0nc3".to_string(),
+            &headers,
+            b"image",
+        );
+
+        assert!(signature.verify("b4s1l", &headers, b"image"));
+        assert!(!signature.verify("ch1l1", &headers, b"image"));
+        assert!(!signature.verify("b4s1l", &headers, b"another image"));
+        assert!(!signature.verify("b4s1l", &[(herbs::CAMERA_HEADER, "chili")], b"image"));
+        assert!(!signature.verify("b4s1l", &[], b"image"));
+        assert!(!Signature {
+            timestamp: signature.timestamp + 1,
+            ..signature.clone()
+        }
+        .verify("b4s1l", &headers, b"image"));
+
+        let sent = signature.headers();
+        let value_of = |name: &str| {
+            sent.iter()
+                .find(|(header, _)| *header == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(Signature::from_headers(value_of), Ok(Some(signature)));
+        assert_eq!(Signature::from_headers(|_| None), Ok(None));
+        assert_eq!(
+            Signature::from_headers(|name| (name == SIGNATURE_HEADER).then_some("00")),
+            Err(SignatureError::Header(SIGNATURE_TIMESTAMP_HEADER))
+        );
+    }
+
+    #[test]
+    fn test_nonce_cache() {
+        let cache = NonceCache::new(300);
+        let signed_at = |timestamp: i64, nonce: &str| Signature {
+            timestamp,
+            nonce: nonce.to_string(),
+            mac: String::new(),
+        };
+
+        assert_eq!(cache.insert(&signed_at(1000, "a"), 1000), Ok(()));
+        assert_eq!(cache.insert(&signed_at(1000, "b"), 1100), Ok(()));
+        assert_eq!(
+            cache.insert(&signed_at(1000, "a"), 1200),
+            Err(SignatureError::Replayed)
+        );
+        assert_eq!(
+            cache.insert(&signed_at(1000, "c"), 1301),
+            Err(SignatureError::Skew {
+                skew: 301,
+                max: 300
+            })
+        );
+        assert_eq!(cache.check_skew(&signed_at(1400, "d"), 1100), Ok(()));
+
+        // Forgotten once too old to be accepted.
+        assert_eq!(cache.insert(&signed_at(1301, "e"), 1301), Ok(()));
+        assert_eq!(cache.seen.lock().unwrap().len(), 1);
+    }
+}
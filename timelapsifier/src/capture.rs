@@ -0,0 +1,246 @@
+//! What is known about each captured image.
+//!
+//! Stored as JSON next to the image, under the same name:
+//! `2022-07-12_22-44-11+0200.jpg` has its metadata in `2022-07-12_22-44-11+0200.json`.
+//! The metadata goes wherever its image goes, from the upload folder to the processed one.
+//! Images from before metadata was kept have none.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::warn;
+
+use crate::{candidates, jobs::write_atomic, sort_files_by_timestamp, TimelapsifyError};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureMeta {
+    /// Which camera took the image, if it said.
+    pub device: Option<String>,
+
+    /// When the camera took the image.
+    pub captured: DateTime<Utc>,
+
+    /// When the server got it.
+    pub received: DateTime<Utc>,
+
+    /// The format it was uploaded in, such as `png`. It may have been stored as a JPEG.
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+
+    /// Size of the upload.
+    pub bytes: u64,
+
+    /// Sensor readings taken along with the image, such as `temperature` or `humidity`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub readings: BTreeMap<String, f64>,
+}
+
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    image.with_extension("json")
+}
+
+pub async fn write(image: &Path, meta: &CaptureMeta) -> Result<(), TimelapsifyError> {
+    write_atomic(&sidecar_path(image), &serde_json::to_vec_pretty(meta)?).await
+}
+
+/// The metadata of an image, `None` if it has none.
+pub async fn read(image: &Path) -> Result<Option<CaptureMeta>, TimelapsifyError> {
+    let path = sidecar_path(image);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_slice(&fs::read(path).await?)?))
+}
+
+/// An image along with its metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub file: PathBuf,
+    pub timestamp: DateTime<Local>,
+    pub meta: Option<CaptureMeta>,
+}
+
+/// Which captures to find. Anything not set matches all of them.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CaptureQuery {
+    /// Only captures from this camera. Images without metadata don't match.
+    pub device: Option<String>,
+
+    /// Only captures from this time on.
+    pub from: Option<DateTime<Utc>>,
+
+    /// Only captures from before this time.
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl CaptureQuery {
+    fn matches_time(&self, timestamp: &DateTime<Local>) -> bool {
+        let timestamp = timestamp.with_timezone(&Utc);
+
+        !matches!(self.from, Some(from) if timestamp < from)
+            && !matches!(self.to, Some(to) if timestamp >= to)
+    }
+
+    fn matches_meta(&self, meta: Option<&CaptureMeta>) -> bool {
+        match &self.device {
+            Some(device) => meta.and_then(|meta| meta.device.as_ref()) == Some(device),
+            None => true,
+        }
+    }
+}
+
+/// Find the captures in the folders matching the query, oldest first.
+/// Folders which don't exist have no captures, and metadata which can't be read is skipped.
+pub async fn query(
+    folders: &[PathBuf],
+    query: &CaptureQuery,
+) -> Result<Vec<Capture>, TimelapsifyError> {
+    let mut images = vec![];
+    for folder in folders.iter().filter(|folder| folder.exists()) {
+        images.extend(candidates(folder).await?);
+    }
+    sort_files_by_timestamp(&mut images);
+
+    let mut captures = vec![];
+    for image in images {
+        if !query.matches_time(&image.timestamp) {
+            continue;
+        }
+
+        let meta = match read(&image.file).await {
+            Ok(meta) => meta,
+            Err(error) => {
+                warn!(?error, file = ?image.file, "Skipping unreadable capture metadata");
+                None
+            }
+        };
+        if !query.matches_meta(meta.as_ref()) {
+            continue;
+        }
+
+        captures.push(Capture {
+            file: image.file,
+            timestamp: image.timestamp,
+            meta,
+        });
+    }
+
+    Ok(captures)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn meta(device: &str, captured: DateTime<Utc>) -> CaptureMeta {
+        CaptureMeta {
+            device: Some(device.to_string()),
+            captured,
+            received: captured + chrono::Duration::seconds(2),
+            format: "jpeg".to_string(),
+            width: 1280,
+            height: 720,
+            bytes: 123_456,
+            readings: [("temperature".to_string(), 21.5)].into_iter().collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        let root =
+            std::env::temp_dir().join(format!("timelapsifier-capture-{}", std::process::id()));
+        let upload = root.join("upload");
+        let processed = root.join("processed");
+        fs::create_dir_all(&upload).await.unwrap();
+        fs::create_dir_all(&processed).await.unwrap();
+
+        let at = |hour| Utc.ymd(2022, 7, 12).and_hms(hour, 0, 0);
+        let image_at =
+            |folder: &Path, hour| folder.join(format!("{}.jpg", at(hour).format("%F_%H-%M-%S%z")));
+
+        // An old image without metadata, and two cameras.
+        for (file, meta) in [
+            (image_at(&processed, 8), None),
+            (image_at(&processed, 9), Some(meta("kitchen", at(9)))),
+            (image_at(&upload, 10), Some(meta("balcony", at(10)))),
+            (image_at(&upload, 11), Some(meta("kitchen", at(11)))),
+        ] {
+            image::RgbImage::new(8, 8).save(&file).unwrap();
+            if let Some(meta) = meta {
+                write(&file, &meta).await.unwrap();
+            }
+        }
+        // Not found in a folder which doesn't exist
+        let folders = [upload.clone(), processed.clone(), root.join("nowhere")];
+
+        assert_eq!(
+            read(&image_at(&upload, 10)).await.unwrap(),
+            Some(meta("balcony", at(10)))
+        );
+        assert_eq!(read(&image_at(&processed, 8)).await.unwrap(), None);
+
+        let all = query(&folders, &CaptureQuery::default()).await.unwrap();
+        assert_eq!(
+            all.iter()
+                .map(|capture| capture.file.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                image_at(&processed, 8),
+                image_at(&processed, 9),
+                image_at(&upload, 10),
+                image_at(&upload, 11),
+            ]
+        );
+        assert_eq!(all[0].meta, None);
+        assert_eq!(all[1].meta.as_ref().unwrap().readings["temperature"], 21.5);
+
+        let kitchen = query(
+            &folders,
+            &CaptureQuery {
+                device: Some("kitchen".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(kitchen.len(), 2);
+
+        let morning = query(
+            &folders,
+            &CaptureQuery {
+                from: Some(at(9)),
+                to: Some(at(11)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            morning
+                .iter()
+                .map(|capture| capture.timestamp)
+                .collect::<Vec<_>>(),
+            vec![at(9), at(10)]
+        );
+
+        // Moved along with the images
+        let uploaded = [image_at(&upload, 10), image_at(&upload, 11)];
+        crate::move_all(&uploaded, &processed).await.unwrap();
+        assert!(!sidecar_path(&uploaded[0]).exists());
+        assert_eq!(
+            read(&image_at(&processed, 10)).await.unwrap(),
+            Some(meta("balcony", at(10)))
+        );
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+}
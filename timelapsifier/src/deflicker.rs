@@ -0,0 +1,289 @@
+//! Evening out brightness and colour between frames.
+//!
+//! The webcam adjusts its exposure and white balance by itself,
+//! so consecutive frames flicker when played back.
+//! Each frame is histogram matched, per colour channel,
+//! against the average histogram of the frames around it.
+//! Slow changes like sunrise are kept, quick jumps are smoothed out.
+
+use std::path::{Path, PathBuf};
+
+use image::RgbImage;
+use tracing::debug;
+
+use crate::{TimelapsifyError, TimestampedFile};
+
+/// Counts of each value, per colour channel.
+type Histogram = [[u32; 256]; 3];
+
+/// A normalized histogram or cumulative distribution, per colour channel.
+type Distribution = [[f64; 256]; 3];
+
+#[derive(Debug, Clone)]
+pub struct DeflickerOptions {
+    /// How many frames before and after a frame make up its reference.
+    pub radius: usize,
+
+    /// How far to move towards the reference, from 0 (not at all) to 1 (all the way).
+    pub strength: f32,
+}
+
+impl Default for DeflickerOptions {
+    /// With a capture every five minutes, the reference spans about an hour.
+    fn default() -> Self {
+        Self {
+            radius: 6,
+            strength: 0.8,
+        }
+    }
+}
+
+fn histogram(image: &RgbImage) -> Histogram {
+    let mut histogram = [[0; 256]; 3];
+
+    for pixel in image.pixels() {
+        for (channel, value) in pixel.0.iter().enumerate() {
+            histogram[channel][*value as usize] += 1;
+        }
+    }
+
+    histogram
+}
+
+fn normalized(histogram: &Histogram) -> Distribution {
+    let mut distribution = [[0.0; 256]; 3];
+
+    for (channel, counts) in histogram.iter().enumerate() {
+        let total = counts
+            .iter()
+            .map(|&count| count as f64)
+            .sum::<f64>()
+            .max(1.0);
+        for (value, count) in counts.iter().enumerate() {
+            distribution[channel][value] = *count as f64 / total;
+        }
+    }
+
+    distribution
+}
+
+fn cumulative(distribution: &[f64; 256]) -> [f64; 256] {
+    let mut cdf = [0.0; 256];
+    let mut sum = 0.0;
+
+    for (value, share) in distribution.iter().enumerate() {
+        sum += share;
+        cdf[value] = sum;
+    }
+
+    cdf
+}
+
+/// Maps each value of the source to the value at the same place in the reference distribution.
+fn matching_lut(source: &[f64; 256], reference: &[f64; 256], strength: f32) -> [u8; 256] {
+    let source = cumulative(source);
+    let reference = cumulative(reference);
+    let strength = strength.clamp(0.0, 1.0) as f64;
+
+    let mut lut = [0; 256];
+    let mut matched = 0;
+
+    for value in 0..256 {
+        // Both are increasing, so the match never moves backwards.
+        while matched < 255 && reference[matched] + 1e-9 < source[value] {
+            matched += 1;
+        }
+
+        let moved = value as f64 + strength * (matched as f64 - value as f64);
+        lut[value] = moved.round().clamp(0.0, 255.0) as u8;
+    }
+
+    lut
+}
+
+/// The average of the distributions within the radius of each frame.
+fn rolling_references(distributions: &[Distribution], radius: usize) -> Vec<Distribution> {
+    (0..distributions.len())
+        .map(|index| {
+            let window = &distributions
+                [index.saturating_sub(radius)..(index + radius + 1).min(distributions.len())];
+
+            let mut reference = [[0.0; 256]; 3];
+            for distribution in window {
+                for channel in 0..3 {
+                    for value in 0..256 {
+                        reference[channel][value] +=
+                            distribution[channel][value] / window.len() as f64;
+                    }
+                }
+            }
+
+            reference
+        })
+        .collect()
+}
+
+/// Match the image to the reference distribution.
+pub fn normalize(image: &mut RgbImage, reference: &Distribution, strength: f32) {
+    let source = normalized(&histogram(image));
+    let luts =
+        [0, 1, 2].map(|channel| matching_lut(&source[channel], &reference[channel], strength));
+
+    for pixel in image.pixels_mut() {
+        for (channel, value) in pixel.0.iter_mut().enumerate() {
+            *value = luts[channel][*value as usize];
+        }
+    }
+}
+
+/// Deflicker the frames, saving them in the given folder under the same names.
+/// Blocking, since every image is decoded twice: once to find the references, once to fix it.
+pub fn deflicker(
+    options: &DeflickerOptions,
+    images: &[TimestampedFile],
+    folder: &Path,
+) -> Result<Vec<TimestampedFile>, TimelapsifyError> {
+    std::fs::create_dir_all(folder)?;
+
+    let distributions = images
+        .iter()
+        .map(|image| {
+            Ok(normalized(&histogram(
+                &image::open(&image.file)?.into_rgb8(),
+            )))
+        })
+        .collect::<Result<Vec<_>, TimelapsifyError>>()?;
+
+    let references = rolling_references(&distributions, options.radius);
+
+    images
+        .iter()
+        .zip(references)
+        .map(|(image, reference)| {
+            let file_name = image
+                .file
+                .file_name()
+                .ok_or_else(|| TimelapsifyError::file_option_issue(&image.file))?;
+            // Frames are JPEGs, whatever the image was uploaded as.
+            let output = folder.join(file_name).with_extension("jpg");
+            debug!(?output, "Deflickering");
+
+            let mut frame = image::open(&image.file)?.into_rgb8();
+            normalize(&mut frame, &reference, options.strength);
+            frame.save(&output)?;
+
+            Ok(TimestampedFile {
+                file: output,
+                timestamp: image.timestamp,
+            })
+        })
+        .collect()
+}
+
+/// Deflicker the frames without blocking the async runtime.
+pub async fn deflicker_frames(
+    options: DeflickerOptions,
+    images: Vec<TimestampedFile>,
+    folder: PathBuf,
+) -> Result<Vec<TimestampedFile>, TimelapsifyError> {
+    tokio::task::spawn_blocking(move || deflicker(&options, &images, &folder)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    // A gradient, with each channel scaled to mimic exposure and white balance.
+    fn frame(scale: [f32; 3]) -> RgbImage {
+        RgbImage::from_fn(64, 32, |x, y| {
+            let value = (x * 2 + y) as f32;
+            Rgb([0, 1, 2].map(|channel| (value * scale[channel]).min(255.0) as u8))
+        })
+    }
+
+    fn means(image: &RgbImage) -> [f32; 3] {
+        let count = image.pixels().len() as f32;
+        [0, 1, 2].map(|channel| {
+            image
+                .pixels()
+                .map(|pixel| pixel.0[channel] as f32)
+                .sum::<f32>()
+                / count
+        })
+    }
+
+    #[test]
+    fn test_matching_lut() {
+        let distribution = normalized(&histogram(&frame([1.0; 3])));
+
+        // Matching against itself changes nothing
+        let lut = matching_lut(&distribution[0], &distribution[0], 1.0);
+        assert!((0..158).all(|value| lut[value] == value as u8));
+
+        // A darker frame is brightened up, but only partly with less strength
+        let darker = normalized(&histogram(&frame([0.5; 3])));
+        let full = matching_lut(&darker[0], &distribution[0], 1.0);
+        let half = matching_lut(&darker[0], &distribution[0], 0.5);
+        // Halving rounds down, so 40 came from 80 or 81.
+        assert!((80..=81).contains(&full[40]));
+        assert!((60..=61).contains(&half[40]));
+    }
+
+    #[test]
+    fn test_deflicker() {
+        let root =
+            std::env::temp_dir().join(format!("timelapsifier-deflicker-{}", std::process::id()));
+        let working = root.join("working");
+        std::fs::create_dir_all(&root).unwrap();
+
+        // Exposure jumps around, and one frame has a blue cast.
+        let scales = [
+            [1.0, 1.0, 1.0],
+            [0.7, 0.7, 0.7],
+            [1.0, 1.0, 1.0],
+            [1.3, 1.3, 1.3],
+            [1.0, 1.0, 1.4],
+            [1.0, 1.0, 1.0],
+        ];
+        let images = scales
+            .iter()
+            .enumerate()
+            .map(|(index, scale)| {
+                let file = root.join(format!("2022-07-12_10-{:02}-00.jpg", index * 5));
+                frame(*scale).save(&file).unwrap();
+                TimestampedFile::new_ymd_hms(file).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let original = std::fs::read(&images[1].file).unwrap();
+
+        let options = DeflickerOptions {
+            radius: 2,
+            strength: 1.0,
+        };
+        let frames = deflicker(&options, &images, &working).unwrap();
+        assert_eq!(frames.len(), images.len());
+
+        let spread = |images: &[TimestampedFile], channel: usize| {
+            let values = images
+                .iter()
+                .map(|image| means(&image::open(&image.file).unwrap().into_rgb8())[channel])
+                .collect::<Vec<_>>();
+            values.iter().cloned().fold(f32::MIN, f32::max)
+                - values.iter().cloned().fold(f32::MAX, f32::min)
+        };
+
+        // Brightness and colour jump far less between the frames.
+        assert!(spread(&frames, 0) < spread(&images, 0) / 2.0);
+        assert!(spread(&frames, 2) < spread(&images, 2) / 2.0);
+
+        // The originals are left alone, with the same names and times.
+        assert_eq!(std::fs::read(&images[1].file).unwrap(), original);
+        assert!(frames.iter().all(|frame| frame.file.starts_with(&working)));
+        assert_eq!(frames[1].file.file_name(), images[1].file.file_name());
+        assert_eq!(frames[1].timestamp, images[1].timestamp);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+//! Turning a sequence of images into a video.
+//!
+//! Encoding is CPU heavy and blocking, so encoders are plain synchronous code.
+//! The worker runs them via [`encode`], which moves them off the async runtime.
+
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
+
+use image::{codecs::gif, Delay, Frame};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    profile::{Codec, EncodingProfile},
+    TimelapsifyError, TimestampedFile,
+};
+
+#[derive(Debug, Error)]
+pub enum EncodeError {
+    #[error("No images to encode")]
+    NoImages,
+
+    #[error("IO: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("ffmpeg failed ({status}): {stderr}")]
+    Ffmpeg { status: String, stderr: String },
+
+    #[error("Image: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+/// Something which can make a video out of images.
+///
+/// Encoders follow the given profile as well as they can,
+/// but may ignore parts of it which do not apply to them.
+pub trait Encoder: Debug + Send + Sync {
+    /// The file extension of the videos made with the profile, such as `mp4`.
+    fn extension(&self, profile: &EncodingProfile) -> &'static str;
+
+    /// Make a video out of the given images, in order, shown at the given frames per second.
+    fn encode(
+        &self,
+        images: &[TimestampedFile],
+        output: &Path,
+        profile: &EncodingProfile,
+        fps: u32,
+    ) -> Result<(), EncodeError>;
+}
+
+/// Run an encoder without blocking the async runtime.
+pub async fn encode(
+    encoder: Arc<dyn Encoder>,
+    images: Vec<TimestampedFile>,
+    output: PathBuf,
+    profile: EncodingProfile,
+    fps: u32,
+) -> Result<(), TimelapsifyError> {
+    tokio::task::spawn_blocking(move || encoder.encode(&images, &output, &profile, fps)).await??;
+
+    Ok(())
+}
+
+/// Makes videos by running `ffmpeg`, which must be on the `PATH`.
+#[derive(Debug, Clone, Default)]
+pub struct FfmpegEncoder;
+
+impl FfmpegEncoder {
+    fn codec_args(profile: &EncodingProfile) -> Vec<String> {
+        let mut args = match profile.codec {
+            Codec::H264 => vec!["-vcodec", "libx264"],
+            // Constant quality for these needs the bitrate to be unbounded.
+            Codec::Vp9 => vec!["-vcodec", "libvpx-vp9", "-b:v", "0"],
+            // The default speed of libaom is far too slow for this.
+            Codec::Av1 => vec!["-vcodec", "libaom-av1", "-b:v", "0", "-cpu-used", "6"],
+        }
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+        args.extend(["-crf".to_string(), profile.crf.to_string()]);
+
+        // Height -2 keeps the aspect ratio, rounded to what yuv420p needs.
+        if let Some(width) = profile.width {
+            args.extend(["-vf".to_string(), format!("scale={width}:-2")]);
+        }
+
+        args.extend(["-pix_fmt".to_string(), "yuv420p".to_string()]);
+        args
+    }
+}
+
+impl Encoder for FfmpegEncoder {
+    fn extension(&self, profile: &EncodingProfile) -> &'static str {
+        profile.codec.extension()
+    }
+
+    fn encode(
+        &self,
+        images: &[TimestampedFile],
+        output: &Path,
+        profile: &EncodingProfile,
+        fps: u32,
+    ) -> Result<(), EncodeError> {
+        if images.is_empty() {
+            return Err(EncodeError::NoImages);
+        }
+
+        // ffmpeg reads the images from a list next to the output,
+        // so several encodes may run at the same time.
+        let list = output.with_extension("images.txt");
+        {
+            let mut f = BufWriter::new(File::create(&list)?);
+            for image in images {
+                writeln!(f, "file '{}'", image.file.display())?;
+            }
+            f.flush()?;
+        }
+
+        let fps = fps.to_string();
+        let result = Command::new("ffmpeg")
+            .args([
+                "-y", "-f", "image2", "-r", &fps, "-f", "concat", "-y", "-safe", "0", "-i",
+            ])
+            .arg(&list)
+            .args(Self::codec_args(profile))
+            .arg(output)
+            .output();
+
+        std::fs::remove_file(&list)?;
+        let result = result?;
+
+        if !result.status.success() {
+            return Err(EncodeError::Ffmpeg {
+                status: result.status.to_string(),
+                stderr: String::from_utf8_lossy(&result.stderr).into_owned(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Makes animated gifs in-process, no external tools needed.
+///
+/// Gifs are large and slow to make compared to mp4s,
+/// so frames are scaled down to fit within `max_size`,
+/// or the width of the profile if that is smaller.
+/// The codec and quality of the profile are ignored.
+#[derive(Debug, Clone)]
+pub struct GifEncoder {
+    pub max_size: (u32, u32),
+}
+
+impl Default for GifEncoder {
+    fn default() -> Self {
+        Self {
+            max_size: (640, 360),
+        }
+    }
+}
+
+impl Encoder for GifEncoder {
+    fn extension(&self, _profile: &EncodingProfile) -> &'static str {
+        "gif"
+    }
+
+    fn encode(
+        &self,
+        images: &[TimestampedFile],
+        output: &Path,
+        profile: &EncodingProfile,
+        fps: u32,
+    ) -> Result<(), EncodeError> {
+        if images.is_empty() {
+            return Err(EncodeError::NoImages);
+        }
+
+        let (max_width, height) = self.max_size;
+        let width = profile
+            .width
+            .map_or(max_width, |width| width.min(max_width));
+        let delay = Delay::from_numer_denom_ms(1000, fps.max(1));
+
+        let mut encoder =
+            gif::GifEncoder::new_with_speed(BufWriter::new(File::create(output)?), 10);
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        for image in images {
+            debug!(?image.file, "Adding frame");
+            let frame = image::open(&image.file)?
+                .thumbnail(width, height)
+                .into_rgba8();
+            encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Records what it is asked to encode, and writes an empty video.
+    #[derive(Debug, Default)]
+    pub(crate) struct MockEncoder {
+        pub(crate) encoded: Mutex<Vec<(PathBuf, usize, u32)>>,
+
+        /// File names of videos which fail to encode.
+        pub(crate) failing: Mutex<Vec<String>>,
+    }
+
+    impl Encoder for MockEncoder {
+        fn extension(&self, profile: &EncodingProfile) -> &'static str {
+            profile.codec.extension()
+        }
+
+        fn encode(
+            &self,
+            images: &[TimestampedFile],
+            output: &Path,
+            _profile: &EncodingProfile,
+            fps: u32,
+        ) -> Result<(), EncodeError> {
+            if images.is_empty() {
+                return Err(EncodeError::NoImages);
+            }
+
+            let file_name = output.file_name().unwrap_or_default().to_string_lossy();
+            if self
+                .failing
+                .lock()
+                .unwrap()
+                .contains(&file_name.to_string())
+            {
+                return Err(EncodeError::Ffmpeg {
+                    status: "mock".to_string(),
+                    stderr: format!("{file_name} is set to fail"),
+                });
+            }
+
+            File::create(output)?;
+            self.encoded
+                .lock()
+                .unwrap()
+                .push((output.to_owned(), images.len(), fps));
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gif_encoder() {
+        let out = std::env::temp_dir().join(format!("timelapsifier-gif-{}", std::process::id()));
+        std::fs::create_dir_all(&out).unwrap();
+
+        let images = ["2022-07-12_22-44-11.jpg", "2022-07-12_22-49-15.jpg"].map(|name| {
+            let file = out.join(name);
+            image::RgbImage::new(1280, 720).save(&file).unwrap();
+            TimestampedFile::new_ymd_hms(file).unwrap()
+        });
+
+        let profile = EncodingProfile::default();
+        let gif = out.join("2022-07-12.gif");
+        GifEncoder::default()
+            .encode(&images, &gif, &profile, 60)
+            .unwrap();
+        assert_eq!(image::image_dimensions(&gif).unwrap(), (640, 360));
+
+        let smaller = EncodingProfile {
+            width: Some(320),
+            ..Default::default()
+        };
+        GifEncoder::default()
+            .encode(&images, &gif, &smaller, 60)
+            .unwrap();
+        assert_eq!(image::image_dimensions(&gif).unwrap(), (320, 180));
+
+        assert!(matches!(
+            GifEncoder::default().encode(&[], &gif, &profile, 60),
+            Err(EncodeError::NoImages)
+        ));
+
+        std::fs::remove_dir_all(&out).unwrap();
+    }
+
+    #[test]
+    fn test_ffmpeg_args() {
+        let profile = EncodingProfile {
+            codec: Codec::Vp9,
+            crf: 31,
+            width: Some(1280),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            FfmpegEncoder::codec_args(&profile).join(" "),
+            "-vcodec libvpx-vp9 -b:v 0 -crf 31 -vf scale=1280:-2 -pix_fmt yuv420p"
+        );
+        assert_eq!(
+            FfmpegEncoder::codec_args(&EncodingProfile::default()).join(" "),
+            "-vcodec libx264 -crf 25 -pix_fmt yuv420p"
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Dropping frames which would make a timelapse worse.
+//!
+//! The camera takes pictures day and night, so without filtering
+//! videos have long dark stretches, and nothing happens in them.
+//! Each image is scored, and dropped if a score is below its threshold.
+
+use std::fmt;
+
+use image::{imageops::FilterType, GrayImage};
+use imageproc::filter::filter3x3;
+use tracing::{debug, info};
+
+use crate::{TimelapsifyError, TimestampedFile};
+
+/// How an image looks, as far as the filter cares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameScore {
+    /// Mean brightness, from 0 (black) to 255 (white).
+    pub brightness: f32,
+
+    /// Variance of the Laplacian. Blurry or featureless images score low.
+    pub sharpness: f32,
+
+    /// Mean absolute difference from the previous frame kept, from 0 to 255.
+    /// `None` for the first frame.
+    pub difference: Option<f32>,
+}
+
+/// Why a frame was dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    TooDark { brightness: f32 },
+    TooBlurry { sharpness: f32 },
+    TooSimilar { difference: f32 },
+    Unreadable(String),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::TooDark { brightness } => write!(f, "too dark ({brightness:.1})"),
+            Rejection::TooBlurry { sharpness } => write!(f, "too blurry ({sharpness:.1})"),
+            Rejection::TooSimilar { difference } => {
+                write!(f, "too similar to the previous frame ({difference:.1})")
+            }
+            Rejection::Unreadable(error) => write!(f, "unreadable ({error})"),
+        }
+    }
+}
+
+/// Thresholds for dropping frames. A threshold which is not set is not checked.
+#[derive(Debug, Clone)]
+pub struct FrameFilter {
+    /// Drop frames darker than this mean brightness.
+    pub min_brightness: Option<f32>,
+
+    /// Drop frames less sharp than this.
+    pub min_sharpness: Option<f32>,
+
+    /// Drop frames which differ less than this from the previous frame kept.
+    pub min_difference: Option<f32>,
+
+    /// Images are scaled down to this width before scoring, which is faster
+    /// and makes sensor noise count less.
+    pub analysis_width: u32,
+}
+
+impl Default for FrameFilter {
+    /// Keeps every frame.
+    fn default() -> Self {
+        Self {
+            min_brightness: None,
+            min_sharpness: None,
+            min_difference: None,
+            analysis_width: 320,
+        }
+    }
+}
+
+impl FrameFilter {
+    /// A small grayscale version of the image, which is what gets scored.
+    pub fn analysis_image(&self, image: &image::DynamicImage) -> GrayImage {
+        let width = self.analysis_width.max(1);
+        let image = if image.width() > width {
+            image.resize(width, u32::MAX, FilterType::Triangle)
+        } else {
+            image.clone()
+        };
+
+        image.into_luma8()
+    }
+
+    pub fn check(&self, score: &FrameScore) -> Option<Rejection> {
+        let below = |value: f32, threshold: Option<f32>| matches!(threshold, Some(t) if value < t);
+
+        if below(score.brightness, self.min_brightness) {
+            return Some(Rejection::TooDark {
+                brightness: score.brightness,
+            });
+        }
+        if below(score.sharpness, self.min_sharpness) {
+            return Some(Rejection::TooBlurry {
+                sharpness: score.sharpness,
+            });
+        }
+        if let Some(difference) = score.difference {
+            if below(difference, self.min_difference) {
+                return Some(Rejection::TooSimilar { difference });
+            }
+        }
+
+        None
+    }
+
+    /// Keep the images which pass, in order.
+    /// Blocking, since every image is decoded.
+    pub fn filter(&self, images: Vec<TimestampedFile>) -> Vec<TimestampedFile> {
+        let total = images.len();
+        let mut previous: Option<GrayImage> = None;
+        let mut kept = vec![];
+
+        for image in images {
+            let analysed = match image::open(&image.file) {
+                Ok(decoded) => self.analysis_image(&decoded),
+                Err(error) => {
+                    let reason = Rejection::Unreadable(error.to_string());
+                    info!(file = ?image.file, %reason, "Dropping frame");
+                    continue;
+                }
+            };
+
+            let score = score(&analysed, previous.as_ref());
+            debug!(file = ?image.file, ?score, "Scored frame");
+
+            if let Some(reason) = self.check(&score) {
+                info!(file = ?image.file, %reason, "Dropping frame");
+                continue;
+            }
+
+            previous = Some(analysed);
+            kept.push(image);
+        }
+
+        info!("Kept {} of {total} frames", kept.len());
+        kept
+    }
+}
+
+/// Score an image, compared to the previous frame if there is one.
+pub fn score(image: &GrayImage, previous: Option<&GrayImage>) -> FrameScore {
+    FrameScore {
+        brightness: mean(image.pixels().map(|pixel| pixel.0[0] as f32)),
+        sharpness: sharpness(image),
+        difference: previous.map(|previous| difference(image, previous)),
+    }
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), value| {
+        (sum + value, count + 1)
+    });
+
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f32
+    }
+}
+
+fn sharpness(image: &GrayImage) -> f32 {
+    let laplacian = filter3x3::<_, i16, i16>(image, &[0, 1, 0, 1, -4, 1, 0, 1, 0]);
+
+    let values = laplacian.pixels().map(|pixel| pixel.0[0] as f32);
+    let average = mean(values.clone());
+    mean(values.map(|value| (value - average).powi(2)))
+}
+
+// Images of different sizes can't be compared, so count them as completely different.
+fn difference(image: &GrayImage, previous: &GrayImage) -> f32 {
+    if image.dimensions() != previous.dimensions() {
+        return 255.0;
+    }
+
+    mean(
+        image
+            .pixels()
+            .zip(previous.pixels())
+            .map(|(a, b)| (a.0[0] as f32 - b.0[0] as f32).abs()),
+    )
+}
+
+/// Run the filter without blocking the async runtime.
+pub async fn filter_frames(
+    filter: FrameFilter,
+    images: Vec<TimestampedFile>,
+) -> Result<Vec<TimestampedFile>, TimelapsifyError> {
+    Ok(tokio::task::spawn_blocking(move || filter.filter(images)).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        let black = GrayImage::new(32, 32);
+        let gray = GrayImage::from_pixel(32, 32, Luma([100]));
+        let stripes = GrayImage::from_fn(32, 32, |x, _| Luma([if x % 2 == 0 { 0 } else { 200 }]));
+
+        let score_black = score(&black, None);
+        assert_eq!(score_black.brightness, 0.0);
+        assert_eq!(score_black.sharpness, 0.0);
+        assert_eq!(score_black.difference, None);
+
+        let score_stripes = score(&stripes, Some(&gray));
+        assert_eq!(score_stripes.brightness, 100.0);
+        assert!(score_stripes.sharpness > 1000.0);
+        assert_eq!(score_stripes.difference, Some(100.0));
+
+        assert_eq!(score(&gray, Some(&gray)).difference, Some(0.0));
+        assert_eq!(
+            score(&gray, Some(&GrayImage::new(16, 16))).difference,
+            Some(255.0)
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let filter = FrameFilter {
+            min_brightness: Some(40.0),
+            min_sharpness: Some(10.0),
+            min_difference: Some(2.0),
+            analysis_width: 320,
+        };
+
+        let good = FrameScore {
+            brightness: 120.0,
+            sharpness: 300.0,
+            difference: Some(10.0),
+        };
+        assert_eq!(filter.check(&good), None);
+        assert_eq!(
+            filter.check(&FrameScore {
+                difference: None,
+                ..good
+            }),
+            None
+        );
+
+        assert_eq!(
+            filter.check(&FrameScore {
+                brightness: 12.0,
+                ..good
+            }),
+            Some(Rejection::TooDark { brightness: 12.0 })
+        );
+        assert_eq!(
+            filter.check(&FrameScore {
+                sharpness: 1.0,
+                ..good
+            }),
+            Some(Rejection::TooBlurry { sharpness: 1.0 })
+        );
+        assert_eq!(
+            filter.check(&FrameScore {
+                difference: Some(0.5),
+                ..good
+            }),
+            Some(Rejection::TooSimilar { difference: 0.5 })
+        );
+
+        let nothing = FrameFilter {
+            min_brightness: None,
+            ..filter
+        };
+        assert_eq!(
+            nothing.check(&FrameScore {
+                brightness: 0.0,
+                ..good
+            }),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filter_fixtures() {
+        let mut images = crate::candidates(format!("{}/test_images", env!("CARGO_MANIFEST_DIR")))
+            .await
+            .unwrap();
+        crate::sort_files_by_timestamp(&mut images);
+        assert_eq!(images.len(), 39);
+
+        let kept = filter_frames(FrameFilter::default(), images.clone())
+            .await
+            .unwrap();
+        assert_eq!(kept.len(), images.len());
+
+        // These are all taken at night
+        let daylight = FrameFilter {
+            min_brightness: Some(80.0),
+            ..Default::default()
+        };
+        assert!(filter_frames(daylight, images.clone())
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Nothing happens at night either
+        let changes = FrameFilter {
+            min_difference: Some(2.0),
+            ..Default::default()
+        };
+        let kept = filter_frames(changes, images.clone()).await.unwrap();
+        assert!(kept.len() < 10);
+        assert_eq!(kept[0].file, images[0].file);
+
+        // An image which can't be read is dropped, not fatal
+        let mut broken = images[..2].to_vec();
+        broken[1].file = broken[1].file.with_file_name("2022-07-12_00-00-00.jpg");
+        let kept = filter_frames(FrameFilter::default(), broken).await.unwrap();
+        assert_eq!(kept.len(), 1);
+    }
+}
@@ -0,0 +1,460 @@
+//! Keeping track of the timelapse made for each day.
+//!
+//! Each day has a job record, stored as JSON in a folder of its own:
+//!
+//! - `pending`: known about, but not started.
+//! - `encoding`: being made right now. If a job is found like this on startup,
+//!   the worker stopped halfway, so it is made again.
+//! - `done`: the video is in place. Images of the day which turn up after that,
+//!   late or not moved before a crash, make it start over with the images processed before.
+//! - `failed`: retried later, with a growing delay, until the attempts run out.
+//!   Then it is given up on, until more images of the day arrive and it starts over.
+//!
+//! Weekly and monthly timelapses keep a record of what they were made from,
+//! in a folder per kind, see [`CompilationSources`].
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::warn;
+
+use crate::TimelapsifyError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Pending,
+    Encoding,
+    Done,
+    Failed,
+}
+
+/// How failed jobs are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Give up on a job after this many attempts.
+    pub max_attempts: u32,
+
+    /// Wait this long after the first failure, then twice as long after each failure after that.
+    pub first_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            first_delay: Duration::minutes(30),
+            max_delay: Duration::hours(12),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait after the given number of failed attempts.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let doublings = attempts.saturating_sub(1).min(16);
+        let delay = self.first_delay * 2_i32.pow(doublings);
+
+        delay.min(self.max_delay)
+    }
+}
+
+/// The job of making the timelapse of a day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub day: NaiveDate,
+    pub state: JobState,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+
+    /// When a failed job should be tried again. Not set once it is given up on.
+    pub retry_at: Option<DateTime<Local>>,
+
+    pub video: Option<PathBuf>,
+    pub updated: DateTime<Local>,
+
+    /// How many unprocessed images the last attempt had,
+    /// so a job given up on can tell when more arrive.
+    #[serde(default)]
+    pub images: usize,
+}
+
+impl Job {
+    pub fn new(day: NaiveDate, now: DateTime<Local>) -> Self {
+        Self {
+            day,
+            state: JobState::Pending,
+            attempts: 0,
+            last_error: None,
+            retry_at: None,
+            video: None,
+            updated: now,
+            images: 0,
+        }
+    }
+
+    pub fn start(&mut self, now: DateTime<Local>) {
+        self.state = JobState::Encoding;
+        self.attempts += 1;
+        self.updated = now;
+    }
+
+    /// `video` is `None` if there was nothing worth making a video of.
+    pub fn finish(&mut self, now: DateTime<Local>, video: Option<PathBuf>) {
+        self.state = JobState::Done;
+        self.last_error = None;
+        self.retry_at = None;
+        self.video = video;
+        self.updated = now;
+    }
+
+    pub fn fail(&mut self, now: DateTime<Local>, error: &TimelapsifyError, policy: &RetryPolicy) {
+        self.state = JobState::Failed;
+        self.last_error = Some(error.to_string());
+        self.retry_at =
+            (self.attempts < policy.max_attempts).then(|| now + policy.delay(self.attempts));
+        self.updated = now;
+    }
+
+    /// Whether the retries have run out.
+    pub fn is_given_up(&self) -> bool {
+        self.state == JobState::Failed && self.retry_at.is_none()
+    }
+
+    /// Whether nothing more will happen to the job by itself,
+    /// because it is done or given up on.
+    pub fn is_settled(&self) -> bool {
+        match self.state {
+            JobState::Done => true,
+            JobState::Failed => self.is_given_up(),
+            JobState::Pending | JobState::Encoding => false,
+        }
+    }
+
+    /// Whether the job should be run now.
+    pub fn is_due(&self, now: DateTime<Local>) -> bool {
+        match self.state {
+            JobState::Pending | JobState::Encoding => true,
+            JobState::Done => false,
+            JobState::Failed => matches!(self.retry_at, Some(retry_at) if retry_at <= now),
+        }
+    }
+}
+
+/// What went into a weekly or monthly timelapse from a single day.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaySources {
+    pub images: usize,
+
+    /// The file name of the last image of the day.
+    pub last_image: Option<String>,
+
+    /// When the job of the day was last updated, if it has one.
+    pub job_updated: Option<DateTime<Local>>,
+}
+
+impl DaySources {
+    /// Whether the day has images it did not have before.
+    /// Fewer images, such as after retention thinned the day, is not something new.
+    pub fn has_new_images(&self, before: &DaySources) -> bool {
+        self.images > before.images
+            || self.last_image > before.last_image
+            || self.job_updated > before.job_updated
+    }
+}
+
+/// What a weekly or monthly timelapse was made from, per day.
+/// It is made again when images are added, such as when an image of the period arrives late,
+/// but not when images are only removed, see [`has_new_images`].
+pub type CompilationSources = BTreeMap<NaiveDate, DaySources>;
+
+/// Whether a compilation made from `before` has new images to be made from now.
+pub fn has_new_images(sources: &CompilationSources, before: &CompilationSources) -> bool {
+    sources.iter().any(|(day, sources)| match before.get(day) {
+        Some(before) => sources.has_new_images(before),
+        None => sources.images > 0,
+    })
+}
+
+/// Write to a temporary file first, then rename it into place,
+/// so a crash leaves either the old contents or the new ones.
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), TimelapsifyError> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| TimelapsifyError::file_option_issue(path))?;
+    let temporary = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+    fs::write(&temporary, contents).await?;
+    fs::rename(&temporary, path).await?;
+
+    Ok(())
+}
+
+/// Job records, one file per day.
+#[derive(Debug, Clone)]
+pub struct JobStore {
+    folder: PathBuf,
+}
+
+impl JobStore {
+    pub fn new<P: Into<PathBuf>>(folder: P) -> Self {
+        Self {
+            folder: folder.into(),
+        }
+    }
+
+    fn path(&self, day: NaiveDate) -> PathBuf {
+        self.folder.join(format!("{}.json", day.format("%Y-%m-%d")))
+    }
+
+    pub async fn load(&self, day: NaiveDate) -> Result<Option<Job>, TimelapsifyError> {
+        let path = self.path(day);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(&fs::read(path).await?)?))
+    }
+
+    pub async fn save(&self, job: &Job) -> Result<(), TimelapsifyError> {
+        fs::create_dir_all(&self.folder).await?;
+
+        write_atomic(&self.path(job.day), &serde_json::to_vec_pretty(job)?).await
+    }
+
+    fn sources_path(&self, kind: &str, start: NaiveDate) -> PathBuf {
+        self.folder
+            .join(kind)
+            .join(format!("{}.json", start.format("%Y-%m-%d")))
+    }
+
+    /// What the compilation of the given kind, such as `weekly`, starting at the given day
+    /// was made from. `None` if it was never recorded.
+    pub async fn load_sources(
+        &self,
+        kind: &str,
+        start: NaiveDate,
+    ) -> Result<Option<CompilationSources>, TimelapsifyError> {
+        let path = self.sources_path(kind, start);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(&fs::read(path).await?)?))
+    }
+
+    pub async fn save_sources(
+        &self,
+        kind: &str,
+        start: NaiveDate,
+        sources: &CompilationSources,
+    ) -> Result<(), TimelapsifyError> {
+        let path = self.sources_path(kind, start);
+        fs::create_dir_all(self.folder.join(kind)).await?;
+
+        write_atomic(&path, &serde_json::to_vec_pretty(sources)?).await
+    }
+
+    /// All jobs, oldest day first. Records which can't be read are skipped.
+    pub async fn all(&self) -> Result<Vec<Job>, TimelapsifyError> {
+        if !self.folder.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut jobs = vec![];
+        let mut entries = fs::read_dir(&self.folder).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            match serde_json::from_slice::<Job>(&fs::read(&path).await?) {
+                Ok(job) => jobs.push(job),
+                Err(error) => warn!(?error, ?path, "Skipping unreadable job record"),
+            }
+        }
+
+        jobs.sort_by_key(|job| job.day);
+        Ok(jobs)
+    }
+
+    /// Jobs which were encoding when the worker stopped are made pending again.
+    /// Returns how many there were.
+    pub async fn recover(&self, now: DateTime<Local>) -> Result<usize, TimelapsifyError> {
+        let mut recovered = 0;
+
+        for mut job in self.all().await? {
+            if job.state == JobState::Encoding {
+                warn!(day = %job.day, "Job was interrupted, it will be made again");
+                job.state = JobState::Pending;
+                job.updated = now;
+                self.save(&job).await?;
+                recovered += 1;
+            }
+        }
+
+        Ok(recovered)
+    }
+
+    /// The earliest time a failed job should be retried.
+    pub async fn next_retry(&self) -> Result<Option<DateTime<Local>>, TimelapsifyError> {
+        Ok(self
+            .all()
+            .await?
+            .iter()
+            .filter(|job| job.state == JobState::Failed)
+            .filter_map(|job| job.retry_at)
+            .min())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            first_delay: Duration::hours(1),
+            max_delay: Duration::hours(3),
+        };
+
+        assert_eq!(policy.delay(1), Duration::hours(1));
+        assert_eq!(policy.delay(2), Duration::hours(2));
+        assert_eq!(policy.delay(3), Duration::hours(3));
+        assert_eq!(policy.delay(1000), Duration::hours(3));
+
+        let now = Local.ymd(2022, 7, 13).and_hms(1, 0, 0);
+        let error = TimelapsifyError::Weird("test");
+        let mut job = Job::new(NaiveDate::from_ymd(2022, 7, 12), now);
+        assert!(job.is_due(now));
+
+        job.start(now);
+        job.fail(now, &error, &policy);
+        assert_eq!(job.retry_at, Some(now + Duration::hours(1)));
+        assert!(!job.is_due(now));
+        assert!(job.is_due(now + Duration::hours(1)));
+
+        job.start(now);
+        job.fail(now, &error, &policy);
+        job.start(now);
+        job.fail(now, &error, &policy);
+
+        // Given up on
+        assert_eq!(job.attempts, 3);
+        assert_eq!(job.retry_at, None);
+        assert!(!job.is_due(now + Duration::days(100)));
+        assert_eq!(job.last_error, Some(error.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_job_store() {
+        let folder =
+            std::env::temp_dir().join(format!("timelapsifier-jobs-{}", std::process::id()));
+        let store = JobStore::new(&folder);
+        let now = Local.ymd(2022, 7, 13).and_hms(1, 0, 0);
+
+        assert_eq!(store.all().await.unwrap(), vec![]);
+        assert_eq!(
+            store.load(NaiveDate::from_ymd(2022, 7, 12)).await.unwrap(),
+            None
+        );
+
+        let mut encoding = Job::new(NaiveDate::from_ymd(2022, 7, 12), now);
+        encoding.start(now);
+        store.save(&encoding).await.unwrap();
+
+        let mut failed = Job::new(NaiveDate::from_ymd(2022, 7, 11), now);
+        failed.start(now);
+        failed.fail(
+            now,
+            &TimelapsifyError::Weird("test"),
+            &RetryPolicy::default(),
+        );
+        store.save(&failed).await.unwrap();
+
+        assert_eq!(
+            store.load(encoding.day).await.unwrap(),
+            Some(encoding.clone())
+        );
+        assert_eq!(
+            store.all().await.unwrap(),
+            vec![failed.clone(), encoding.clone()]
+        );
+        assert_eq!(store.next_retry().await.unwrap(), failed.retry_at);
+
+        // No temporary files left behind
+        let mut entries = std::fs::read_dir(&folder).unwrap();
+        assert!(entries.all(|entry| !entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .ends_with(".tmp")));
+
+        assert_eq!(store.recover(now).await.unwrap(), 1);
+        let recovered = store.load(encoding.day).await.unwrap().unwrap();
+        assert_eq!(recovered.state, JobState::Pending);
+        assert!(recovered.is_due(now));
+        assert_eq!(store.recover(now).await.unwrap(), 0);
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_has_new_images() {
+        let now = Local.ymd(2022, 7, 13).and_hms(1, 0, 0);
+        let day = |images, last_image: &str| DaySources {
+            images,
+            last_image: Some(last_image.to_string()),
+            job_updated: Some(now),
+        };
+        let sources = |days: &[(u32, DaySources)]| {
+            days.iter()
+                .map(|(day, sources)| {
+                    (
+                        NaiveDate::from_ymd_opt(2022, 7, *day).unwrap(),
+                        sources.clone(),
+                    )
+                })
+                .collect::<CompilationSources>()
+        };
+
+        let made_from = sources(&[
+            (11, day(3, "2022-07-11_10-10-00.jpg")),
+            (12, day(3, "2022-07-12_10-10-00.jpg")),
+        ]);
+        assert!(!has_new_images(&made_from, &made_from));
+
+        // Thinned, or gone altogether.
+        let thinned = sources(&[(12, day(1, "2022-07-12_10-00-00.jpg"))]);
+        assert!(!has_new_images(&thinned, &made_from));
+
+        // An image later in the day, one in between, or a day made again.
+        let later = sources(&[(12, day(3, "2022-07-12_10-15-00.jpg"))]);
+        assert!(has_new_images(&later, &made_from));
+        let between = sources(&[(12, day(4, "2022-07-12_10-10-00.jpg"))]);
+        assert!(has_new_images(&between, &made_from));
+        let remade = sources(&[(
+            12,
+            DaySources {
+                job_updated: Some(now + Duration::hours(1)),
+                ..day(1, "2022-07-12_10-00-00.jpg")
+            },
+        )]);
+        assert!(has_new_images(&remade, &made_from));
+
+        // A day it did not have.
+        let new_day = sources(&[(13, day(1, "2022-07-13_10-00-00.jpg"))]);
+        assert!(has_new_images(&new_day, &made_from));
+    }
+}
@@ -2,17 +2,43 @@ use std::{
     collections::BTreeMap,
     num::ParseIntError,
     path::{Path, PathBuf},
-    process::Output,
     sync::Arc,
 };
 
-use chrono::{DateTime, Local, TimeZone, Timelike};
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use thiserror::Error;
-use tokio::{fs, io::AsyncWriteExt, process::Command, sync::RwLock};
+use tokio::{fs, sync::RwLock};
 use tracing::{debug, error, info, instrument, trace, warn};
 
+pub mod capture;
+pub mod deflicker;
+pub mod encoder;
+pub mod filter;
+pub mod jobs;
+pub mod meta;
+pub mod overlay;
+pub mod profile;
+pub mod retention;
+pub mod schedule;
+
+use deflicker::DeflickerOptions;
+use encoder::Encoder;
+use filter::FrameFilter;
+use jobs::{has_new_images, CompilationSources, Job, JobState, JobStore, RetryPolicy};
+use meta::{TimelapseVideo, VideoMeta};
+use overlay::OverlayOptions;
+use profile::EncodingProfile;
+use retention::RetentionOptions;
+use schedule::{Clock, Schedule, Trigger};
+
+/// Extensions of the images made into timelapses. Other files, such as metadata, are left be.
+const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+/// Extensions of the videos made, the first found is the main one of a video.
+const VIDEO_EXTENSIONS: [&str; 3] = ["mp4", "webm", "gif"];
+
 static RE_YMD_HMS: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
         r"(?x)
@@ -27,6 +53,7 @@ static RE_YMD_HMS: Lazy<Regex> = Lazy::new(|| {
   (?P<M>\d{2}) # the minutes
   -
   (?P<S>\d{2}) # the seconds
+  (?P<z>Z|[+-]\d{4})? # the offset from UTC, not in older names
 ",
     )
     .expect("regex should compile")
@@ -57,7 +84,17 @@ impl AsRef<Path> for TimestampedFile {
     }
 }
 
+impl AsRef<TimestampedFile> for TimestampedFile {
+    fn as_ref(&self) -> &TimestampedFile {
+        self
+    }
+}
+
 impl TimestampedFile {
+    /// For names like `2022-07-12_22-44-11+0200`, or `2022-07-12_22-44-11` for the server's local time.
+    ///
+    /// Names without an offset are from before they had one. Where the clocks went back such
+    /// names are ambiguous, and the earliest time is used.
     pub fn new_ymd_hms(file: PathBuf) -> Result<Self, TimelapsifyError> {
         let re = Lazy::force(&RE_YMD_HMS);
 
@@ -67,46 +104,38 @@ impl TimestampedFile {
 
         let file_name_str = file_name.to_string_lossy();
 
-        let dt = if let Some(cap) = re.captures(&file_name_str) {
-            let year = cap
-                .name("y")
-                .ok_or(TimelapsifyError::RegexNoMatch)?
-                .as_str()
-                .parse()?;
-            let month = cap
-                .name("m")
-                .ok_or(TimelapsifyError::RegexNoMatch)?
-                .as_str()
-                .parse()?;
-            let day = cap
-                .name("d")
-                .ok_or(TimelapsifyError::RegexNoMatch)?
-                .as_str()
-                .parse()?;
-            let hour = cap
-                .name("H")
-                .ok_or(TimelapsifyError::RegexNoMatch)?
-                .as_str()
-                .parse()?;
-            let minutes = cap
-                .name("M")
+        let cap = re
+            .captures(&file_name_str)
+            .ok_or(TimelapsifyError::RegexNoMatch)?;
+        let number = |name: &str| -> Result<u32, TimelapsifyError> {
+            Ok(cap
+                .name(name)
                 .ok_or(TimelapsifyError::RegexNoMatch)?
                 .as_str()
-                .parse()?;
-            let seconds = cap
-                .name("S")
-                .ok_or(TimelapsifyError::RegexNoMatch)?
-                .as_str()
-                .parse()?;
-            Ok(Local.ymd(year, month, day).and_hms(hour, minutes, seconds))
-        } else {
-            Err(TimelapsifyError::RegexNoMatch)
-        }?;
+                .parse()?)
+        };
 
-        Ok(Self {
-            file,
-            timestamp: dt,
-        })
+        let (hour, minutes, seconds) = (number("H")?, number("M")?, number("S")?);
+        let naive = NaiveDate::from_ymd_opt(number("y")? as i32, number("m")?, number("d")?)
+            .and_then(|date| date.and_hms_opt(hour, minutes, seconds))
+            .ok_or_else(|| TimelapsifyError::InvalidTime(file_name_str.to_string()))?;
+
+        let timestamp = match cap.name("z").map(|offset| offset.as_str()) {
+            None => local_from_naive(naive)?,
+            Some("Z") => Utc.from_utc_datetime(&naive).with_timezone(&Local),
+            Some(offset) => {
+                let hours: i32 = offset[1..3].parse()?;
+                let minutes: i32 = offset[3..5].parse()?;
+                let sign = if offset.starts_with('-') { -1 } else { 1 };
+
+                FixedOffset::east_opt(sign * (hours * 60 + minutes) * 60)
+                    .and_then(|offset| offset.from_local_datetime(&naive).single())
+                    .ok_or_else(|| TimelapsifyError::InvalidTime(file_name_str.to_string()))?
+                    .with_timezone(&Local)
+            }
+        };
+
+        Ok(Self { file, timestamp })
     }
 
     pub fn new_ymd(file: PathBuf) -> Result<Self, TimelapsifyError> {
@@ -134,7 +163,10 @@ impl TimestampedFile {
                 .ok_or(TimelapsifyError::RegexNoMatch)?
                 .as_str()
                 .parse()?;
-            Ok(Local.ymd(year, month, day).and_hms(0, 0, 0))
+            NaiveDate::from_ymd_opt(year, month, day)
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .ok_or_else(|| TimelapsifyError::InvalidTime(file_name_str.to_string()))
+                .and_then(local_from_naive)
         } else {
             Err(TimelapsifyError::RegexNoMatch)
         }?;
@@ -146,40 +178,128 @@ impl TimestampedFile {
     }
 }
 
-fn duration_until_tomorrow_night_01() -> std::time::Duration {
-    let now = Local::now();
+/// The server's local time at a wall clock time.
+/// When the clocks go back the earliest is used, and times skipped when they go forward
+/// are taken to be an hour later.
+fn local_from_naive(naive: NaiveDateTime) -> Result<DateTime<Local>, TimelapsifyError> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| {
+            Local
+                .from_local_datetime(&(naive + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .ok_or_else(|| TimelapsifyError::InvalidTime(naive.to_string()))
+}
 
-    // Add one day, then floor towards 01:00 in the night.
-    let tomorrow_night_01 = (now + chrono::Duration::days(1))
-        .with_hour(1)
-        .expect("hour ok")
-        .with_minute(0)
-        .expect("minute ok")
-        .with_second(0)
-        .expect("seconds ok");
+/// The day a timestamp is from, in the given time zone or the server's local one.
+pub fn day_of(timestamp: &DateTime<Local>, time_zone: Option<chrono_tz::Tz>) -> NaiveDate {
+    match time_zone {
+        Some(time_zone) => timestamp.with_timezone(&time_zone).naive_local().date(),
+        None => timestamp.naive_local().date(),
+    }
+}
 
-    tomorrow_night_01
-        .signed_duration_since(now)
-        .to_std()
-        .expect("Duration std ok")
+/// All timelapse videos, each list sorted oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct Timelapses {
+    pub days: Vec<TimelapseVideo>,
+    pub weeks: Vec<TimelapseVideo>,
+    pub months: Vec<TimelapseVideo>,
 }
 
-pub type StateVideos = Arc<RwLock<Vec<TimestampedFile>>>;
+impl Timelapses {
+    pub async fn load(options: &TimelapserOptions) -> Self {
+        Self {
+            days: timelapse_videos_in(&options.timelapse_output_folder).await,
+            weeks: timelapse_videos_in(&options.weekly.output_folder).await,
+            months: timelapse_videos_in(&options.monthly.output_folder).await,
+        }
+    }
+}
+
+pub type StateVideos = Arc<RwLock<Timelapses>>;
+
+/// How to make a timelapse spanning a longer period, such as a week or a month.
+#[derive(Debug, Clone)]
+pub struct CompilationOptions {
+    pub output_folder: PathBuf,
+    pub profile: EncodingProfile,
+
+    /// Only use every n-th image, so long periods stay watchable.
+    pub every_nth_image: usize,
+}
 
 #[derive(Debug)]
 pub struct TimelapserOptions {
     pub unprocessed_images_folder: PathBuf,
     pub processed_images_folder: PathBuf,
     pub timelapse_output_folder: PathBuf,
+    pub profile: EncodingProfile,
+
+    /// Where the job record of each day is kept, see [`jobs`].
+    pub jobs_folder: PathBuf,
+    pub retry: RetryPolicy,
+
+    pub weekly: CompilationOptions,
+    pub monthly: CompilationOptions,
+
+    /// Makes the videos, see [`encoder::FfmpegEncoder`] and [`encoder::GifEncoder`].
+    pub encoder: Arc<dyn Encoder>,
+
+    /// Drops unwanted frames before they are made into videos, all are kept if not set.
+    pub filter: Option<FrameFilter>,
+
+    /// Evens out exposure and colour between frames, not done if not set.
+    pub deflicker: Option<DeflickerOptions>,
+
+    /// Text drawn onto the frames before encoding, none if not set.
+    pub overlay: Option<OverlayOptions>,
+
+    /// Images are grouped into days, weeks and months by the calendar of this time zone.
+    /// The server's own if not set.
+    pub day_boundary: Option<chrono_tz::Tz>,
+
+    /// Removes old images and videos, see [`retention`]. Nothing is removed if not set.
+    pub retention: Option<RetentionOptions>,
+
+    /// When the worker runs, see [`schedule`].
+    pub schedule: Schedule,
+
+    /// Where the worker gets the time from, so tests can choose it.
+    pub clock: Arc<dyn Clock>,
+
+    /// Makes the worker run right away.
+    pub trigger: Trigger,
 
     pub timelapse_videos: StateVideos,
 }
 
 #[instrument]
 async fn do_work(options: &TimelapserOptions) {
+    let now = options.clock.now();
+
+    make_daily_timelapses(options, now).await;
+    make_compilations(options, now).await;
+
+    if let Some(retention) = &options.retention {
+        retention::enforce(retention.clone(), now).await;
+    }
+
+    *options.timelapse_videos.write().await = Timelapses::load(options).await;
+}
+
+async fn make_daily_timelapses(options: &TimelapserOptions, now: DateTime<Local>) {
     info!("Looking for timelapse image candidates");
 
-    let mut candidates = candidates(&options.unprocessed_images_folder).await;
+    let mut candidates = match candidates(&options.unprocessed_images_folder).await {
+        Ok(candidates) => candidates,
+        Err(error) => {
+            error!(?error, "Could not look for candidates");
+            return;
+        }
+    };
     if candidates.is_empty() {
         info!("No candidates");
         return;
@@ -188,76 +308,548 @@ async fn do_work(options: &TimelapserOptions) {
 
     info!("Found {} candidates", candidates.len());
 
-    let before_today = images_before_day_of(candidates.clone(), Local::now());
+    let time_zone = options.day_boundary;
+    let before_today = images_before_day(candidates, day_of(&now, time_zone), time_zone);
     if before_today.is_empty() {
         info!("No images from earlier days");
         return;
     }
 
-    let grouped_by_days = group_by_day(before_today);
+    let jobs = JobStore::new(&options.jobs_folder);
+
+    // Each day is a job of its own, so one failing does not hold up the others.
+    for (day, images_that_day) in group_by_day(before_today, time_zone) {
+        if let Err(error) = run_day_job(options, &jobs, day, &images_that_day, now).await {
+            error!(?error, %day, "Timelapse job not successful!");
+        }
+    }
+}
+
+/// Make the timelapse of a day, keeping its job record up to date along the way.
+/// The images are only moved to the processed folder once the video is in place.
+///
+/// A day which is already done is made again, along with the images processed before,
+/// since there is no telling images which arrived late from ones left behind.
+/// A day given up on starts over once more images of it arrive.
+async fn run_day_job(
+    options: &TimelapserOptions,
+    jobs: &JobStore,
+    day: NaiveDate,
+    images: &[TimestampedFile],
+    now: DateTime<Local>,
+) -> Result<(), TimelapsifyError> {
+    let mut job = jobs.load(day).await?.unwrap_or_else(|| Job::new(day, now));
+
+    if job.state == JobState::Done {
+        info!(%day, new_images = images.len(), "Timelapse already made, making it again");
+        job = Job::new(day, now);
+    } else if job.is_given_up() && images.len() > job.images {
+        info!(%day, images = images.len(), "Timelapse given up on has new images, starting over");
+        job = Job::new(day, now);
+    }
+
+    if !job.is_due(now) {
+        debug!(%day, retry_at = ?job.retry_at, "Not retrying yet");
+        return Ok(());
+    }
 
-    for (day, images_that_day) in grouped_by_days {
-        info!("Making a timelapse for day {day:?}");
+    info!(%day, attempt = job.attempts + 1, "Making a timelapse");
 
-        let mut output_video = options.timelapse_output_folder.clone();
-        if !output_video.exists() {
-            info!("Making output dir {output_video:?}");
-            fs::create_dir_all(&output_video)
-                .await
-                .expect("make dirs ok");
+    job.start(now);
+    job.images = images.len();
+    jobs.save(&job).await?;
+
+    let mut images_that_day = processed_images_of_day(options, day).await?;
+    images_that_day.extend_from_slice(images);
+    sort_files_by_timestamp(&mut images_that_day);
+
+    match make_day_video(options, day, &images_that_day).await {
+        Ok(video) => {
+            info!(%day, ?video, "Timelapse made ok");
+            job.finish(now, video);
+            jobs.save(&job).await?;
         }
+        Err(error) => {
+            job.fail(now, &error, &options.retry);
+            jobs.save(&job).await?;
+
+            if job.retry_at.is_none() {
+                warn!(%day, "Giving up on this timelapse, the images are left as they are");
+            }
+            return Err(error);
+        }
+    }
 
-        output_video.push(format!("{}.mp4", day.format("%Y-%m-%d")));
+    fs::create_dir_all(&options.processed_images_folder).await?;
+    move_all(images, &options.processed_images_folder).await?;
+    info!(
+        "Images processed ({}) moved to processed folder.",
+        images.len()
+    );
 
-        let output = ffmpeg_make_clip(
-            &images_that_day,
-            output_video.to_str().expect("ok &str mp4 path"),
-        )
-        .await;
+    Ok(())
+}
+
+/// The images of a day which are in the processed folder already.
+async fn processed_images_of_day(
+    options: &TimelapserOptions,
+    day: NaiveDate,
+) -> Result<Vec<TimestampedFile>, TimelapsifyError> {
+    if !options.processed_images_folder.exists() {
+        return Ok(vec![]);
+    }
+
+    let images = candidates(&options.processed_images_folder).await?;
+    Ok(images
+        .into_iter()
+        .filter(|image| day_of(&image.timestamp, options.day_boundary) == day)
+        .collect())
+}
 
-        if !output.status.success() {
-            error!(?output, "Timelapse creation not successful!");
+/// The video made, or `None` if no frames were worth keeping.
+async fn make_day_video(
+    options: &TimelapserOptions,
+    day: NaiveDate,
+    images: &[TimestampedFile],
+) -> Result<Option<PathBuf>, TimelapsifyError> {
+    let frames = filter_frames(options, images.to_vec()).await;
+
+    // The images are still processed, there was just nothing worth watching that day.
+    if frames.is_empty() {
+        info!("No frames left after filtering, no timelapse for {day}");
+        return Ok(None);
+    }
+
+    fs::create_dir_all(&options.timelapse_output_folder).await?;
+    let output_video = options.timelapse_output_folder.join(format!(
+        "{}.{}",
+        day.format("%Y-%m-%d"),
+        options.encoder.extension(&options.profile)
+    ));
+
+    make_video(options, &frames, &output_video, &options.profile).await?;
+
+    Ok(Some(output_video))
+}
+
+/// Make weekly and monthly timelapses from the processed images.
+///
+/// Only periods which are over are made, once the jobs of their days are settled.
+/// They are made again if images are added to them, see [`CompilationSources`].
+/// Videos are named after the first day of the period, so a week is named after its monday.
+async fn make_compilations(options: &TimelapserOptions, now: DateTime<Local>) {
+    if !options.processed_images_folder.exists() {
+        return;
+    }
+
+    let mut images = match candidates(&options.processed_images_folder).await {
+        Ok(images) => images,
+        Err(error) => {
+            error!(?error, "Could not look for processed images");
             return;
-        } else {
-            info!("Timelapse made ok: {output_video:?}")
         }
+    };
+    sort_files_by_timestamp(&mut images);
+
+    let store = JobStore::new(&options.jobs_folder);
+    let jobs = match store.all().await {
+        Ok(jobs) => jobs,
+        Err(error) => {
+            error!(?error, "Could not read the day jobs");
+            return;
+        }
+    };
+
+    let time_zone = options.day_boundary;
+    let today = day_of(&now, time_zone);
+
+    let weeks = Periods {
+        kind: "weekly",
+        floor: floor_to_week,
+        images: images_before_day(images.clone(), floor_to_week(today), time_zone),
+    };
+    make_compilation(options, &store, &jobs, &options.weekly, weeks).await;
+
+    let months = Periods {
+        kind: "monthly",
+        floor: floor_to_month,
+        images: images_before_day(images, floor_to_month(today), time_zone),
+    };
+    make_compilation(options, &store, &jobs, &options.monthly, months).await;
+}
+
+/// Images to make a compilation of each period of.
+struct Periods {
+    /// Names the records of what each compilation was made from.
+    kind: &'static str,
+
+    /// The first day of the period a day is in.
+    floor: fn(NaiveDate) -> NaiveDate,
+
+    images: Vec<TimestampedFile>,
+}
+
+/// What a compilation is made from, given its images and the jobs of its days.
+fn compilation_sources(
+    images: &[TimestampedFile],
+    jobs: &[&Job],
+    time_zone: Option<chrono_tz::Tz>,
+) -> CompilationSources {
+    let mut sources = CompilationSources::new();
 
-        if !options.processed_images_folder.exists() {
-            info!(
-                "Making processed dir {:?}",
-                &options.processed_images_folder
+    for image in images {
+        let day = sources
+            .entry(day_of(&image.timestamp, time_zone))
+            .or_default();
+        day.images += 1;
+        day.last_image = image
+            .file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+    }
+    for job in jobs {
+        sources.entry(job.day).or_default().job_updated = Some(job.updated);
+    }
+
+    sources
+}
+
+async fn make_compilation(
+    options: &TimelapserOptions,
+    store: &JobStore,
+    jobs: &[Job],
+    compilation: &CompilationOptions,
+    periods: Periods,
+) {
+    let time_zone = options.day_boundary;
+    let kind = periods.kind;
+
+    for (start, images) in group_by(periods.images, time_zone, periods.floor) {
+        let output_video = compilation.output_folder.join(format!(
+            "{}.{}",
+            start.format("%Y-%m-%d"),
+            options.encoder.extension(&compilation.profile)
+        ));
+
+        let days = jobs
+            .iter()
+            .filter(|job| (periods.floor)(job.day) == start)
+            .collect::<Vec<_>>();
+        if let Some(job) = days.iter().find(|job| !job.is_settled()) {
+            info!(day = %job.day, "Waiting for the day before making {output_video:?}");
+            continue;
+        }
+
+        let sources = compilation_sources(&images, &days, time_zone);
+        if output_video.exists() {
+            match store.load_sources(kind, start).await {
+                Ok(Some(made_from)) if !has_new_images(&sources, &made_from) => continue,
+                Ok(Some(_)) => info!("New images since {output_video:?} was made"),
+                // Made before sources were recorded, so taken to be up to date.
+                Ok(None) => {
+                    if let Err(error) = store.save_sources(kind, start, &sources).await {
+                        error!(?error, "Could not record the sources of {output_video:?}");
+                    }
+                    continue;
+                }
+                Err(error) => warn!(?error, "Could not read the sources of {output_video:?}"),
+            }
+        }
+
+        if let Err(error) = fs::create_dir_all(&compilation.output_folder).await {
+            error!(
+                ?error,
+                "Could not make output dir {:?}", compilation.output_folder
             );
-            fs::create_dir_all(&options.processed_images_folder)
-                .await
-                .expect("make dirs ok");
+            return;
+        }
+
+        let images = filter_frames(options, images)
+            .await
+            .into_iter()
+            .step_by(compilation.every_nth_image.max(1))
+            .collect::<Vec<_>>();
+
+        if images.is_empty() {
+            info!("No frames left after filtering: {output_video:?}");
+            continue;
         }
 
-        move_all(&images_that_day, &options.processed_images_folder).await;
         info!(
-            "Images processed ({}) moved to processed folder.",
-            images_that_day.len()
+            "Making a timelapse from {} images: {output_video:?}",
+            images.len()
         );
 
-        let videos = files_of_ext_in(&options.timelapse_output_folder, &["mp4"]).await;
-        let num_videos = videos.len();
-        info!("# mp4: {num_videos}");
+        let result = make_video(options, &images, &output_video, &compilation.profile).await;
 
-        let timestamped_videos = videos
-            .into_iter()
-            .filter_map(|f| TimestampedFile::new_ymd(f).ok())
-            .collect::<Vec<_>>();
-        if timestamped_videos.len() != num_videos {
-            warn!(
-                "Could not figure out the timestamp of some mp4 files ({} out of {})",
-                timestamped_videos.len(),
-                num_videos
-            );
+        if let Err(error) = result {
+            error!(?error, "Timelapse creation not successful!");
+            continue;
         }
 
-        *options.timelapse_videos.write().await = timestamped_videos;
+        if let Err(error) = store.save_sources(kind, start, &sources).await {
+            error!(?error, "Could not record the sources of {output_video:?}");
+        }
+    }
+}
+
+/// Drop unwanted frames, if there is a filter.
+/// Should filtering itself fail, all frames are kept.
+async fn filter_frames(
+    options: &TimelapserOptions,
+    images: Vec<TimestampedFile>,
+) -> Vec<TimestampedFile> {
+    let filter = match &options.filter {
+        Some(filter) => filter.clone(),
+        None => return images,
+    };
+
+    match filter::filter_frames(filter, images.clone()).await {
+        Ok(frames) => frames,
+        Err(error) => {
+            warn!(?error, "Could not filter frames, keeping all");
+            images
+        }
+    }
+}
 
-        // TODO: Delete processed images >1 week old?
+/// The prefix of the folders videos are made in, next to where they end up.
+const WORK_FOLDER_PREFIX: &str = ".work-";
+
+/// Make a video of the images, preprocessing them first if wanted.
+///
+/// Everything is made in a working folder of its own, then moved into place.
+/// A crash never leaves half a video behind, and videos made at the same time
+/// don't share any scratch files.
+async fn make_video(
+    options: &TimelapserOptions,
+    images: &[TimestampedFile],
+    output_video: &Path,
+    profile: &EncodingProfile,
+) -> Result<(), TimelapsifyError> {
+    let (folder, file_name) = match (output_video.parent(), output_video.file_name()) {
+        (Some(folder), Some(file_name)) => (folder, file_name),
+        _ => return Err(TimelapsifyError::file_option_issue(output_video)),
+    };
+
+    let work = folder.join(format!(
+        "{WORK_FOLDER_PREFIX}{}",
+        file_name.to_string_lossy()
+    ));
+    if work.exists() {
+        fs::remove_dir_all(&work).await?;
     }
+    fs::create_dir_all(&work).await?;
+
+    let work_video = work.join(file_name);
+    let result = match preprocess_and_encode(options, images, &work_video, profile).await {
+        Ok(()) => publish(&work, folder, &work_video).await,
+        Err(error) => Err(error),
+    };
+
+    if let Err(error) = fs::remove_dir_all(&work).await {
+        warn!(?error, ?work, "Could not remove working folder");
+    }
+
+    result
+}
+
+async fn preprocess_and_encode(
+    options: &TimelapserOptions,
+    images: &[TimestampedFile],
+    output_video: &Path,
+    profile: &EncodingProfile,
+) -> Result<(), TimelapsifyError> {
+    let mut frames = images.to_vec();
+
+    // Before the overlay, which should not be evened out.
+    if let Some(deflicker) = &options.deflicker {
+        let folder = output_video.with_extension("deflicker");
+        frames = deflicker::deflicker_frames(deflicker.clone(), frames, folder).await?;
+    }
+
+    if let Some(overlay) = &options.overlay {
+        let folder = output_video.with_extension("overlay");
+        frames =
+            overlay::render_frames(overlay.clone(), options.day_boundary, frames, folder).await?;
+    }
+
+    encode_video(&options.encoder, &frames, output_video, profile).await
+}
+
+/// Move the files made in the working folder into the output folder.
+/// Videos are listed once they are there, so the video itself goes last,
+/// after its poster, metadata and alternatives.
+async fn publish(work: &Path, folder: &Path, video: &Path) -> Result<(), TimelapsifyError> {
+    let mut entries = fs::read_dir(work).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path != video && entry.file_type().await?.is_file() {
+            fs::rename(&path, folder.join(entry.file_name())).await?;
+        }
+    }
+
+    let file_name = video
+        .file_name()
+        .ok_or_else(|| TimelapsifyError::file_option_issue(video))?;
+    fs::rename(video, folder.join(file_name)).await?;
+
+    Ok(())
+}
+
+/// Clean up after the worker stopped in the middle of making videos:
+/// working folders are removed, and interrupted jobs are made pending again.
+async fn recover(options: &TimelapserOptions) {
+    for folder in [
+        &options.timelapse_output_folder,
+        &options.weekly.output_folder,
+        &options.monthly.output_folder,
+    ] {
+        let mut entries = match fs::read_dir(folder).await {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(WORK_FOLDER_PREFIX)
+            {
+                warn!(path = ?entry.path(), "Removing working folder left behind");
+                if let Err(error) = fs::remove_dir_all(entry.path()).await {
+                    error!(?error, "Could not remove working folder");
+                }
+            }
+        }
+    }
+
+    match JobStore::new(&options.jobs_folder)
+        .recover(options.clock.now())
+        .await
+    {
+        Ok(0) => {}
+        Ok(recovered) => info!("Recovered {recovered} interrupted jobs"),
+        Err(error) => error!(?error, "Could not recover jobs"),
+    }
+}
+
+/// Encode a video with the given profile, along with a webm if the profile wants one,
+/// and write its poster and metadata.
+async fn encode_video(
+    encoder: &Arc<dyn Encoder>,
+    images: &[TimestampedFile],
+    output_video: &Path,
+    profile: &EncodingProfile,
+) -> Result<(), TimelapsifyError> {
+    let fps = profile.frame_rate.fps_for(images.len());
+
+    encoder::encode(
+        encoder.clone(),
+        images.to_vec(),
+        output_video.to_owned(),
+        profile.clone(),
+        fps,
+    )
+    .await?;
+
+    // Encoders which only make one kind of video would overwrite the first one.
+    if let Some(webm) = profile.webm() {
+        let extension = encoder.extension(&webm);
+
+        if extension != encoder.extension(profile) {
+            let output_webm = output_video.with_extension(extension);
+            let result =
+                encoder::encode(encoder.clone(), images.to_vec(), output_webm, webm, fps).await;
+
+            if let Err(error) = result {
+                warn!(?error, ?output_video, "Could not make webm");
+            }
+        }
+    }
+
+    write_poster_and_meta(images, output_video, profile, fps).await;
+
+    Ok(())
+}
+
+/// The poster is taken from the middle of the day,
+/// since the first images are often taken in the dark.
+/// A video is still useful without these, so failing to make them is not fatal.
+async fn write_poster_and_meta(
+    images: &[TimestampedFile],
+    video: &Path,
+    profile: &EncodingProfile,
+    fps: u32,
+) {
+    if let Some(image) = images.get(images.len() / 2) {
+        if let Err(error) = meta::write_poster(&image.file, video).await {
+            warn!(?error, ?video, "Could not write poster");
+        }
+    }
+
+    if let Some(video_meta) = VideoMeta::new(images, profile, fps) {
+        if let Err(error) = meta::write_sidecar(video, &video_meta).await {
+            warn!(?error, ?video, "Could not write video metadata");
+        }
+    }
+}
+
+/// Find the timelapse videos in the given folder, along with their posters and metadata.
+/// A folder which does not exist yet has no videos.
+pub async fn timelapse_videos_in<P: AsRef<Path>>(folder: P) -> Vec<TimelapseVideo> {
+    if !folder.as_ref().exists() {
+        return vec![];
+    }
+
+    let files = match files_of_ext_in(&folder, &VIDEO_EXTENSIONS).await {
+        Ok(files) => files,
+        Err(error) => {
+            error!(?error, "Could not look for videos");
+            return vec![];
+        }
+    };
+
+    // The same video may be there in several formats.
+    let mut by_name = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+    for file in files {
+        by_name
+            .entry(file.with_extension(""))
+            .or_default()
+            .push(file);
+    }
+
+    let num_videos = by_name.len();
+    info!("# videos: {num_videos}");
+
+    let mut timelapse_videos = vec![];
+    for (_, mut files) in by_name {
+        files.sort_by_key(|file| {
+            let extension = file
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default();
+            VIDEO_EXTENSIONS
+                .iter()
+                .position(|&e| e == extension.to_ascii_lowercase())
+        });
+
+        let video = files.remove(0);
+        if let Ok(video) = TimestampedFile::new_ymd(video) {
+            timelapse_videos.push(TimelapseVideo::load(video, files).await);
+        }
+    }
+
+    if timelapse_videos.len() != num_videos {
+        warn!(
+            "Could not figure out the timestamp of some video files ({} out of {})",
+            timelapse_videos.len(),
+            num_videos
+        );
+    }
+
+    timelapse_videos.sort_unstable_by_key(|video| video.video.timestamp);
+    timelapse_videos
 }
 
 /// Create a worker
@@ -266,88 +858,151 @@ pub fn spawn_worker(options: TimelapserOptions) {
 
     tokio::spawn(async move {
         info!("Timelapsifying forever");
+        recover(&options).await;
+
+        let jobs = JobStore::new(&options.jobs_folder);
 
         loop {
             do_work(&options).await;
 
-            let sleep_duration = duration_until_tomorrow_night_01();
+            // Wake up early for retries of failed jobs.
+            let next_retry = jobs.next_retry().await.ok().flatten();
+            let sleep_duration =
+                schedule::sleep_duration(&options.schedule, options.clock.now(), next_retry);
             debug!("Sleeping for {:?}", sleep_duration);
 
-            time::sleep(sleep_duration).await;
+            tokio::select! {
+                _ = time::sleep(sleep_duration) => {}
+                _ = options.trigger.triggered() => info!("Triggered, running now"),
+            }
         }
     });
 }
 
-async fn ffmpeg_make_clip(images: &[TimestampedFile], output_mp4: &str) -> Output {
-    let file_name = "images-input.txt";
-
-    let mut f = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(file_name)
-        .await
-        .expect("should be able to create file");
-
-    for image in images {
-        let to_write = format!(
-            "file '{}'\n",
-            image
-                .file
-                .to_str()
-                .expect("should be able to get file name")
-        );
-
-        f.write(to_write.as_bytes()).await.expect("write ok");
-    }
-
-    Command::new("ffmpeg")
-        .args([
-            "-y", "-f", "image2", "-r", "60", "-f", "concat", "-y", "-safe", "0", "-i", file_name,
-            "-vcodec", "libx264", "-crf", "25", "-pix_fmt", "yuv420p", output_mp4,
-        ])
-        .output()
-        .await
-        .expect("Couldn't run ffmpeg properly")
-}
-
 /// Sort images such that earlier timestamped images
 /// come first.
 pub fn sort_files_by_timestamp(files: &mut [TimestampedFile]) {
     files.sort_unstable_by_key(|file| file.timestamp);
 }
 
-// In order to compare on a day-to-day basis, floor other components.
-fn floor_to_day(timestamp: &DateTime<Local>) -> DateTime<Local> {
-    timestamp
-        .with_hour(0)
-        .unwrap()
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap()
-}
-
-/// Takes a vector of sorted images, and returns all that came before the day of the given timestamp.
-fn images_before_day_of(
+/// Takes a vector of sorted images, and returns all that came before the given day.
+fn images_before_day(
     images: Vec<TimestampedFile>,
-    timestamp: DateTime<Local>,
+    day: NaiveDate,
+    time_zone: Option<chrono_tz::Tz>,
 ) -> Vec<TimestampedFile> {
-    let timestamp = floor_to_day(&timestamp);
-
     images
         .into_iter()
-        .take_while(|image| image.timestamp < timestamp)
+        .take_while(|image| day_of(&image.timestamp, time_zone) < day)
         .collect()
 }
 
+// The monday of the week.
+fn floor_to_week(day: NaiveDate) -> NaiveDate {
+    day - chrono::Duration::days(day.weekday().num_days_from_monday().into())
+}
+
+// The first day of the month.
+fn floor_to_month(day: NaiveDate) -> NaiveDate {
+    day.with_day(1).expect("every month has a first day")
+}
+
 /// Group a vector of sorted images into groups by the day.
-fn group_by_day(images: Vec<TimestampedFile>) -> BTreeMap<DateTime<Local>, Vec<TimestampedFile>> {
-    let mut groups = BTreeMap::<DateTime<Local>, Vec<TimestampedFile>>::new();
+fn group_by_day(
+    images: Vec<TimestampedFile>,
+    time_zone: Option<chrono_tz::Tz>,
+) -> BTreeMap<NaiveDate, Vec<TimestampedFile>> {
+    group_by(images, time_zone, |day| day)
+}
+
+/// Group a vector of sorted images by the given flooring of their days.
+fn group_by(
+    images: Vec<TimestampedFile>,
+    time_zone: Option<chrono_tz::Tz>,
+    floor: fn(NaiveDate) -> NaiveDate,
+) -> BTreeMap<NaiveDate, Vec<TimestampedFile>> {
+    let mut groups = BTreeMap::<NaiveDate, Vec<TimestampedFile>>::new();
 
     for image in images {
-        let t = floor_to_day(&image.timestamp);
-        groups.entry(t).or_default().push(image);
+        let day = floor(day_of(&image.timestamp, time_zone));
+        groups.entry(day).or_default().push(image);
+    }
+
+    groups
+}
+
+/// A calendar month, such as July 2022.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Month {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl Month {
+    /// `None` if the month is not in `1..=12`.
+    pub fn new(year: i32, month: u32) -> Option<Self> {
+        NaiveDate::from_ymd_opt(year, month, 1).map(|_| Self { year, month })
+    }
+
+    pub fn of<Tz: TimeZone>(timestamp: &DateTime<Tz>) -> Self {
+        Self {
+            year: timestamp.year(),
+            month: timestamp.month(),
+        }
+    }
+
+    pub fn first_day(&self) -> NaiveDate {
+        NaiveDate::from_ymd_opt(self.year, self.month, 1)
+            .expect("month should be in 1..=12, see Month::new")
+    }
+
+    pub fn next(&self) -> Self {
+        match self.month {
+            12 => Self {
+                year: self.year + 1,
+                month: 1,
+            },
+            month => Self {
+                year: self.year,
+                month: month + 1,
+            },
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        match self.month {
+            1 => Self {
+                year: self.year - 1,
+                month: 12,
+            },
+            month => Self {
+                year: self.year,
+                month: month - 1,
+            },
+        }
+    }
+
+    pub fn num_days(&self) -> u32 {
+        (self.next().first_day() - self.first_day()).num_days() as u32
+    }
+
+    /// Every day in the month, in order.
+    pub fn days(&self) -> impl Iterator<Item = NaiveDate> {
+        let first = self.first_day();
+        (0..self.num_days()).map(move |offset| first + chrono::Duration::days(offset.into()))
+    }
+}
+
+/// Group things with timestamps by the month they are from.
+/// Within a month the order is kept.
+pub fn group_by_month<T: AsRef<TimestampedFile>>(
+    files: impl IntoIterator<Item = T>,
+) -> BTreeMap<Month, Vec<T>> {
+    let mut groups = BTreeMap::<Month, Vec<T>>::new();
+
+    for file in files {
+        let month = Month::of(&file.as_ref().timestamp);
+        groups.entry(month).or_default().push(file);
     }
 
     groups
@@ -364,8 +1019,29 @@ pub enum TimelapsifyError {
     #[error("No match")]
     RegexNoMatch,
 
+    #[error("Not a valid time: {0}")]
+    InvalidTime(String),
+
     #[error("Parse oh no: {0:?}")]
     ParseOhNo(#[from] ParseIntError),
+
+    #[error("IO: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Image: {0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Background task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+
+    #[error("Encoding failed: {0}")]
+    Encode(#[from] encoder::EncodeError),
+
+    #[error("Overlay failed: {0}")]
+    Overlay(#[from] overlay::OverlayError),
 }
 
 impl TimelapsifyError {
@@ -383,76 +1059,72 @@ impl TimelapsifyError {
 /// Async because this will run in a worker on the web server,
 /// and we don't want to be blocking threads.
 #[instrument(skip(folder), fields(dir = ?folder.as_ref()))]
-async fn candidates<P: AsRef<Path>>(folder: P) -> Vec<TimestampedFile> {
+async fn candidates<P: AsRef<Path>>(folder: P) -> Result<Vec<TimestampedFile>, TimelapsifyError> {
     debug!("Looking for candidates in folder {:?}", folder.as_ref());
-    let mut dir_stream = fs::read_dir(folder)
-        .await
-        .expect("should be able to read given folder");
+    let mut dir_stream = fs::read_dir(folder).await?;
 
     let mut candidates = vec![];
 
-    while let Ok(Some(entry)) = dir_stream.next_entry().await {
-        let file_metadata = entry
-            .metadata()
-            .await
-            .expect("should be able to get file metadata");
-
-        if !file_metadata.is_file() {
+    while let Some(entry) = dir_stream.next_entry().await? {
+        let path = entry.path();
+        let is_image = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some(ext) if IMAGE_EXTENSIONS.contains(&ext)
+        );
+        if !entry.file_type().await?.is_file() || !is_image {
             continue;
         }
 
-        if let Ok(timestamped_file) = TimestampedFile::new_ymd_hms(entry.path()) {
+        if let Ok(timestamped_file) = TimestampedFile::new_ymd_hms(path) {
             candidates.push(timestamped_file);
         }
     }
 
     debug!("Candidates: {}", candidates.len());
-    candidates
+    Ok(candidates)
 }
 
-async fn move_all<P1: AsRef<Path>, P2: AsRef<Path>>(images: &[P1], output_folder: P2) {
+async fn move_all<P1: AsRef<Path>, P2: AsRef<Path>>(
+    images: &[P1],
+    output_folder: P2,
+) -> Result<(), TimelapsifyError> {
     for image in images {
-        trace!(
-            "Moving {:?} to {:?}",
-            image.as_ref(),
-            output_folder.as_ref()
-        );
+        let image = image.as_ref();
+        trace!("Moving {:?} to {:?}", image, output_folder.as_ref());
 
-        let out_folder = output_folder.as_ref().to_str().expect("out folder ok");
+        let file_name = image
+            .file_name()
+            .ok_or_else(|| TimelapsifyError::file_option_issue(image))?;
 
-        let out = format!(
-            "{out_folder}/{}",
-            image
-                .as_ref()
-                .file_name()
-                .expect("should be able to get file name")
-                .to_str()
-                .expect("&str ok")
-        );
+        fs::rename(image, output_folder.as_ref().join(file_name)).await?;
 
-        fs::rename(&image, out)
-            .await
-            .expect("should be able to move to processed folder");
+        // The capture metadata goes along with its image.
+        let sidecar = capture::sidecar_path(image);
+        if sidecar.exists() {
+            fs::rename(
+                &sidecar,
+                capture::sidecar_path(&output_folder.as_ref().join(file_name)),
+            )
+            .await?;
+        }
     }
+
+    Ok(())
 }
 
 /// Count the number of files with any of the given extensions in the given folder.
 #[instrument(skip(folder), fields(dir = ?folder.as_ref()))]
-pub async fn files_of_ext_in<P: AsRef<Path>>(folder: P, exts: &[&'static str]) -> Vec<PathBuf> {
+pub async fn files_of_ext_in<P: AsRef<Path>>(
+    folder: P,
+    exts: &[&'static str],
+) -> Result<Vec<PathBuf>, TimelapsifyError> {
     debug!("Looking for {:?} in {:?}", exts, folder.as_ref());
-    let mut dir_stream = fs::read_dir(folder)
-        .await
-        .expect("should be able to read given folder");
+    let mut dir_stream = fs::read_dir(folder).await?;
 
     let mut files = vec![];
 
-    while let Ok(Some(entry)) = dir_stream.next_entry().await {
-        let file_metadata = entry
-            .metadata()
-            .await
-            .expect("should be able to get file metadata");
-
-        if !file_metadata.is_file() {
+    while let Some(entry) = dir_stream.next_entry().await? {
+        if !entry.file_type().await?.is_file() {
             continue;
         }
 
@@ -462,7 +1134,7 @@ pub async fn files_of_ext_in<P: AsRef<Path>>(folder: P, exts: &[&'static str]) -
             None => continue,
         };
 
-        let ext = ext.to_str().expect("&str ok").to_ascii_lowercase();
+        let ext = ext.to_string_lossy().to_ascii_lowercase();
         trace!("Comparing {ext} to {exts:?}");
 
         if !exts.iter().any(|&e| e == ext.as_str()) {
@@ -473,21 +1145,176 @@ pub async fn files_of_ext_in<P: AsRef<Path>>(folder: P, exts: &[&'static str]) -
     }
 
     debug!("Found {}", files.len());
-    files
+    Ok(files)
 }
 
 #[cfg(test)]
 mod tests {
     use tracing::metadata::LevelFilter;
 
+    use chrono::Timelike;
+
     use super::*;
+    use schedule::ManualClock;
+
+    #[test]
+    fn test_months() {
+        let july = Month::new(2022, 7).unwrap();
+        assert_eq!(july.num_days(), 31);
+        assert_eq!(july.previous(), Month::new(2022, 6).unwrap());
+        assert_eq!(
+            Month::new(2022, 12).unwrap().next(),
+            Month::new(2023, 1).unwrap()
+        );
+        assert_eq!(
+            Month::new(2023, 1).unwrap().previous(),
+            Month::new(2022, 12).unwrap()
+        );
+        assert_eq!(Month::new(2024, 2).unwrap().num_days(), 29);
+        assert_eq!(Month::new(2022, 13), None);
+
+        let days = july.days().collect::<Vec<_>>();
+        assert_eq!(days.len(), 31);
+        assert_eq!(
+            days.first(),
+            Some(&NaiveDate::from_ymd_opt(2022, 7, 1).unwrap())
+        );
+        assert_eq!(
+            days.last(),
+            Some(&NaiveDate::from_ymd_opt(2022, 7, 31).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_group_by_month() {
+        let videos = [
+            "2022-06-30.mp4",
+            "2022-07-01.mp4",
+            "2022-07-12.mp4",
+            "2023-07-01.mp4",
+        ]
+        .map(|name| TimestampedFile::new_ymd(PathBuf::from(name)).unwrap());
+
+        let groups = group_by_month(&videos);
+        let sizes = groups
+            .iter()
+            .map(|(month, videos)| (month.year, month.month, videos.len()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(sizes, vec![(2022, 6, 1), (2022, 7, 2), (2023, 7, 1)]);
+    }
 
     #[test]
-    fn test_time_until_tomorrow() {
-        let secs = duration_until_tomorrow_night_01().as_secs_f32();
+    fn test_floor_to_periods() {
+        // A wednesday
+        let day = NaiveDate::from_ymd_opt(2022, 7, 13).unwrap();
+
+        assert_eq!(
+            floor_to_week(day),
+            NaiveDate::from_ymd_opt(2022, 7, 11).unwrap()
+        );
+        assert_eq!(
+            floor_to_month(day),
+            NaiveDate::from_ymd_opt(2022, 7, 1).unwrap()
+        );
 
-        let hrs = secs / 60.0 / 60.0;
-        dbg!(hrs);
+        // Weeks may start in the previous month
+        let day = NaiveDate::from_ymd_opt(2022, 8, 3).unwrap();
+        assert_eq!(
+            floor_to_week(day),
+            NaiveDate::from_ymd_opt(2022, 8, 1).unwrap()
+        );
+        let day = NaiveDate::from_ymd_opt(2022, 7, 2).unwrap();
+        assert_eq!(
+            floor_to_week(day),
+            NaiveDate::from_ymd_opt(2022, 6, 27).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_timestamps() {
+        let parse = |name: &str| TimestampedFile::new_ymd_hms(PathBuf::from(name));
+        let utc = |h, m| Utc.ymd(2022, 7, 12).and_hms(h, m, 0);
+
+        // The same time, written with different offsets
+        for name in [
+            "2022-07-12_22-44-00+0200.jpg",
+            "2022-07-12_20-44-00Z.jpg",
+            "2022-07-12_16-14-00-0430.jpg",
+        ] {
+            assert_eq!(parse(name).unwrap().timestamp, utc(20, 44), "{name}");
+        }
+
+        // Older names are in the server's local time
+        assert_eq!(
+            parse("2022-07-12_22-44-00.jpg").unwrap().timestamp,
+            Local.ymd(2022, 7, 12).and_hms(22, 44, 0)
+        );
+
+        assert!(matches!(
+            parse("2022-13-12_22-44-00.jpg"),
+            Err(TimelapsifyError::InvalidTime(_))
+        ));
+        assert!(matches!(
+            parse("2022-07-12_22-44-00+9900.jpg"),
+            Err(TimelapsifyError::InvalidTime(_))
+        ));
+        assert!(matches!(
+            parse("basil.jpg"),
+            Err(TimelapsifyError::RegexNoMatch)
+        ));
+
+        // Names made for the same time parse back to it
+        let now = Local::now().with_nanosecond(0).unwrap();
+        let name = format!("{}.jpg", now.format("%F_%H-%M-%S%z"));
+        assert_eq!(parse(&name).unwrap().timestamp, now);
+    }
+
+    #[test]
+    fn test_dst() {
+        use chrono_tz::Europe::Oslo;
+
+        // Clocks went back from 03:00 to 02:00 on the 30th of October 2022,
+        // so images from 02:30 and an hour later had the same local time.
+        let first = TimestampedFile::new_ymd_hms("2022-10-30_02-30-00+0200.jpg".into()).unwrap();
+        let second = TimestampedFile::new_ymd_hms("2022-10-30_02-30-00+0100.jpg".into()).unwrap();
+        assert_eq!(
+            second.timestamp - first.timestamp,
+            chrono::Duration::hours(1)
+        );
+
+        // A day with 25 hours is still one day.
+        let day = NaiveDate::from_ymd_opt(2022, 10, 30).unwrap();
+        let images = [
+            "2022-10-29_23-59-59+0200.jpg",
+            "2022-10-30_00-00-00+0200.jpg",
+            "2022-10-30_02-30-00+0200.jpg",
+            "2022-10-30_02-30-00+0100.jpg",
+            "2022-10-30_23-59-59+0100.jpg",
+            "2022-10-31_00-00-00+0100.jpg",
+        ]
+        .map(|name| TimestampedFile::new_ymd_hms(name.into()).unwrap())
+        .to_vec();
+
+        let days = group_by_day(images.clone(), Some(Oslo));
+        assert_eq!(
+            days.iter()
+                .map(|(day, images)| (*day, images.len()))
+                .collect::<Vec<_>>(),
+            vec![
+                (day.pred_opt().unwrap(), 1),
+                (day, 4),
+                (day.succ_opt().unwrap(), 1)
+            ]
+        );
+
+        // The day boundary follows the time zone, not the server.
+        let utc_days = group_by_day(images.clone(), Some(chrono_tz::UTC));
+        assert_eq!(utc_days[&day.pred_opt().unwrap()].len(), 2);
+        assert_eq!(
+            images_before_day(images, day.succ_opt().unwrap(), Some(Oslo)).len(),
+            5
+        );
     }
 
     #[tokio::test]
@@ -496,62 +1323,735 @@ mod tests {
             .with_max_level(LevelFilter::DEBUG)
             .init();
 
-        let images_unprocessed_path = format!("{}/test_images", env!("CARGO_MANIFEST_DIR"));
-        let images_processed_path = format!("{}/test_images_processed", env!("CARGO_MANIFEST_DIR"));
+        let root = std::env::temp_dir().join(format!("timelapsifier-make-{}", std::process::id()));
+        let images_unprocessed_path = root.join("upload");
+        let images_processed_path = root.join("processed");
+        let output_path = root.join("days");
+        for folder in [
+            &images_unprocessed_path,
+            &images_processed_path,
+            &output_path,
+        ] {
+            fs::create_dir_all(folder).await.unwrap();
+        }
 
-        // For test purposes move any images back
-        let images_processed = files_of_ext_in(&images_processed_path, &["jpg"]).await;
-        move_all(&images_processed, &images_unprocessed_path).await;
+        // Copied, so the fixtures stay where they are.
+        let fixtures = format!("{}/test_images", env!("CARGO_MANIFEST_DIR"));
+        for image in files_of_ext_in(&fixtures, &["jpg"]).await.unwrap() {
+            let name = image.file_name().unwrap();
+            fs::copy(&image, images_unprocessed_path.join(name))
+                .await
+                .unwrap();
+        }
 
-        let mut candidates = candidates(images_unprocessed_path).await;
+        let mut candidates = candidates(&images_unprocessed_path).await.unwrap();
 
         sort_files_by_timestamp(&mut candidates);
 
-        let before_day = images_before_day_of(
-            candidates.clone(),
-            Local.ymd(2022, 07, 12).and_hms(05, 30, 25),
-        );
-        let groups = group_by_day(before_day);
+        let before_day =
+            images_before_day(candidates.clone(), NaiveDate::from_ymd(2022, 7, 12), None);
+        let groups = group_by_day(before_day, None);
         // No images in test folder from before 2022-07-12
         assert_eq!(groups.keys().len(), 0);
 
-        let before_day = images_before_day_of(
-            candidates.clone(),
-            Local.ymd(2022, 07, 13).and_hms(10, 50, 50),
-        );
-        let groups = group_by_day(before_day);
+        let before_day =
+            images_before_day(candidates.clone(), NaiveDate::from_ymd(2022, 7, 13), None);
+        let groups = group_by_day(before_day, None);
         // One group: 2022-07-12
         assert_eq!(groups.keys().len(), 1);
 
-        let before_day = images_before_day_of(
-            candidates.clone(),
-            Local.ymd(2022, 07, 14).and_hms(00, 00, 00),
-        );
-        let groups = group_by_day(before_day);
+        let before_day =
+            images_before_day(candidates.clone(), NaiveDate::from_ymd(2022, 7, 14), None);
+        let groups = group_by_day(before_day, None);
         // Two groups: 2022-07-12, 2022-07-13
         assert_eq!(groups.keys().len(), 2);
 
-        let before_day = images_before_day_of(
-            candidates.clone(),
-            Local.ymd(2025, 01, 01).and_hms(00, 00, 00),
-        );
-        let groups = group_by_day(before_day);
+        let before_day =
+            images_before_day(candidates.clone(), NaiveDate::from_ymd(2025, 1, 1), None);
+        let groups = group_by_day(before_day, None);
         // Two groups: 2022-07-12, 2022-07-13
         assert_eq!(groups.keys().len(), 2);
 
+        // Small gifs, so this runs quickly and without ffmpeg
+        let encoder = Arc::new(encoder::GifEncoder {
+            max_size: (160, 90),
+        });
+
         for (dt, images) in groups {
-            let output = ffmpeg_make_clip(
-                &images,
-                &format!("output_mp4/days/{}.mp4", dt.format("%Y-%m-%d")),
+            let output = output_path.join(format!("{}.gif", dt.format("%Y-%m-%d")));
+            encoder::encode(
+                encoder.clone(),
+                images.clone(),
+                output,
+                EncodingProfile::default(),
+                60,
             )
-            .await;
+            .await
+            .unwrap();
 
-            println!("{output:?}");
-            assert!(output.status.success());
-            move_all(&images, &images_processed_path).await;
+            move_all(&images, &images_processed_path).await.unwrap();
         }
 
-        let videos = files_of_ext_in("output_mp4/days", &["mp4"]).await;
+        let videos = files_of_ext_in(&output_path, &["gif"]).await.unwrap();
         assert_eq!(2, videos.len());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_do_work() {
+        let root = std::env::temp_dir().join(format!("timelapsifier-work-{}", std::process::id()));
+        let unprocessed = root.join("upload");
+        fs::create_dir_all(&unprocessed).await.unwrap();
+
+        for name in [
+            "2022-07-12_10-00-00.jpg",
+            "2022-07-12_10-05-00.jpg",
+            "2022-07-12_10-10-00.jpg",
+            "2022-07-13_10-00-00.jpg",
+            "2022-07-13_10-05-00.jpg",
+        ] {
+            image::RgbImage::new(64, 36)
+                .save(unprocessed.join(name))
+                .unwrap();
+        }
+
+        let encoder = Arc::new(encoder::mock::MockEncoder::default());
+        let options = TimelapserOptions {
+            unprocessed_images_folder: unprocessed.clone(),
+            processed_images_folder: root.join("processed"),
+            timelapse_output_folder: root.join("days"),
+            profile: EncodingProfile {
+                also_webm: true,
+                ..EncodingProfile::with_fps(60)
+            },
+            jobs_folder: root.join("jobs"),
+            retry: RetryPolicy::default(),
+            weekly: CompilationOptions {
+                output_folder: root.join("weeks"),
+                profile: EncodingProfile::with_fps(30),
+                every_nth_image: 2,
+            },
+            monthly: CompilationOptions {
+                output_folder: root.join("months"),
+                profile: EncodingProfile {
+                    frame_rate: profile::FrameRate::TargetDuration {
+                        seconds: 1.0,
+                        min_fps: 1,
+                        max_fps: 10,
+                    },
+                    ..Default::default()
+                },
+                every_nth_image: 4,
+            },
+            encoder: encoder.clone(),
+            filter: None,
+            deflicker: None,
+            overlay: None,
+            day_boundary: None,
+            retention: None,
+            schedule: Schedule::default(),
+            clock: Arc::new(schedule::SystemClock),
+            trigger: Trigger::default(),
+            timelapse_videos: Default::default(),
+        };
+
+        do_work(&options).await;
+
+        // Each video is made in a working folder of its own.
+        let encoded = encoder.encoded.lock().unwrap().clone();
+        assert_eq!(
+            encoded,
+            vec![
+                (root.join("days/.work-2022-07-12.mp4/2022-07-12.mp4"), 3, 60),
+                (
+                    root.join("days/.work-2022-07-12.mp4/2022-07-12.webm"),
+                    3,
+                    60
+                ),
+                (root.join("days/.work-2022-07-13.mp4/2022-07-13.mp4"), 2, 60),
+                (
+                    root.join("days/.work-2022-07-13.mp4/2022-07-13.webm"),
+                    2,
+                    60
+                ),
+                (
+                    root.join("weeks/.work-2022-07-11.mp4/2022-07-11.mp4"),
+                    3,
+                    30
+                ),
+                // Two frames over a second
+                (
+                    root.join("months/.work-2022-07-01.mp4/2022-07-01.mp4"),
+                    2,
+                    2
+                ),
+            ]
+        );
+
+        assert!(candidates(&unprocessed).await.unwrap().is_empty());
+        assert!(!root.join("days/.work-2022-07-12.mp4").exists());
+
+        let jobs = JobStore::new(root.join("jobs")).all().await.unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert!(jobs.iter().all(|job| job.state == JobState::Done));
+        assert_eq!(jobs[0].video, Some(root.join("days/2022-07-12.mp4")));
+
+        let timelapses = options.timelapse_videos.read().await.clone();
+        assert_eq!(timelapses.days.len(), 2);
+        assert_eq!(timelapses.weeks.len(), 1);
+        assert_eq!(timelapses.months.len(), 1);
+        assert!(timelapses.days.iter().all(|day| day.poster.is_some()));
+        assert_eq!(
+            timelapses.days[0].alternatives,
+            vec![root.join("days/2022-07-12.webm")]
+        );
+
+        let meta = timelapses.days[0].meta.as_ref().unwrap();
+        assert_eq!(meta.frame_count, 3);
+        assert_eq!(meta.profile, Some(options.profile.clone()));
+
+        // Nothing new, so nothing more to encode
+        do_work(&options).await;
+        assert_eq!(encoder.encoded.lock().unwrap().len(), 6);
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    fn job_options(
+        root: &Path,
+        encoder: Arc<encoder::mock::MockEncoder>,
+        clock: Arc<ManualClock>,
+    ) -> TimelapserOptions {
+        TimelapserOptions {
+            unprocessed_images_folder: root.join("upload"),
+            processed_images_folder: root.join("processed"),
+            timelapse_output_folder: root.join("days"),
+            profile: EncodingProfile::default(),
+            jobs_folder: root.join("jobs"),
+            retry: RetryPolicy {
+                max_attempts: 2,
+                first_delay: chrono::Duration::hours(1),
+                max_delay: chrono::Duration::hours(1),
+            },
+            weekly: CompilationOptions {
+                output_folder: root.join("weeks"),
+                profile: EncodingProfile::default(),
+                every_nth_image: 1,
+            },
+            monthly: CompilationOptions {
+                output_folder: root.join("months"),
+                profile: EncodingProfile::default(),
+                every_nth_image: 1,
+            },
+            encoder,
+            filter: None,
+            deflicker: None,
+            overlay: None,
+            day_boundary: None,
+            retention: None,
+            schedule: Schedule::default(),
+            clock,
+            trigger: Trigger::default(),
+            timelapse_videos: Default::default(),
+        }
+    }
+
+    async fn upload_images(folder: &Path, names: &[&str]) {
+        fs::create_dir_all(folder).await.unwrap();
+        for name in names {
+            image::RgbImage::new(64, 36)
+                .save(folder.join(name))
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_day_does_not_block() {
+        let root = std::env::temp_dir().join(format!("timelapsifier-fail-{}", std::process::id()));
+        let encoder = Arc::new(encoder::mock::MockEncoder::default());
+        // Early in the week, so no compilations get in the way.
+        let now = Local.ymd(2022, 7, 14).and_hms(1, 0, 0);
+        let clock = Arc::new(ManualClock::new(now));
+        let options = job_options(&root, encoder.clone(), clock.clone());
+        let jobs = JobStore::new(&options.jobs_folder);
+
+        upload_images(
+            &options.unprocessed_images_folder,
+            &[
+                "2022-07-12_10-00-00.jpg",
+                "2022-07-12_10-05-00.jpg",
+                "2022-07-13_10-00-00.jpg",
+            ],
+        )
+        .await;
+
+        let day_12 = NaiveDate::from_ymd(2022, 7, 12);
+
+        encoder
+            .failing
+            .lock()
+            .unwrap()
+            .push("2022-07-12.mp4".to_string());
+        do_work(&options).await;
+
+        // The day after was made anyway, and the failed day kept its images.
+        assert!(root.join("days/2022-07-13.mp4").exists());
+        assert!(!root.join("days/2022-07-12.mp4").exists());
+        assert!(!root.join("days/.work-2022-07-12.mp4").exists());
+        assert_eq!(
+            candidates(&options.unprocessed_images_folder)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let job = jobs.load(day_12).await.unwrap().unwrap();
+        assert_eq!(job.state, JobState::Failed);
+        assert_eq!(job.attempts, 1);
+        assert!(job.last_error.unwrap().contains("mock"));
+        assert_eq!(job.retry_at, Some(now + chrono::Duration::hours(1)));
+
+        // Too early to retry
+        do_work(&options).await;
+        assert_eq!(jobs.load(day_12).await.unwrap().unwrap().attempts, 1);
+
+        encoder.failing.lock().unwrap().clear();
+        clock.advance(chrono::Duration::hours(1));
+        do_work(&options).await;
+
+        let job = jobs.load(day_12).await.unwrap().unwrap();
+        assert_eq!(job.state, JobState::Done);
+        assert_eq!(job.attempts, 2);
+        assert!(root.join("days/2022-07-12.mp4").exists());
+        assert!(candidates(&options.unprocessed_images_folder)
+            .await
+            .unwrap()
+            .is_empty());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_late_images() {
+        let root = std::env::temp_dir().join(format!("timelapsifier-late-{}", std::process::id()));
+        let encoder = Arc::new(encoder::mock::MockEncoder::default());
+        // Early in the week, so no compilations get in the way.
+        let now = Local.ymd(2022, 7, 14).and_hms(1, 0, 0);
+        let options = job_options(&root, encoder.clone(), Arc::new(ManualClock::new(now)));
+        let jobs = JobStore::new(&options.jobs_folder);
+        let day_12 = NaiveDate::from_ymd(2022, 7, 12);
+        let days_encoded = || {
+            encoder
+                .encoded
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(video, ..)| video.ends_with("2022-07-12.mp4"))
+                .map(|(_, frames, _)| *frames)
+                .collect::<Vec<_>>()
+        };
+
+        upload_images(
+            &options.unprocessed_images_folder,
+            &["2022-07-12_10-00-00.jpg"],
+        )
+        .await;
+        do_work(&options).await;
+        assert_eq!(days_encoded(), vec![1]);
+        assert_eq!(
+            jobs.load(day_12).await.unwrap().unwrap().state,
+            JobState::Done
+        );
+
+        // The video is made again from the image processed before and the late one.
+        upload_images(
+            &options.unprocessed_images_folder,
+            &["2022-07-12_10-05-00.jpg"],
+        )
+        .await;
+        do_work(&options).await;
+        assert_eq!(days_encoded(), vec![1, 2]);
+
+        let job = jobs.load(day_12).await.unwrap().unwrap();
+        assert_eq!(job.state, JobState::Done);
+        assert_eq!(job.attempts, 1);
+        assert!(root.join("days/2022-07-12.mp4").exists());
+        assert!(candidates(&options.unprocessed_images_folder)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            candidates(&options.processed_images_folder)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Nothing new
+        do_work(&options).await;
+        assert_eq!(days_encoded(), vec![1, 2]);
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_late_images_after_giving_up() {
+        let root =
+            std::env::temp_dir().join(format!("timelapsifier-given-up-{}", std::process::id()));
+        let encoder = Arc::new(encoder::mock::MockEncoder::default());
+        let now = Local.ymd(2022, 7, 14).and_hms(1, 0, 0);
+        let clock = Arc::new(ManualClock::new(now));
+        let options = job_options(&root, encoder.clone(), clock.clone());
+        let jobs = JobStore::new(&options.jobs_folder);
+        let day_12 = NaiveDate::from_ymd_opt(2022, 7, 12).unwrap();
+
+        upload_images(
+            &options.unprocessed_images_folder,
+            &["2022-07-12_10-00-00.jpg"],
+        )
+        .await;
+        encoder
+            .failing
+            .lock()
+            .unwrap()
+            .push("2022-07-12.mp4".to_string());
+        do_work(&options).await;
+        clock.advance(chrono::Duration::hours(1));
+        do_work(&options).await;
+
+        let job = jobs.load(day_12).await.unwrap().unwrap();
+        assert!(job.is_given_up());
+        assert_eq!(job.attempts, 2);
+
+        // The images left behind are not new, so it stays given up on.
+        encoder.failing.lock().unwrap().clear();
+        clock.advance(chrono::Duration::days(1));
+        do_work(&options).await;
+        assert_eq!(jobs.load(day_12).await.unwrap().unwrap(), job);
+
+        upload_images(
+            &options.unprocessed_images_folder,
+            &["2022-07-12_10-05-00.jpg"],
+        )
+        .await;
+        do_work(&options).await;
+
+        let job = jobs.load(day_12).await.unwrap().unwrap();
+        assert_eq!(job.state, JobState::Done);
+        assert_eq!(job.attempts, 1);
+        assert!(root.join("days/2022-07-12.mp4").exists());
+        assert!(candidates(&options.unprocessed_images_folder)
+            .await
+            .unwrap()
+            .is_empty());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compilation_made_again() {
+        let root = std::env::temp_dir().join(format!("timelapsifier-again-{}", std::process::id()));
+        let encoder = Arc::new(encoder::mock::MockEncoder::default());
+        // The week of the images is over, the month is not.
+        let clock = Arc::new(ManualClock::new(Local.ymd(2022, 7, 19).and_hms(1, 0, 0)));
+        let options = job_options(&root, encoder.clone(), clock.clone());
+        let week = root.join("weeks/2022-07-11.mp4");
+        let weeks_encoded = || {
+            encoder
+                .encoded
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(video, ..)| video.ends_with("2022-07-11.mp4"))
+                .map(|(_, frames, _)| *frames)
+                .collect::<Vec<_>>()
+        };
+
+        upload_images(
+            &options.unprocessed_images_folder,
+            &["2022-07-12_10-00-00.jpg", "2022-07-13_10-00-00.jpg"],
+        )
+        .await;
+
+        // Not made while a day of the week is still to be retried.
+        encoder
+            .failing
+            .lock()
+            .unwrap()
+            .push("2022-07-13.mp4".to_string());
+        do_work(&options).await;
+        assert!(root.join("days/2022-07-12.mp4").exists());
+        assert!(!week.exists());
+
+        encoder.failing.lock().unwrap().clear();
+        clock.advance(chrono::Duration::hours(1));
+        do_work(&options).await;
+        assert!(week.exists());
+        assert_eq!(weeks_encoded(), vec![2]);
+
+        // Nothing new
+        do_work(&options).await;
+        assert_eq!(weeks_encoded(), vec![2]);
+
+        // An image of the week which arrived late.
+        upload_images(
+            &options.unprocessed_images_folder,
+            &["2022-07-12_10-05-00.jpg"],
+        )
+        .await;
+        do_work(&options).await;
+        assert_eq!(weeks_encoded(), vec![2, 3]);
+
+        // Images removed is nothing new.
+        fs::remove_file(root.join("processed/2022-07-13_10-00-00.jpg"))
+            .await
+            .unwrap();
+        do_work(&options).await;
+        assert_eq!(weeks_encoded(), vec![2, 3]);
+
+        // Made before sources were recorded.
+        fs::remove_dir_all(root.join("jobs/weekly")).await.unwrap();
+        do_work(&options).await;
+        assert_eq!(weeks_encoded(), vec![2, 3]);
+        assert!(root.join("jobs/weekly/2022-07-11.json").exists());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compilation_not_made_again_after_retention() {
+        let root =
+            std::env::temp_dir().join(format!("timelapsifier-retained-{}", std::process::id()));
+        let encoder = Arc::new(encoder::mock::MockEncoder::default());
+        // The week of the images is over.
+        let now = Local.ymd(2022, 7, 19).and_hms(1, 0, 0);
+        let options = job_options(&root, encoder.clone(), Arc::new(ManualClock::new(now)));
+
+        upload_images(
+            &options.unprocessed_images_folder,
+            &[
+                "2022-07-12_10-00-00.jpg",
+                "2022-07-12_10-05-00.jpg",
+                "2022-07-12_10-10-00.jpg",
+                "2022-07-13_10-00-00.jpg",
+            ],
+        )
+        .await;
+        do_work(&options).await;
+        assert!(root.join("weeks/2022-07-11.mp4").exists());
+        let encoded = encoder.encoded.lock().unwrap().len();
+
+        // The images of the 12th are thinned to the first of the hour.
+        let reports = retention::enforce(
+            RetentionOptions {
+                policies: vec![retention::RetentionPolicy {
+                    keep_days: Some(6),
+                    thin_to_hourly: true,
+                    ..retention::RetentionPolicy::new(&options.processed_images_folder)
+                }],
+                dry_run: false,
+            },
+            now,
+        )
+        .await;
+        assert_eq!(reports[0].removed.len(), 2);
+        assert_eq!(
+            candidates(&options.processed_images_folder)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        make_compilations(&options, now).await;
+        assert_eq!(encoder.encoded.lock().unwrap().len(), encoded);
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recover() {
+        let root =
+            std::env::temp_dir().join(format!("timelapsifier-recover-{}", std::process::id()));
+        let encoder = Arc::new(encoder::mock::MockEncoder::default());
+        let now = Local.ymd(2022, 7, 14).and_hms(1, 0, 0);
+        let options = job_options(&root, encoder.clone(), Arc::new(ManualClock::new(now)));
+        let jobs = JobStore::new(&options.jobs_folder);
+
+        upload_images(
+            &options.unprocessed_images_folder,
+            &["2022-07-12_10-00-00.jpg", "2022-07-13_10-00-00.jpg"],
+        )
+        .await;
+
+        // The video of the 12th was made, but the worker stopped before moving its images.
+        let mut done = Job::new(NaiveDate::from_ymd(2022, 7, 12), now);
+        done.start(now);
+        done.finish(now, Some(root.join("days/2022-07-12.mp4")));
+        jobs.save(&done).await.unwrap();
+
+        // The worker stopped while making the 13th.
+        let mut interrupted = Job::new(NaiveDate::from_ymd(2022, 7, 13), now);
+        interrupted.start(now);
+        jobs.save(&interrupted).await.unwrap();
+        let work = root.join("days/.work-2022-07-13.mp4");
+        fs::create_dir_all(&work).await.unwrap();
+        fs::write(work.join("2022-07-13.mp4"), b"half a video")
+            .await
+            .unwrap();
+
+        recover(&options).await;
+        assert!(!work.exists());
+        assert_eq!(
+            jobs.load(interrupted.day).await.unwrap().unwrap().state,
+            JobState::Pending
+        );
+
+        do_work(&options).await;
+
+        // The done day is made again too, its image could just as well have arrived late.
+        let encoded = encoder.encoded.lock().unwrap().clone();
+        assert_eq!(
+            encoded,
+            vec![
+                (root.join("days/.work-2022-07-12.mp4/2022-07-12.mp4"), 1, 60),
+                (work.join("2022-07-13.mp4"), 1, 60)
+            ]
+        );
+        assert!(candidates(&options.unprocessed_images_folder)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            jobs.load(interrupted.day).await.unwrap().unwrap().state,
+            JobState::Done
+        );
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_trigger_worker() {
+        let root =
+            std::env::temp_dir().join(format!("timelapsifier-trigger-{}", std::process::id()));
+        let encoder = Arc::new(encoder::mock::MockEncoder::default());
+        let clock = Arc::new(ManualClock::new(Local.ymd(2022, 7, 14).and_hms(1, 0, 0)));
+        let options = job_options(&root, encoder, clock.clone());
+        let trigger = options.trigger.clone();
+        let videos = options.timelapse_videos.clone();
+
+        // The 14th is today, so it waits.
+        upload_images(
+            &options.unprocessed_images_folder,
+            &["2022-07-13_10-00-00.jpg", "2022-07-14_10-00-00.jpg"],
+        )
+        .await;
+
+        let days_made = |count: usize| {
+            let videos = videos.clone();
+            async move {
+                while videos.read().await.days.len() < count {
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                }
+            }
+        };
+        let timeout = std::time::Duration::from_secs(10);
+
+        spawn_worker(options);
+        tokio::time::timeout(timeout, days_made(1)).await.unwrap();
+
+        // The next run is almost a day away, unless triggered.
+        clock.advance(chrono::Duration::days(1));
+        trigger.run_now();
+        tokio::time::timeout(timeout, days_made(2)).await.unwrap();
+        assert!(root.join("days/2022-07-14.mp4").exists());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_make_video_preprocessed() {
+        let root =
+            std::env::temp_dir().join(format!("timelapsifier-overlay-{}", std::process::id()));
+        fs::create_dir_all(&root).await.unwrap();
+
+        let images = ["2022-07-12_10-00-00.jpg", "2022-07-12_10-05-00.jpg"].map(|name| {
+            let file = root.join(name);
+            image::RgbImage::from_pixel(320, 180, image::Rgb([128, 128, 128]))
+                .save(&file)
+                .unwrap();
+            TimestampedFile::new_ymd_hms(file).unwrap()
+        });
+
+        let encoder = Arc::new(encoder::GifEncoder::default());
+        let options = TimelapserOptions {
+            unprocessed_images_folder: root.clone(),
+            processed_images_folder: root.clone(),
+            timelapse_output_folder: root.clone(),
+            profile: EncodingProfile::default(),
+            jobs_folder: root.join("jobs"),
+            retry: RetryPolicy::default(),
+            weekly: CompilationOptions {
+                output_folder: root.clone(),
+                profile: EncodingProfile::default(),
+                every_nth_image: 1,
+            },
+            monthly: CompilationOptions {
+                output_folder: root.clone(),
+                profile: EncodingProfile::default(),
+                every_nth_image: 1,
+            },
+            encoder,
+            filter: None,
+            deflicker: Some(DeflickerOptions::default()),
+            overlay: Some(OverlayOptions {
+                timestamp_format: Some("%H:%M".to_string()),
+                ..OverlayOptions::new(overlay::TEST_FONT)
+            }),
+            day_boundary: None,
+            retention: None,
+            schedule: Schedule::default(),
+            clock: Arc::new(schedule::SystemClock),
+            trigger: Trigger::default(),
+            timelapse_videos: Default::default(),
+        };
+
+        let video = root.join("2022-07-12.gif");
+        make_video(&options, &images, &video, &options.profile)
+            .await
+            .unwrap();
+
+        // The overlay is drawn on the frames, not on the images themselves.
+        // It has a dark box behind the text.
+        let top_left = |file: &Path| image::open(file).unwrap().into_luma8().get_pixel(2, 2).0[0];
+        assert!(top_left(&images[0].file) > 100);
+        assert!(top_left(&meta::poster_path(&video)) < 100);
+
+        assert!(video.exists());
+        assert!(!root.join("2022-07-12.deflicker").exists());
+        assert!(!root.join("2022-07-12.overlay").exists());
+
+        let mut broken = options.overlay.clone().unwrap();
+        broken.font = root.join("no-such-font.ttf");
+        let options = TimelapserOptions {
+            overlay: Some(broken),
+            ..options
+        };
+        assert!(matches!(
+            make_video(
+                &options,
+                &images,
+                &root.join("2022-07-13.gif"),
+                &options.profile
+            )
+            .await,
+            Err(TimelapsifyError::Overlay(_))
+        ));
+        assert!(!root.join("2022-07-13.overlay").exists());
+
+        fs::remove_dir_all(&root).await.unwrap();
     }
 }
@@ -0,0 +1,161 @@
+//! Posters and metadata written alongside each timelapse video.
+//!
+//! For a video `2022-07-12.mp4` these are `2022-07-12.jpg` and `2022-07-12.json`.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, warn};
+
+use crate::{profile::EncodingProfile, TimelapsifyError, TimestampedFile};
+
+/// Posters are scaled down to fit within this size.
+const POSTER_SIZE: (u32, u32) = (640, 360);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoMeta {
+    pub frame_count: usize,
+    pub fps: u32,
+    pub first_capture: DateTime<Local>,
+    pub last_capture: DateTime<Local>,
+
+    /// Missing for videos made before profiles existed.
+    #[serde(default)]
+    pub profile: Option<EncodingProfile>,
+}
+
+impl VideoMeta {
+    /// Metadata for a video made from the given sorted images.
+    pub fn new(images: &[TimestampedFile], profile: &EncodingProfile, fps: u32) -> Option<Self> {
+        Some(Self {
+            frame_count: images.len(),
+            fps,
+            first_capture: images.first()?.timestamp,
+            last_capture: images.last()?.timestamp,
+            profile: Some(profile.clone()),
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frame_count as f64 / self.fps.max(1) as f64)
+    }
+}
+
+/// A timelapse video, and its poster and metadata if they exist.
+#[derive(Debug, Clone)]
+pub struct TimelapseVideo {
+    pub video: TimestampedFile,
+
+    /// The same video in other formats, such as a webm next to an mp4.
+    pub alternatives: Vec<PathBuf>,
+
+    pub poster: Option<PathBuf>,
+    pub meta: Option<VideoMeta>,
+}
+
+impl AsRef<TimestampedFile> for TimelapseVideo {
+    fn as_ref(&self) -> &TimestampedFile {
+        &self.video
+    }
+}
+
+impl TimelapseVideo {
+    /// Look for a poster and metadata next to the given video.
+    pub async fn load(video: TimestampedFile, alternatives: Vec<PathBuf>) -> Self {
+        let poster = poster_path(&video.file);
+        let poster = fs::metadata(&poster).await.ok().map(|_| poster);
+
+        let meta = read_sidecar(&video.file).await;
+
+        Self {
+            video,
+            alternatives,
+            poster,
+            meta,
+        }
+    }
+}
+
+pub fn poster_path(video: &Path) -> PathBuf {
+    video.with_extension("jpg")
+}
+
+pub fn sidecar_path(video: &Path) -> PathBuf {
+    video.with_extension("json")
+}
+
+pub async fn write_sidecar(video: &Path, meta: &VideoMeta) -> Result<(), TimelapsifyError> {
+    let path = sidecar_path(video);
+    debug!(?path, "Writing video metadata");
+
+    fs::write(path, serde_json::to_vec_pretty(meta)?).await?;
+
+    Ok(())
+}
+
+/// Read the metadata of a video.
+/// Videos made before metadata was written have none, so that is not an error.
+pub async fn read_sidecar(video: &Path) -> Option<VideoMeta> {
+    let path = sidecar_path(video);
+    let bytes = fs::read(&path).await.ok()?;
+
+    serde_json::from_slice(&bytes)
+        .map_err(|error| warn!(?path, ?error, "Bad video metadata"))
+        .ok()
+}
+
+/// Write a scaled down copy of the given image as the poster of the video.
+pub async fn write_poster(image: &Path, video: &Path) -> Result<(), TimelapsifyError> {
+    let image = image.to_owned();
+    let poster = poster_path(video);
+    debug!(?image, ?poster, "Writing poster");
+
+    tokio::task::spawn_blocking(move || {
+        let (width, height) = POSTER_SIZE;
+        image::open(image)?.thumbnail(width, height).save(poster)
+    })
+    .await??;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_poster_and_sidecar() {
+        let out = std::env::temp_dir().join(format!("timelapsifier-meta-{}", std::process::id()));
+        fs::create_dir_all(&out).await.unwrap();
+
+        let image = out.join("2022-07-12_12-00-00.jpg");
+        image::RgbImage::new(1280, 720).save(&image).unwrap();
+
+        let images = ["2022-07-12_22-44-11.jpg", "2022-07-12_22-54-19.jpg"]
+            .map(|name| TimestampedFile::new_ymd_hms(out.join(name)).unwrap());
+        let video = out.join("2022-07-12.mp4");
+
+        let profile = EncodingProfile::with_fps(40);
+        let meta = VideoMeta::new(&images, &profile, 40).unwrap();
+        assert_eq!(meta.frame_count, 2);
+        assert_eq!(meta.duration(), Duration::from_millis(50));
+        assert!(meta.first_capture < meta.last_capture);
+        assert!(VideoMeta::new(&[], &profile, 40).is_none());
+
+        write_sidecar(&video, &meta).await.unwrap();
+        assert_eq!(read_sidecar(&video).await, Some(meta));
+
+        write_poster(&image, &video).await.unwrap();
+        assert_eq!(
+            image::image_dimensions(poster_path(&video)).unwrap(),
+            POSTER_SIZE
+        );
+
+        fs::remove_dir_all(&out).await.unwrap();
+    }
+}
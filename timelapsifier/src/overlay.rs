@@ -0,0 +1,483 @@
+//! Text drawn on top of the frames of a timelapse.
+//!
+//! Overlays are rendered from the timestamp of each image,
+//! so any image source gets them, and the style can be changed and videos remade.
+//!
+//! Captions for single days are read from a notes file, one day per line:
+//!
+//! ```text
+//! # Anything after a hash is ignored.
+//! 2022-07-12 Repotted into a bigger pot
+//! 2022-07-20 First harvest
+//! 2022-08-02 Basil \#2 sprouted
+//! ```
+//!
+//! A hash that is part of the caption is written `\#`.
+//!
+//! Days are counted and captions looked up in the time zone days start in,
+//! the same one the day timelapses are split by.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use image::{Rgba, RgbaImage};
+use imageproc::{
+    drawing::{draw_filled_rect_mut, draw_text_mut, text_size, Blend},
+    rect::Rect,
+};
+use rusttype::{Font, Scale};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{day_of, TimelapsifyError, TimestampedFile};
+
+#[derive(Debug, Error)]
+pub enum OverlayError {
+    #[error("IO: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Image: {0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("No file name: {0:?}")]
+    NoFileName(PathBuf),
+
+    #[error("Not a usable font: {0:?}")]
+    Font(PathBuf),
+
+    #[error("Notes line {line}: {reason}")]
+    Notes { line: usize, reason: String },
+}
+
+/// What to draw on top of the frames, and how it looks.
+///
+/// Start from [`OverlayOptions::new`], which draws nothing,
+/// and turn on the parts wanted.
+#[derive(Debug, Clone)]
+pub struct OverlayOptions {
+    /// A TrueType font used for all text.
+    pub font: PathBuf,
+
+    /// Height of the text in pixels.
+    pub font_size: f32,
+    pub color: Rgba<u8>,
+
+    /// A box behind the text, so it can be read on bright frames.
+    pub background: Option<Rgba<u8>>,
+
+    /// Distance from the edges of the frame, in pixels.
+    pub margin: u32,
+
+    /// Draw the capture time in the top left corner, formatted like `%Y-%m-%d %H:%M`.
+    pub timestamp_format: Option<String>,
+
+    /// Draw which day it is since this one, below the time.
+    /// The day of planting is day 1.
+    pub planted: Option<NaiveDate>,
+
+    /// Drawn in the bottom right corner.
+    pub watermark: Option<String>,
+
+    /// A notes file with captions for some days, drawn in the bottom left corner.
+    pub notes: Option<PathBuf>,
+}
+
+impl OverlayOptions {
+    /// White text on a black box, like the timestamps burnt in by the camera.
+    pub fn new<P: Into<PathBuf>>(font: P) -> Self {
+        Self {
+            font: font.into(),
+            font_size: 24.0,
+            color: Rgba([255, 255, 255, 255]),
+            background: Some(Rgba([0, 0, 0, 160])),
+            margin: 5,
+            timestamp_format: None,
+            planted: None,
+            watermark: None,
+            notes: None,
+        }
+    }
+
+    /// Whether any text is turned on, so the overlay is worth rendering.
+    pub fn draws_anything(&self) -> bool {
+        self.timestamp_format.is_some()
+            || self.planted.is_some()
+            || self.watermark.is_some()
+            || self.notes.is_some()
+    }
+}
+
+/// Overlay options along with the font and notes they refer to.
+pub struct Overlay {
+    options: OverlayOptions,
+    font: Font<'static>,
+    notes: BTreeMap<NaiveDate, String>,
+    time_zone: Option<chrono_tz::Tz>,
+}
+
+impl std::fmt::Debug for Overlay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Overlay")
+            .field("options", &self.options)
+            .field("notes", &self.notes)
+            .field("time_zone", &self.time_zone)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Parse a notes file, see the [module docs](self).
+pub fn parse_notes(notes: &str) -> Result<BTreeMap<NaiveDate, String>, OverlayError> {
+    let mut captions = BTreeMap::new();
+
+    for (index, line) in notes.lines().enumerate() {
+        let line = without_comment(line);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let error = |reason: &str| OverlayError::Notes {
+            line: index + 1,
+            reason: reason.to_string(),
+        };
+
+        let (date, caption) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| error("want a date followed by a caption"))?;
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| error("the date should look like 2022-07-12"))?;
+
+        captions.insert(date, caption.trim().to_string());
+    }
+
+    Ok(captions)
+}
+
+/// The part of a notes line before its comment, with escaped hashes unescaped.
+fn without_comment(line: &str) -> String {
+    let mut text = String::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.as_str().starts_with('#') => {
+                text.push('#');
+                chars.next();
+            }
+            '#' => break,
+            c => text.push(c),
+        }
+    }
+
+    text
+}
+
+impl Overlay {
+    /// Read the font and notes of the options.
+    /// Days are told apart in the given time zone, or the server's local one if none.
+    /// Blocking, so best done where encoding happens.
+    pub fn load(
+        options: &OverlayOptions,
+        time_zone: Option<chrono_tz::Tz>,
+    ) -> Result<Self, OverlayError> {
+        let font = Font::try_from_vec(std::fs::read(&options.font)?)
+            .ok_or_else(|| OverlayError::Font(options.font.clone()))?;
+
+        let notes = match &options.notes {
+            Some(notes) => parse_notes(&std::fs::read_to_string(notes)?)?,
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            options: options.clone(),
+            font,
+            notes,
+            time_zone,
+        })
+    }
+
+    /// The lines for the top left corner.
+    fn top_left(&self, timestamp: &DateTime<Local>) -> Vec<String> {
+        let mut lines = vec![];
+
+        if let Some(format) = &self.options.timestamp_format {
+            let time = match self.time_zone {
+                Some(time_zone) => timestamp.with_timezone(&time_zone).format(format),
+                None => timestamp.format(format),
+            };
+            lines.push(time.to_string());
+        }
+
+        if let Some(planted) = self.options.planted {
+            let days = (day_of(timestamp, self.time_zone) - planted).num_days();
+            if days >= 0 {
+                lines.push(format!("Day {}", days + 1));
+            }
+        }
+
+        lines
+    }
+
+    fn caption(&self, timestamp: &DateTime<Local>) -> Option<&str> {
+        self.notes
+            .get(&day_of(timestamp, self.time_zone))
+            .map(String::as_str)
+    }
+
+    /// Draw the overlay onto an image taken at the given time.
+    pub fn draw(&self, image: RgbaImage, timestamp: &DateTime<Local>) -> RgbaImage {
+        let (width, height) = image.dimensions();
+        let scale = Scale::uniform(self.options.font_size);
+        let margin = self.options.margin as i32;
+        let line_height = self.options.font_size.ceil() as i32 + margin;
+
+        // Blending lets the background box be see-through.
+        let mut canvas = Blend(image);
+
+        let mut text = |text: &str, x: Option<i32>, y: i32| {
+            let (text_width, _) = text_size(scale, &self.font, text);
+            // Right aligned if no x is given.
+            let x = x.unwrap_or(width as i32 - margin - text_width);
+
+            if let Some(background) = self.options.background {
+                let rect = Rect::at(x - margin, y - margin).of_size(
+                    (text_width + 2 * margin).max(1) as u32,
+                    (line_height + margin).max(1) as u32,
+                );
+                draw_filled_rect_mut(&mut canvas, rect, background);
+            }
+            draw_text_mut(
+                &mut canvas,
+                self.options.color,
+                x,
+                y,
+                scale,
+                &self.font,
+                text,
+            );
+        };
+
+        for (index, line) in self.top_left(timestamp).iter().enumerate() {
+            text(line, Some(margin), margin + index as i32 * line_height);
+        }
+
+        let bottom = height as i32 - line_height;
+        if let Some(caption) = self.caption(timestamp) {
+            text(caption, Some(margin), bottom);
+        }
+        if let Some(watermark) = &self.options.watermark {
+            text(watermark, None, bottom);
+        }
+
+        canvas.0
+    }
+
+    /// Draw the overlay onto an image, saving the result in the given folder under the same name,
+    /// as a JPEG.
+    pub fn render(
+        &self,
+        image: &TimestampedFile,
+        folder: &Path,
+    ) -> Result<TimestampedFile, OverlayError> {
+        let file_name = image
+            .file
+            .file_name()
+            .ok_or_else(|| OverlayError::NoFileName(image.file.clone()))?;
+        let output = folder.join(file_name).with_extension("jpg");
+        debug!(?output, "Rendering overlay");
+
+        let frame = self.draw(image::open(&image.file)?.into_rgba8(), &image.timestamp);
+        image::DynamicImage::ImageRgba8(frame)
+            .into_rgb8()
+            .save(&output)?;
+
+        Ok(TimestampedFile {
+            file: output,
+            timestamp: image.timestamp,
+        })
+    }
+}
+
+/// Render the overlay onto all images, without blocking the async runtime.
+/// The frames are saved in the given folder, which is made if needed.
+pub async fn render_frames(
+    options: OverlayOptions,
+    time_zone: Option<chrono_tz::Tz>,
+    images: Vec<TimestampedFile>,
+    folder: PathBuf,
+) -> Result<Vec<TimestampedFile>, TimelapsifyError> {
+    let frames = tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(&folder)?;
+        let overlay = Overlay::load(&options, time_zone)?;
+
+        images
+            .iter()
+            .map(|image| overlay.render(image, &folder))
+            .collect::<Result<Vec<_>, OverlayError>>()
+    })
+    .await??;
+
+    Ok(frames)
+}
+
+/// A font from the homepage, so tests need no system fonts.
+#[cfg(test)]
+pub(crate) const TEST_FONT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../html-strong-homepage/static/fonts/ComicMono.ttf"
+);
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// A wall clock time where the tests run.
+    fn local(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    ) -> DateTime<Local> {
+        let naive = NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, second))
+            .unwrap();
+        Local.from_local_datetime(&naive).unwrap()
+    }
+
+    /// A moment given in UTC, as a local time wherever the tests run.
+    fn utc(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    ) -> DateTime<Local> {
+        let naive = NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, second))
+            .unwrap();
+        chrono::Utc.from_utc_datetime(&naive).with_timezone(&Local)
+    }
+
+    #[test]
+    fn test_parse_notes() {
+        let notes = parse_notes(
+            "# Basil\n\
+             2022-07-12 Repotted into a bigger pot\n\
+             \n\
+             2022-07-20   First harvest # finally\n\
+             2022-08-02 Basil \\#2 sprouted \\# # with a \\ too\n",
+        )
+        .unwrap();
+
+        assert_eq!(notes.len(), 3);
+        assert_eq!(
+            notes[&NaiveDate::from_ymd_opt(2022, 7, 20).unwrap()],
+            "First harvest".to_string()
+        );
+        assert_eq!(
+            notes[&NaiveDate::from_ymd_opt(2022, 8, 2).unwrap()],
+            "Basil #2 sprouted #".to_string()
+        );
+        assert_eq!(without_comment("a \\b # c"), "a \\b ".to_string());
+
+        assert!(matches!(
+            parse_notes("2022-07-12 ok\n12.07.2022 Not ok"),
+            Err(OverlayError::Notes { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse_notes("2022-07-12"),
+            Err(OverlayError::Notes { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_draw() {
+        let overlay = Overlay {
+            options: OverlayOptions {
+                timestamp_format: Some("%Y-%m-%d %H:%M".to_string()),
+                planted: Some(NaiveDate::from_ymd_opt(2022, 7, 1).unwrap()),
+                watermark: Some("example.com".to_string()),
+                ..OverlayOptions::new(TEST_FONT)
+            },
+            font: Font::try_from_vec(std::fs::read(TEST_FONT).unwrap()).unwrap(),
+            notes: parse_notes("2022-07-12 Repotted").unwrap(),
+            time_zone: None,
+        };
+
+        let timestamp = local(2022, 7, 12, 22, 44, 11);
+        assert_eq!(
+            overlay.top_left(&timestamp),
+            vec!["2022-07-12 22:44".to_string(), "Day 12".to_string()]
+        );
+        assert_eq!(overlay.caption(&timestamp), Some("Repotted"));
+
+        // Before planting there is no day to count.
+        let before = local(2022, 6, 30, 12, 0, 0);
+        assert_eq!(
+            overlay.top_left(&before),
+            vec!["2022-06-30 12:00".to_string()]
+        );
+        assert_eq!(overlay.caption(&before), None);
+
+        let gray = Rgba([128, 128, 128, 255]);
+        let frame = overlay.draw(RgbaImage::from_pixel(640, 360, gray), &timestamp);
+
+        let changed = |x: std::ops::Range<u32>, y: std::ops::Range<u32>| {
+            x.flat_map(|x| y.clone().map(move |y| (x, y)))
+                .any(|(x, y)| *frame.get_pixel(x, y) != gray)
+        };
+
+        // Time and day top left, caption bottom left, watermark bottom right.
+        assert!(changed(0..200, 0..60));
+        assert!(changed(0..200, 300..360));
+        assert!(changed(440..640, 300..360));
+        // The middle is left alone.
+        assert!(!changed(200..440, 100..260));
+    }
+
+    #[test]
+    fn test_days_in_time_zone() {
+        let overlay = Overlay {
+            options: OverlayOptions {
+                timestamp_format: Some("%Y-%m-%d %H:%M".to_string()),
+                planted: Some(NaiveDate::from_ymd_opt(2022, 3, 1).unwrap()),
+                ..OverlayOptions::new(TEST_FONT)
+            },
+            font: Font::try_from_vec(std::fs::read(TEST_FONT).unwrap()).unwrap(),
+            notes: parse_notes("2022-03-27 Clocks forward\n2022-07-12 Repotted").unwrap(),
+            time_zone: Some(chrono_tz::Europe::Oslo),
+        };
+
+        // Half an hour into the day in Oslo, whatever the day is where the tests run.
+        let summer = utc(2022, 7, 11, 22, 30, 0);
+        assert_eq!(
+            overlay.top_left(&summer),
+            vec!["2022-07-12 00:30".to_string(), "Day 134".to_string()]
+        );
+        assert_eq!(overlay.caption(&summer), Some("Repotted"));
+
+        // The night the clocks go forward, an hour before they do.
+        let switch = utc(2022, 3, 26, 23, 30, 0);
+        assert_eq!(
+            overlay.top_left(&switch),
+            vec!["2022-03-27 00:30".to_string(), "Day 27".to_string()]
+        );
+        assert_eq!(overlay.caption(&switch), Some("Clocks forward"));
+
+        // And just before midnight the day before.
+        let before = utc(2022, 3, 26, 22, 59, 59);
+        assert_eq!(
+            overlay.top_left(&before),
+            vec!["2022-03-26 23:59".to_string(), "Day 26".to_string()]
+        );
+        assert_eq!(overlay.caption(&before), None);
+    }
+}
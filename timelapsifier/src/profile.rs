@@ -0,0 +1,201 @@
+//! How timelapse videos are encoded.
+//!
+//! Profiles can be read from a JSON file, where anything left out gets its default:
+//!
+//! ```json
+//! {
+//!     "day": { "frame_rate": { "target_duration": { "seconds": 10.0 } }, "also_webm": true },
+//!     "month": { "codec": "av1", "crf": 35, "width": 1280 }
+//! }
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::TimelapsifyError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    H264,
+    Vp9,
+    Av1,
+}
+
+impl Codec {
+    /// The container videos of this codec are put in.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Codec::H264 | Codec::Av1 => "mp4",
+            Codec::Vp9 => "webm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameRate {
+    /// Always this many frames per second.
+    Fps(u32),
+
+    /// Pick the frame rate which makes the video last about this long.
+    /// Days with few captures then still become watchable videos.
+    TargetDuration {
+        seconds: f32,
+        #[serde(default = "FrameRate::default_min_fps")]
+        min_fps: u32,
+        #[serde(default = "FrameRate::default_max_fps")]
+        max_fps: u32,
+    },
+}
+
+impl FrameRate {
+    fn default_min_fps() -> u32 {
+        1
+    }
+
+    fn default_max_fps() -> u32 {
+        60
+    }
+
+    /// The frames per second to use for a video of this many frames.
+    pub fn fps_for(&self, frames: usize) -> u32 {
+        match *self {
+            FrameRate::Fps(fps) => fps.max(1),
+            FrameRate::TargetDuration {
+                seconds,
+                min_fps,
+                max_fps,
+            } => {
+                let fps = (frames as f32 / seconds.max(f32::EPSILON)).round() as u32;
+                fps.clamp(min_fps.max(1), max_fps.max(min_fps).max(1))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncodingProfile {
+    pub frame_rate: FrameRate,
+    pub codec: Codec,
+
+    /// Constant rate factor. Lower is better quality and bigger files.
+    pub crf: u8,
+
+    /// Scale the video down to this width, keeping the aspect ratio.
+    /// Full size if not set.
+    pub width: Option<u32>,
+
+    /// Also make a vp9 webm next to the video, for browsers which prefer it.
+    pub also_webm: bool,
+}
+
+impl Default for EncodingProfile {
+    fn default() -> Self {
+        Self {
+            frame_rate: FrameRate::Fps(60),
+            codec: Codec::H264,
+            crf: 25,
+            width: None,
+            also_webm: false,
+        }
+    }
+}
+
+impl EncodingProfile {
+    pub fn with_fps(fps: u32) -> Self {
+        Self {
+            frame_rate: FrameRate::Fps(fps),
+            ..Default::default()
+        }
+    }
+
+    /// The same profile, encoding a vp9 webm instead.
+    /// `None` if no extra webm is wanted, or if this already is one.
+    pub fn webm(&self) -> Option<Self> {
+        (self.also_webm && self.codec != Codec::Vp9).then(|| Self {
+            codec: Codec::Vp9,
+            also_webm: false,
+            ..self.clone()
+        })
+    }
+}
+
+/// The encoding profile for each kind of timelapse.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+    pub day: EncodingProfile,
+    pub week: EncodingProfile,
+    pub month: EncodingProfile,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self {
+            day: EncodingProfile::with_fps(60),
+            week: EncodingProfile::with_fps(30),
+            month: EncodingProfile::with_fps(30),
+        }
+    }
+}
+
+impl Profiles {
+    /// Read profiles from a JSON file.
+    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self, TimelapsifyError> {
+        let bytes = tokio::fs::read(path).await?;
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_rate() {
+        assert_eq!(FrameRate::Fps(60).fps_for(10), 60);
+
+        let target = FrameRate::TargetDuration {
+            seconds: 10.0,
+            min_fps: 2,
+            max_fps: 30,
+        };
+        assert_eq!(target.fps_for(150), 15);
+        assert_eq!(target.fps_for(5), 2);
+        assert_eq!(target.fps_for(10_000), 30);
+    }
+
+    #[test]
+    fn test_profiles_from_json() {
+        let profiles: Profiles = serde_json::from_str(
+            r#"{
+                "day": { "frame_rate": { "target_duration": { "seconds": 10.0 } }, "also_webm": true },
+                "month": { "codec": "av1", "crf": 35, "width": 1280 }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            profiles.day.frame_rate,
+            FrameRate::TargetDuration {
+                seconds: 10.0,
+                min_fps: 1,
+                max_fps: 60
+            }
+        );
+        assert_eq!(profiles.day.webm().unwrap().codec, Codec::Vp9);
+
+        // Left out entirely, so the default
+        assert_eq!(profiles.week, EncodingProfile::with_fps(30));
+
+        assert_eq!(profiles.month.codec, Codec::Av1);
+        assert_eq!(profiles.month.crf, 35);
+        assert_eq!(profiles.month.width, Some(1280));
+        assert_eq!(profiles.month.frame_rate, FrameRate::Fps(60));
+        assert_eq!(profiles.month.webm(), None);
+    }
+}
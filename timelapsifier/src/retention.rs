@@ -0,0 +1,498 @@
+//! Removing old images and videos, so folders don't grow forever.
+//!
+//! Each folder has a policy of its own. Files with the same name but different
+//! extensions are handled together, so a video goes along with its poster and metadata.
+//! They are dated by that name, and files without a date in their name are left alone.
+//!
+//! A policy is applied in order:
+//!
+//! 1. Files older than `keep_days` are removed, or thinned to the first of every hour.
+//! 2. If the folder still takes up more than `max_bytes`, the oldest files are removed
+//!    until it doesn't.
+//!
+//! Removed files can be put into a tarball first.
+//! On a dry run nothing is touched, the report just says what would have been removed.
+//!
+//! Compilations are made from the processed images if their video is missing,
+//! or if images were added since, see [`crate::jobs::CompilationSources`].
+//! Thinning or removing images does not make them again,
+//! but keep videos at least as long as the images they were made from,
+//! or a missing one is made from what is left.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Local, NaiveDate, Timelike};
+use flate2::{write::GzEncoder, Compression};
+use tracing::{error, info};
+
+use crate::{TimelapsifyError, TimestampedFile};
+
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub folder: PathBuf,
+
+    /// Everything from the last this many days is kept. Nothing expires if not set.
+    pub keep_days: Option<u32>,
+
+    /// Keep the first file of every hour once expired, instead of removing all of them.
+    pub thin_to_hourly: bool,
+
+    /// Remove the oldest files until the folder takes up at most this many bytes.
+    pub max_bytes: Option<u64>,
+
+    /// Put removed files into a gzipped tarball in this folder before removing them.
+    pub archive_folder: Option<PathBuf>,
+}
+
+impl RetentionPolicy {
+    /// Keeps everything, turn on the parts wanted.
+    pub fn new<P: Into<PathBuf>>(folder: P) -> Self {
+        Self {
+            folder: folder.into(),
+            keep_days: None,
+            thin_to_hourly: false,
+            max_bytes: None,
+            archive_folder: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RetentionOptions {
+    pub policies: Vec<RetentionPolicy>,
+
+    /// Only report what would be removed.
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Expired,
+    Thinned,
+    OverDiskCap,
+}
+
+/// Files sharing a name, such as a video with its poster.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub timestamp: DateTime<Local>,
+    pub files: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Removal {
+    pub entry: Entry,
+    pub reason: Reason,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionReport {
+    pub folder: PathBuf,
+    pub removed: Vec<Removal>,
+
+    /// The tarball the removed files were put in.
+    pub archive: Option<PathBuf>,
+    pub dry_run: bool,
+}
+
+impl RetentionReport {
+    /// How much space was freed, or would be on a dry run.
+    pub fn bytes(&self) -> u64 {
+        self.removed.iter().map(|removal| removal.entry.bytes).sum()
+    }
+}
+
+fn timestamp_of(file: &Path) -> Option<DateTime<Local>> {
+    let stem = PathBuf::from(file.file_stem()?);
+
+    TimestampedFile::new_ymd_hms(stem.clone())
+        .or_else(|_| TimestampedFile::new_ymd(stem))
+        .ok()
+        .map(|file| file.timestamp)
+}
+
+/// The dated entries of a folder, oldest first.
+/// Folders and hidden files, such as work in progress, are skipped.
+fn entries(folder: &Path) -> Result<Vec<Entry>, TimelapsifyError> {
+    let mut entries: BTreeMap<(DateTime<Local>, PathBuf), Entry> = BTreeMap::new();
+
+    for dir_entry in std::fs::read_dir(folder)? {
+        let dir_entry = dir_entry?;
+        let path = dir_entry.path();
+        let metadata = dir_entry.metadata()?;
+
+        if !metadata.is_file() || dir_entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let timestamp = match timestamp_of(&path) {
+            Some(timestamp) => timestamp,
+            None => continue,
+        };
+
+        let entry = entries
+            .entry((timestamp, path.with_extension("")))
+            .or_insert_with(|| Entry {
+                timestamp,
+                files: vec![],
+                bytes: 0,
+            });
+        entry.files.push(path);
+        entry.bytes += metadata.len();
+    }
+
+    Ok(entries
+        .into_values()
+        .map(|mut entry| {
+            entry.files.sort();
+            entry
+        })
+        .collect())
+}
+
+/// What the policy removes of the entries, which are sorted oldest first.
+fn plan(policy: &RetentionPolicy, entries: Vec<Entry>, now: DateTime<Local>) -> Vec<Removal> {
+    let mut removed = vec![];
+    let mut kept = vec![];
+    let mut hours_seen: HashSet<(NaiveDate, u32)> = HashSet::new();
+
+    let cutoff = policy
+        .keep_days
+        .map(|days| now - Duration::days(days as i64));
+
+    for entry in entries {
+        match cutoff {
+            Some(cutoff) if entry.timestamp < cutoff => {
+                let hour = (entry.timestamp.naive_local().date(), entry.timestamp.hour());
+
+                if !policy.thin_to_hourly {
+                    removed.push(Removal {
+                        entry,
+                        reason: Reason::Expired,
+                    });
+                } else if hours_seen.insert(hour) {
+                    kept.push(entry);
+                } else {
+                    removed.push(Removal {
+                        entry,
+                        reason: Reason::Thinned,
+                    });
+                }
+            }
+            _ => kept.push(entry),
+        }
+    }
+
+    if let Some(max_bytes) = policy.max_bytes {
+        let mut total: u64 = kept.iter().map(|entry| entry.bytes).sum();
+
+        for entry in kept {
+            if total <= max_bytes {
+                break;
+            }
+            total -= entry.bytes;
+            removed.push(Removal {
+                entry,
+                reason: Reason::OverDiskCap,
+            });
+        }
+    }
+
+    removed.sort_by_key(|removal| removal.entry.timestamp);
+    removed
+}
+
+/// Put the files into a new tarball in the archive folder, named after the folder and the time.
+fn archive(
+    folder: &Path,
+    removed: &[Removal],
+    archive_folder: &Path,
+    now: DateTime<Local>,
+) -> Result<PathBuf, TimelapsifyError> {
+    std::fs::create_dir_all(archive_folder)?;
+
+    let folder_name = folder
+        .file_name()
+        .ok_or_else(|| TimelapsifyError::file_option_issue(folder))?
+        .to_string_lossy();
+    let name = format!("{folder_name}-{}.tar.gz", now.format("%Y-%m-%d_%H-%M-%S"));
+    let path = archive_folder.join(&name);
+
+    // Only in place once complete, so a crash does not leave half an archive
+    // while the files are still around.
+    let temporary = archive_folder.join(format!(".{name}.tmp"));
+    let mut tarball = tar::Builder::new(GzEncoder::new(
+        File::create(&temporary)?,
+        Compression::default(),
+    ));
+
+    for file in removed.iter().flat_map(|removal| &removal.entry.files) {
+        let file_name = file
+            .file_name()
+            .ok_or_else(|| TimelapsifyError::file_option_issue(file))?;
+        tarball.append_path_with_name(file, file_name)?;
+    }
+    tarball.into_inner()?.finish()?.sync_all()?;
+    std::fs::rename(&temporary, &path)?;
+
+    Ok(path)
+}
+
+/// Apply a policy to its folder. Blocking, since archiving compresses.
+pub fn enforce_policy(
+    policy: &RetentionPolicy,
+    dry_run: bool,
+    now: DateTime<Local>,
+) -> Result<RetentionReport, TimelapsifyError> {
+    let mut report = RetentionReport {
+        folder: policy.folder.clone(),
+        removed: vec![],
+        archive: None,
+        dry_run,
+    };
+
+    if !policy.folder.exists() {
+        return Ok(report);
+    }
+
+    report.removed = plan(policy, entries(&policy.folder)?, now);
+    if dry_run || report.removed.is_empty() {
+        return Ok(report);
+    }
+
+    if let Some(archive_folder) = &policy.archive_folder {
+        report.archive = Some(archive(
+            &policy.folder,
+            &report.removed,
+            archive_folder,
+            now,
+        )?);
+    }
+
+    for file in report
+        .removed
+        .iter()
+        .flat_map(|removal| &removal.entry.files)
+    {
+        std::fs::remove_file(file)?;
+    }
+
+    Ok(report)
+}
+
+/// Apply all policies without blocking the async runtime.
+/// A policy which fails is logged, and does not stop the others.
+pub async fn enforce(options: RetentionOptions, now: DateTime<Local>) -> Vec<RetentionReport> {
+    let dry_run = options.dry_run;
+
+    let results = tokio::task::spawn_blocking(move || {
+        options
+            .policies
+            .iter()
+            .map(|policy| (policy.folder.clone(), enforce_policy(policy, dry_run, now)))
+            .collect::<Vec<_>>()
+    })
+    .await;
+
+    let results = match results {
+        Ok(results) => results,
+        Err(error) => {
+            error!(?error, "Retention task failed");
+            return vec![];
+        }
+    };
+
+    let mut reports = vec![];
+    for (folder, result) in results {
+        match result {
+            Ok(report) => {
+                if report.dry_run {
+                    for removal in &report.removed {
+                        info!(files = ?removal.entry.files, reason = ?removal.reason, "Would remove");
+                    }
+                }
+                info!(
+                    ?folder,
+                    dry_run,
+                    archive = ?report.archive,
+                    "Retention removed {} entries, {} bytes",
+                    report.removed.len(),
+                    report.bytes()
+                );
+                reports.push(report);
+            }
+            Err(error) => error!(?error, ?folder, "Could not apply retention policy"),
+        }
+    }
+
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn entry(timestamp: DateTime<Local>, bytes: u64) -> Entry {
+        Entry {
+            timestamp,
+            files: vec![PathBuf::from(format!(
+                "{}.jpg",
+                timestamp.format("%Y-%m-%d_%H-%M-%S")
+            ))],
+            bytes,
+        }
+    }
+
+    fn reasons(removed: &[Removal]) -> Vec<(String, Reason)> {
+        removed
+            .iter()
+            .map(|removal| {
+                (
+                    removal.entry.timestamp.format("%d %H:%M").to_string(),
+                    removal.reason,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan() {
+        let now = Local.ymd(2022, 7, 20).and_hms(1, 0, 0);
+        let at = |day, hour, minute| Local.ymd(2022, 7, day).and_hms(hour, minute, 0);
+        let entries = vec![
+            entry(at(10, 10, 0), 100),
+            entry(at(10, 10, 5), 100),
+            entry(at(10, 11, 0), 100),
+            entry(at(18, 10, 0), 100),
+            entry(at(19, 10, 0), 100),
+        ];
+
+        // Nothing set keeps everything
+        let policy = RetentionPolicy::new("processed");
+        assert_eq!(plan(&policy, entries.clone(), now), vec![]);
+
+        let policy = RetentionPolicy {
+            keep_days: Some(7),
+            ..policy
+        };
+        assert_eq!(
+            reasons(&plan(&policy, entries.clone(), now)),
+            vec![
+                ("10 10:00".to_string(), Reason::Expired),
+                ("10 10:05".to_string(), Reason::Expired),
+                ("10 11:00".to_string(), Reason::Expired),
+            ]
+        );
+
+        let policy = RetentionPolicy {
+            thin_to_hourly: true,
+            ..policy
+        };
+        assert_eq!(
+            reasons(&plan(&policy, entries.clone(), now)),
+            vec![("10 10:05".to_string(), Reason::Thinned)]
+        );
+
+        // Four are kept, one too many for the cap
+        let policy = RetentionPolicy {
+            max_bytes: Some(300),
+            ..policy
+        };
+        assert_eq!(
+            reasons(&plan(&policy, entries, now)),
+            vec![
+                ("10 10:00".to_string(), Reason::OverDiskCap),
+                ("10 10:05".to_string(), Reason::Thinned),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_enforce_policy() {
+        let root =
+            std::env::temp_dir().join(format!("timelapsifier-retention-{}", std::process::id()));
+        let folder = root.join("days");
+        std::fs::create_dir_all(folder.join(".work-2022-07-19.mp4")).unwrap();
+
+        for (name, contents) in [
+            ("2022-07-10.mp4", "old video"),
+            ("2022-07-10.jpg", "old poster"),
+            ("2022-07-19.mp4", "new video"),
+            ("2022-07-19.jpg", "new poster"),
+            ("notes.txt", "not dated"),
+        ] {
+            std::fs::write(folder.join(name), contents).unwrap();
+        }
+
+        let policy = RetentionPolicy {
+            keep_days: Some(7),
+            archive_folder: Some(root.join("archive")),
+            ..RetentionPolicy::new(&folder)
+        };
+        let now = Local.ymd(2022, 7, 20).and_hms(1, 0, 0);
+
+        // A dry run reports the video and its poster, but leaves them be.
+        let report = enforce_policy(&policy, true, now).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(
+            report.removed[0].entry.files,
+            vec![folder.join("2022-07-10.jpg"), folder.join("2022-07-10.mp4")]
+        );
+        assert_eq!(report.bytes(), 19);
+        assert_eq!(report.archive, None);
+        assert!(folder.join("2022-07-10.mp4").exists());
+        assert!(!root.join("archive").exists());
+
+        let report = enforce_policy(&policy, false, now).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert!(!folder.join("2022-07-10.mp4").exists());
+        assert!(!folder.join("2022-07-10.jpg").exists());
+        assert!(folder.join("2022-07-19.mp4").exists());
+        assert!(folder.join("notes.txt").exists());
+        assert!(folder.join(".work-2022-07-19.mp4").exists());
+
+        let archive = report.archive.unwrap();
+        assert_eq!(
+            archive,
+            root.join("archive/days-2022-07-20_01-00-00.tar.gz")
+        );
+        let mut tarball =
+            tar::Archive::new(flate2::read::GzDecoder::new(File::open(&archive).unwrap()));
+        let mut archived = tarball
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                (
+                    entry.path().unwrap().to_string_lossy().to_string(),
+                    contents,
+                )
+            })
+            .collect::<Vec<_>>();
+        archived.sort();
+        assert_eq!(
+            archived,
+            vec![
+                ("2022-07-10.jpg".to_string(), "old poster".to_string()),
+                ("2022-07-10.mp4".to_string(), "old video".to_string()),
+            ]
+        );
+
+        // Nothing left to do
+        let report = enforce_policy(&policy, false, now).unwrap();
+        assert_eq!(report.removed, vec![]);
+        assert_eq!(report.archive, None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,345 @@
+//! When the worker runs.
+//!
+//! A schedule is either a time of day, optionally in a given time zone:
+//!
+//! ```text
+//! 01:00
+//! 01:00 Europe/Oslo
+//! ```
+//!
+//! or a cron expression, with seconds first:
+//!
+//! ```text
+//! 0 30 1,13 * * *
+//! ```
+//!
+//! The worker can also be made to run right away with a [`Trigger`].
+
+use std::{
+    fmt::Debug,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use thiserror::Error;
+use tokio::sync::Notify;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ScheduleError {
+    #[error("Not a time of day like 01:00: {0}")]
+    Time(String),
+
+    #[error("Unknown time zone: {0}")]
+    TimeZone(String),
+
+    #[error("Not a valid cron expression: {0}")]
+    Cron(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Every day at this time, in the given time zone or the local one.
+    Daily {
+        time: NaiveTime,
+        time_zone: Option<Tz>,
+    },
+    Cron(Box<cron::Schedule>),
+}
+
+impl Default for Schedule {
+    /// In the night, when the day before is over.
+    fn default() -> Self {
+        Self::Daily {
+            time: NaiveTime::from_hms_opt(1, 0, 0).expect("1 am is a time"),
+            time_zone: None,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(time), time_zone, None) if time.contains(':') => {
+                let time = NaiveTime::parse_from_str(time, "%H:%M")
+                    .map_err(|_| ScheduleError::Time(time.to_string()))?;
+                let time_zone = time_zone
+                    .map(|tz| {
+                        tz.parse::<Tz>()
+                            .map_err(|_| ScheduleError::TimeZone(tz.to_string()))
+                    })
+                    .transpose()?;
+
+                Ok(Self::Daily { time, time_zone })
+            }
+            _ => cron::Schedule::from_str(s)
+                .map(|schedule| Self::Cron(Box::new(schedule)))
+                .map_err(|error| ScheduleError::Cron(error.to_string())),
+        }
+    }
+}
+
+/// The first time after `after` that the time of day is `time` in the time zone.
+fn next_daily<Z: TimeZone>(
+    time_zone: &Z,
+    time: NaiveTime,
+    after: DateTime<Local>,
+) -> Option<DateTime<Local>> {
+    let today = after.with_timezone(time_zone).naive_local().date();
+
+    // A day or two ahead, in case the time is skipped by a DST change.
+    (0..3).find_map(|days| {
+        let local = (today + Duration::days(days)).and_time(time);
+
+        // The earliest of the two if the clocks go back, an hour later if they skip it.
+        let next = time_zone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                time_zone
+                    .from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })?
+            .with_timezone(&Local);
+
+        (next > after).then_some(next)
+    })
+}
+
+impl Schedule {
+    /// The next time to run, strictly after the given time.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Schedule::Daily {
+                time,
+                time_zone: Some(time_zone),
+            } => next_daily(time_zone, *time, after),
+            Schedule::Daily {
+                time,
+                time_zone: None,
+            } => next_daily(&Local, *time, after),
+            Schedule::Cron(schedule) => schedule.after(&after).next(),
+        }
+    }
+}
+
+/// How long to sleep before the next run, given the time now and when a failed job should be retried.
+///
+/// Retries which are overdue still wait a minute, so a job which can't be retried
+/// does not keep the worker busy.
+pub fn sleep_duration(
+    schedule: &Schedule,
+    now: DateTime<Local>,
+    next_retry: Option<DateTime<Local>>,
+) -> std::time::Duration {
+    let scheduled = schedule
+        .next_after(now)
+        .unwrap_or_else(|| now + Duration::days(1));
+
+    let until = |time: DateTime<Local>| (time - now).to_std().unwrap_or_default();
+
+    match next_retry {
+        Some(retry) => until(scheduled).min(until(retry).max(std::time::Duration::from_secs(60))),
+        None => until(scheduled),
+    }
+}
+
+/// Tells the time. The worker asks this instead of the system, so tests can pick the time.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// A clock which only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock(Mutex<DateTime<Local>>);
+
+impl ManualClock {
+    pub fn new(now: DateTime<Local>) -> Self {
+        Self(Mutex::new(now))
+    }
+
+    pub fn set(&self, now: DateTime<Local>) {
+        *self.0.lock().expect("clock lock ok") = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.0.lock().expect("clock lock ok");
+        *now += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.0.lock().expect("clock lock ok")
+    }
+}
+
+/// Makes the worker run right away, instead of waiting for the schedule.
+///
+/// Clones trigger the same worker. Triggering while the worker is busy
+/// makes it run again once it is done.
+#[derive(Debug, Clone, Default)]
+pub struct Trigger(Arc<Notify>);
+
+impl Trigger {
+    pub fn run_now(&self) {
+        self.0.notify_one();
+    }
+
+    pub(crate) async fn triggered(&self) {
+        self.0.notified().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oslo(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Local> {
+        chrono_tz::Europe::Oslo
+            .ymd(y, m, d)
+            .and_hms(h, min, 0)
+            .with_timezone(&Local)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(
+            "01:00".parse::<Schedule>(),
+            Ok(Schedule::Daily {
+                time_zone: None,
+                ..
+            })
+        ));
+        assert!(matches!(
+            "01:00 Europe/Oslo".parse::<Schedule>(),
+            Ok(Schedule::Daily {
+                time_zone: Some(chrono_tz::Europe::Oslo),
+                ..
+            })
+        ));
+        assert!(matches!(
+            "0 30 1 * * *".parse::<Schedule>(),
+            Ok(Schedule::Cron(_))
+        ));
+
+        assert_eq!(
+            "25:00".parse::<Schedule>().unwrap_err(),
+            ScheduleError::Time("25:00".to_string())
+        );
+        assert_eq!(
+            "01:00 Mars/Olympus".parse::<Schedule>().unwrap_err(),
+            ScheduleError::TimeZone("Mars/Olympus".to_string())
+        );
+        assert!(matches!(
+            "every night".parse::<Schedule>(),
+            Err(ScheduleError::Cron(_))
+        ));
+    }
+
+    #[test]
+    fn test_daily() {
+        let schedule: Schedule = "01:00 Europe/Oslo".parse().unwrap();
+
+        // Later the same night, or the next one once it has passed.
+        assert_eq!(
+            schedule.next_after(oslo(2022, 7, 13, 0, 30)),
+            Some(oslo(2022, 7, 13, 1, 0))
+        );
+        assert_eq!(
+            schedule.next_after(oslo(2022, 7, 13, 1, 0)),
+            Some(oslo(2022, 7, 14, 1, 0))
+        );
+    }
+
+    #[test]
+    fn test_daily_dst() {
+        // Clocks go from 02:00 to 03:00 on the last sunday of March,
+        // and from 03:00 back to 02:00 on the last sunday of October.
+        let schedule: Schedule = "02:30 Europe/Oslo".parse().unwrap();
+
+        // 02:30 does not exist, so an hour later.
+        let skipped = schedule.next_after(oslo(2022, 3, 27, 0, 0)).unwrap();
+        assert_eq!(skipped, oslo(2022, 3, 27, 3, 30));
+
+        // 02:30 happens twice, the first one is used, once.
+        let repeated = schedule.next_after(oslo(2022, 10, 30, 0, 0)).unwrap();
+        assert_eq!(
+            repeated
+                .with_timezone(&chrono_tz::Europe::Oslo)
+                .to_rfc3339(),
+            "2022-10-30T02:30:00+02:00"
+        );
+        assert_eq!(
+            schedule.next_after(repeated),
+            Some(oslo(2022, 10, 31, 2, 30))
+        );
+    }
+
+    #[test]
+    fn test_cron() {
+        let schedule: Schedule = "0 30 1,13 * * *".parse().unwrap();
+        let now = Local.ymd(2022, 7, 13).and_hms(2, 0, 0);
+
+        assert_eq!(
+            schedule.next_after(now),
+            Some(Local.ymd(2022, 7, 13).and_hms(13, 30, 0))
+        );
+    }
+
+    #[test]
+    fn test_sleep_duration() {
+        let schedule: Schedule = "01:00".parse().unwrap();
+        let now = Local.ymd(2022, 7, 13).and_hms(2, 0, 0);
+        let hours = |hours: u64| std::time::Duration::from_secs(hours * 60 * 60);
+
+        assert_eq!(sleep_duration(&schedule, now, None), hours(23));
+
+        let retry = now + Duration::hours(2);
+        assert_eq!(sleep_duration(&schedule, now, Some(retry)), hours(2));
+
+        let overdue = now - Duration::hours(2);
+        assert_eq!(
+            sleep_duration(&schedule, now, Some(overdue)),
+            std::time::Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let now = Local.ymd(2022, 7, 13).and_hms(2, 0, 0);
+        let clock = ManualClock::new(now);
+        assert_eq!(clock.now(), now);
+
+        clock.advance(Duration::hours(1));
+        assert_eq!(clock.now(), now + Duration::hours(1));
+
+        clock.set(now);
+        assert_eq!(clock.now(), now);
+    }
+
+    #[tokio::test]
+    async fn test_trigger() {
+        let trigger = Trigger::default();
+
+        // Triggering before anyone waits is not lost.
+        trigger.clone().run_now();
+        tokio::time::timeout(std::time::Duration::from_secs(1), trigger.triggered())
+            .await
+            .unwrap();
+    }
+}